@@ -0,0 +1,33 @@
+//! Feeds arbitrary bytes through everything that parses a VMPAK: header, metadata, index,
+//! chunk tables, signatures and entry payloads. Run with `cargo fuzz run vmpak_reader` from `src-tauri`.
+//! Any panic, or an allocation past libFuzzer's `-rss_limit_mb`, is a bug
+
+#![no_main]
+
+use std::io::{self, Cursor};
+
+use libfuzzer_sys::fuzz_target;
+use void_mod_manager_lib::binary::{read_signatures, verify, VmpakHeader, VmpakKey, VmpakReader, VmpakUnlock};
+
+fuzz_target!(|data: &[u8]| {
+    let _ = VmpakHeader::read_unchecked(&mut &data[..]);
+    if let Ok(header) = VmpakHeader::read(&mut &data[..]) {
+        let _ = read_signatures(&mut Cursor::new(data), &header);
+    }
+
+    // Encrypted packs only get past the key check with this exact key, it's there so the corpus can carry some
+    let unlock = VmpakUnlock::Key(VmpakKey::from_bytes([0x42; 32]));
+    for unlock in [None, Some(&unlock)] {
+        let Ok(mut reader) = VmpakReader::new(Cursor::new(data), unlock) else {
+            continue;
+        };
+        let paths: Vec<_> = reader.entries().iter().map(|entry| entry.path.clone()).collect();
+        for path in paths {
+            if let Ok(mut entry) = reader.open_entry(&path) {
+                let _ = io::copy(&mut entry, &mut io::sink());
+            }
+        }
+    }
+
+    let _ = verify(&mut Cursor::new(data), None);
+});
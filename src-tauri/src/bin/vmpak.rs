@@ -0,0 +1,713 @@
+//! Command line tool for producing and inspecting VMPAK files outside the app.
+//!
+//! Exit codes: 0 on success, 1 on usage or I/O errors, 2 when `unpack`, `ls`, `inspect` or `verify` find a pack corrupt
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufWriter},
+    path::{Component, Path, PathBuf},
+    process::ExitCode,
+};
+
+use binary::{
+    apply_patch, apply_patch_in_place, convert_archive, create_patch, dedup_stats, read_signatures, remove_volumes, sign_pack, verify,
+    Compression, InstallContext, InstallRoot, PatchOptions, VmpakDedup, VmpakEncryption, VmpakEncryptionHeader, VmpakFlags, VmpakHeader,
+    VmpakKey, VmpakMetadata, VmpakReader, VmpakUnlock, VmpakWriter, VolumeReader, VolumeWriter,
+};
+use clap::{Args, Parser, Subcommand};
+use ed25519_dalek::SigningKey;
+use serde_json::json;
+use void_mod_manager_lib::binary;
+
+/// Metadata file picked up from the root of the directory being packed
+const METADATA_FILE: &str = "vmpak.json";
+
+#[derive(Parser)]
+#[command(name = "vmpak", about = "Create, inspect and verify VMPAK mod packages")]
+struct Cli {
+    /// Print machine-readable JSON instead of text
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Pack a directory into a VMPAK
+    Pack(PackArgs),
+    /// Extract every entry of a VMPAK into a directory
+    Unpack {
+        file: PathBuf,
+        dir: PathBuf,
+        #[command(flatten)]
+        unlock: UnlockArgs,
+    },
+    /// List the entries of a VMPAK
+    Ls {
+        file: PathBuf,
+        #[command(flatten)]
+        unlock: UnlockArgs,
+    },
+    /// Show the header, flags, metadata and signatures of a VMPAK
+    Inspect {
+        file: PathBuf,
+        #[command(flatten)]
+        unlock: UnlockArgs,
+    },
+    /// Check every checksum in a VMPAK, exits with 2 if anything is corrupt
+    Verify {
+        file: PathBuf,
+        #[command(flatten)]
+        unlock: UnlockArgs,
+    },
+    /// Repack a zip, 7z or tar archive into a VMPAK
+    Convert(ConvertArgs),
+    /// Create a patch holding only what changed between two versions of a VMPAK
+    Diff {
+        base: PathBuf,
+        target: PathBuf,
+        #[arg(short, long)]
+        output: PathBuf,
+        /// Store changed files whole instead of as binary diffs
+        #[arg(long)]
+        no_binary_diffs: bool,
+        /// `stored`, `deflate[:level]` or `zstd[:level]`
+        #[arg(long, default_value = "zstd:3")]
+        compression: String,
+    },
+    /// Apply a patch to its base VMPAK, or to an extracted copy of it with --in-place
+    Patch {
+        patch: PathBuf,
+        /// Base pack the patch was made against
+        #[arg(long, requires = "output", required_unless_present = "in_place")]
+        base: Option<PathBuf>,
+        /// Where to write the patched pack
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+        /// Directory holding the extracted base pack, patched in place
+        #[arg(long, conflicts_with_all = ["base", "output"])]
+        in_place: Option<PathBuf>,
+    },
+    /// Install a VMPAK into a game following its install manifest
+    Install(InstallArgs),
+    /// Add an Ed25519 signature to an existing VMPAK
+    Sign {
+        file: PathBuf,
+        /// File containing the hex encoded 32 byte signing key
+        #[arg(long)]
+        key_file: PathBuf,
+    },
+}
+
+#[derive(Args)]
+struct PackArgs {
+    dir: PathBuf,
+    /// Output file, defaults to `<dir>.vmpak`
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    #[command(flatten)]
+    metadata: MetadataArgs,
+    /// `stored`, `deflate[:level]` or `zstd[:level]`
+    #[arg(long, default_value = "zstd:3")]
+    compression: String,
+    /// `off`, `files` (identical files share a payload) or `chunks` (content-defined chunks)
+    #[arg(long, default_value = "files")]
+    dedup: String,
+    /// Split the pack into volumes of at most this size (e.g. `4095M` for FAT32), named `.vmpak`, `.vmpak.001`, ...
+    #[arg(long)]
+    volume_size: Option<String>,
+    /// Mark the pack as shipping user editable configs
+    #[arg(long)]
+    has_configs: bool,
+    /// File containing the hex encoded 32 byte signing key
+    #[arg(long)]
+    sign_key_file: Option<PathBuf>,
+    /// Encrypt payloads with this passphrase
+    #[arg(long, env = "VMPAK_PASSPHRASE", hide_env_values = true, conflicts_with = "key")]
+    passphrase: Option<String>,
+    /// Encrypt payloads with this hex encoded key
+    #[arg(long, env = "VMPAK_KEY", hide_env_values = true)]
+    key: Option<String>,
+    /// Keep the metadata and index readable when encrypting
+    #[arg(long)]
+    public_index: bool,
+}
+
+/// Values passed on the command line take precedence over the metadata file
+#[derive(Args)]
+struct MetadataArgs {
+    /// JSON metadata file, `pack` defaults to `vmpak.json` inside the directory
+    #[arg(long = "metadata")]
+    file: Option<PathBuf>,
+    #[arg(long)]
+    id: Option<String>,
+    #[arg(long)]
+    name: Option<String>,
+    #[arg(long = "mod-version")]
+    mod_version: Option<String>,
+    #[arg(long)]
+    game: Option<String>,
+}
+
+#[derive(Args)]
+struct ConvertArgs {
+    archive: PathBuf,
+    /// Output file, defaults to the archive name with a `.vmpak` extension
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    #[command(flatten)]
+    metadata: MetadataArgs,
+    /// `stored`, `deflate[:level]` or `zstd[:level]`
+    #[arg(long, default_value = "zstd:3")]
+    compression: String,
+    /// File containing the hex encoded 32 byte signing key
+    #[arg(long)]
+    sign_key_file: Option<PathBuf>,
+}
+
+#[derive(Args)]
+struct InstallArgs {
+    file: PathBuf,
+    #[arg(long)]
+    game_dir: PathBuf,
+    #[arg(long)]
+    config_dir: Option<PathBuf>,
+    #[arg(long)]
+    save_dir: Option<PathBuf>,
+    /// Checked against the manifest's game version conditions
+    #[arg(long)]
+    game_version: Option<String>,
+    /// Optional component to install, repeat for several. Defaults to the pack's default components
+    #[arg(long = "component")]
+    components: Vec<String>,
+    /// Id of a mod that's already installed, for the manifest's mod conditions
+    #[arg(long = "with-mod")]
+    installed_mods: Vec<String>,
+    /// Only print where each file would go
+    #[arg(long)]
+    dry_run: bool,
+    #[command(flatten)]
+    unlock: UnlockArgs,
+}
+
+#[derive(Args)]
+struct UnlockArgs {
+    /// Passphrase for encrypted packs
+    #[arg(long, env = "VMPAK_PASSPHRASE", hide_env_values = true, conflicts_with = "key")]
+    passphrase: Option<String>,
+    /// Hex encoded key for encrypted packs
+    #[arg(long, env = "VMPAK_KEY", hide_env_values = true)]
+    key: Option<String>,
+}
+
+impl UnlockArgs {
+    fn unlock(&self) -> io::Result<Option<VmpakUnlock>> {
+        if let Some(passphrase) = &self.passphrase {
+            return Ok(Some(VmpakUnlock::Passphrase(passphrase.clone())));
+        }
+        self.key.as_deref().map(|key| VmpakKey::from_hex(key).map(VmpakUnlock::Key)).transpose()
+    }
+}
+
+fn main() -> ExitCode {
+    tracing_subscriber::fmt().with_writer(io::stderr).with_max_level(tracing::Level::WARN).init();
+    let cli = Cli::parse();
+    // Only commands that read a pack can find it corrupt, invalid input to the others is a usage error
+    let reads_pack = matches!(cli.command, Command::Unpack { .. } | Command::Ls { .. } | Command::Inspect { .. } | Command::Verify { .. });
+
+    match run(&cli) {
+        Ok(code) => code,
+        Err(e) => {
+            if cli.json {
+                println!("{}", json!({ "error": e.to_string() }));
+            } else {
+                eprintln!("error: {e}");
+            }
+            if reads_pack && e.kind() == io::ErrorKind::InvalidData {
+                ExitCode::from(2)
+            } else {
+                ExitCode::FAILURE
+            }
+        }
+    }
+}
+
+fn run(cli: &Cli) -> io::Result<ExitCode> {
+    match &cli.command {
+        Command::Pack(args) => pack(args, cli.json),
+        Command::Unpack { file, dir, unlock } => unpack(file, dir, unlock, cli.json),
+        Command::Ls { file, unlock } => list(file, unlock, cli.json),
+        Command::Inspect { file, unlock } => inspect(file, unlock, cli.json),
+        Command::Verify { file, unlock } => verify_pack(file, unlock, cli.json),
+        Command::Convert(args) => convert(args, cli.json),
+        Command::Diff { base, target, output, no_binary_diffs, compression } => {
+            let options = PatchOptions { binary_diffs: !no_binary_diffs, compression: parse_compression(compression)? };
+            diff(base, target, output, &options, cli.json)
+        }
+        Command::Patch { patch, base, output, in_place } => apply(patch, base.as_deref(), output.as_deref(), in_place.as_deref(), cli.json),
+        Command::Install(args) => install(args, cli.json),
+        Command::Sign { file, key_file } => sign(file, key_file, cli.json),
+    }
+}
+
+fn parse_compression(value: &str) -> io::Result<Compression> {
+    let (method, level) = match value.split_once(':') {
+        Some((method, level)) => (method, Some(level)),
+        None => (value, None),
+    };
+    let invalid = || io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid compression '{value}'"));
+
+    match method {
+        "stored" => Ok(Compression::Stored),
+        "deflate" => Ok(Compression::Deflate(level.map(str::parse).transpose().map_err(|_| invalid())?.unwrap_or(6))),
+        "zstd" => Ok(Compression::Zstd(level.map(str::parse).transpose().map_err(|_| invalid())?.unwrap_or(3))),
+        _ => Err(invalid()),
+    }
+}
+
+fn parse_dedup(value: &str) -> io::Result<VmpakDedup> {
+    match value {
+        "off" => Ok(VmpakDedup::Off),
+        "files" => Ok(VmpakDedup::Files),
+        "chunks" => Ok(VmpakDedup::Chunks),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid dedup mode '{value}'"))),
+    }
+}
+
+/// Byte count with an optional binary `K`, `M` or `G` suffix
+fn parse_size(value: &str) -> io::Result<u64> {
+    let invalid = || io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid size '{value}'"));
+    let upper = value.trim().to_ascii_uppercase();
+    let (number, multiplier) = match upper.trim_end_matches('B').trim_end_matches('I') {
+        n if n.ends_with('K') => (&n[..n.len() - 1], 1u64 << 10),
+        n if n.ends_with('M') => (&n[..n.len() - 1], 1 << 20),
+        n if n.ends_with('G') => (&n[..n.len() - 1], 1 << 30),
+        n => (n, 1),
+    };
+    number.trim().parse::<u64>().ok().and_then(|n| n.checked_mul(multiplier)).filter(|&n| n > 0).ok_or_else(invalid)
+}
+
+fn read_signing_key(path: &Path) -> io::Result<SigningKey> {
+    let bytes = hex::decode(fs::read_to_string(path)?.trim())
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Signing key must be 64 hex characters"))?;
+    Ok(SigningKey::from_bytes(&bytes))
+}
+
+fn load_pack_metadata(args: &MetadataArgs, default_file: Option<PathBuf>) -> io::Result<VmpakMetadata> {
+    // Only the implicit `vmpak.json` is optional, a file asked for by name has to be there
+    let metadata_path = match (&args.file, default_file) {
+        (Some(path), _) => Some(path.clone()),
+        (None, Some(path)) if path.exists() => Some(path),
+        (None, _) => None,
+    };
+    let mut metadata = match metadata_path {
+        Some(path) => {
+            let bytes = fs::read(&path).map_err(|e| io::Error::new(e.kind(), format!("Can't read metadata file {}: {e}", path.display())))?;
+            VmpakMetadata::from_bytes(&bytes, binary::VMPAK_FORMAT_VERSION)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("{}: {e}", path.display())))?
+        }
+        None => VmpakMetadata::new("", "", "", ""),
+    };
+
+    if let Some(id) = &args.id {
+        metadata.mod_id = id.clone();
+    }
+    if let Some(name) = &args.name {
+        metadata.name = name.clone();
+    }
+    if let Some(version) = &args.mod_version {
+        metadata.version = version.clone();
+    }
+    if let Some(game) = &args.game {
+        metadata.game_id = game.clone();
+    }
+
+    if metadata.mod_id.is_empty() || metadata.game_id.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "A mod id and game id are required, pass --id/--game or add them to the metadata file",
+        ));
+    }
+    if metadata.name.is_empty() {
+        metadata.name = metadata.mod_id.clone();
+    }
+
+    Ok(metadata)
+}
+
+/// Every regular file under `dir`, as (`/` separated relative path, absolute path), sorted so packs are reproducible
+fn collect_files(dir: &Path, relative: &str, files: &mut Vec<(String, PathBuf)>) -> io::Result<()> {
+    let mut children: Vec<_> = fs::read_dir(dir)?.collect::<io::Result<_>>()?;
+    children.sort_by_key(|c| c.file_name());
+
+    for child in children {
+        let name = child.file_name().to_string_lossy().into_owned();
+        let path = if relative.is_empty() { name.clone() } else { format!("{relative}/{name}") };
+        let file_type = child.file_type()?;
+
+        if file_type.is_dir() {
+            collect_files(&child.path(), &path, files)?;
+        } else if file_type.is_file() {
+            files.push((path, child.path()));
+        } else {
+            tracing::warn!("Skipping {}, only regular files are packed", child.path().display());
+        }
+    }
+    Ok(())
+}
+
+fn pack(args: &PackArgs, json: bool) -> io::Result<ExitCode> {
+    let metadata = load_pack_metadata(&args.metadata, Some(args.dir.join(METADATA_FILE)))?;
+    let output = args.output.clone().unwrap_or_else(|| args.dir.with_extension("vmpak"));
+
+    let mut files = Vec::new();
+    collect_files(&args.dir, "", &mut files)?;
+    let output_abs = fs::canonicalize(output.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new(".")))?
+        .join(output.file_name().unwrap_or_default());
+    // Skips the output and any of its volumes if they land inside the directory being packed
+    let volume_prefix = format!("{}.", output_abs.display());
+    files.retain(|(relative, path)| {
+        relative != METADATA_FILE
+            && fs::canonicalize(path)
+                .map(|p| p != output_abs && !p.display().to_string().starts_with(&volume_prefix))
+                .unwrap_or(true)
+    });
+
+    let encryption = match (&args.passphrase, &args.key) {
+        (Some(passphrase), _) => Some(VmpakEncryption::from_passphrase(passphrase, args.public_index)?),
+        (None, Some(key)) => Some(VmpakEncryption::from_key(VmpakKey::from_hex(key)?, args.public_index)?),
+        (None, None) => None,
+    };
+
+    let mut flags = VmpakFlags::empty();
+    flags.set(VmpakFlags::HAS_CONFIGS, args.has_configs);
+
+    let volume_size = args.volume_size.as_deref().map(parse_size).transpose()?.unwrap_or(u64::MAX);
+    let file = BufWriter::new(VolumeWriter::create(&output, volume_size)?);
+    let mut writer = VmpakWriter::with_encryption(file, &metadata, flags, encryption)?;
+    writer.set_compression(parse_compression(&args.compression)?);
+    writer.set_dedup(parse_dedup(&args.dedup)?);
+    if let Some(key_file) = &args.sign_key_file {
+        writer.add_signer(read_signing_key(key_file)?);
+    }
+
+    let mut total_size = 0;
+    for (relative, path) in &files {
+        total_size += writer.add_file(relative, path)?.size;
+    }
+    let volumes = writer.finish()?.into_inner().map_err(|e| e.into_error())?.paths();
+    // Chunked entries only account for their chunk table, so count what actually landed on disk
+    let total_compressed = volumes.iter().map(|v| fs::metadata(v).map(|m| m.len())).sum::<io::Result<u64>>()?;
+
+    if json {
+        println!("{}", json!({
+            "output": output.display().to_string(),
+            "volumes": volumes.iter().map(|v| v.display().to_string()).collect::<Vec<_>>(),
+            "entries": files.len(),
+            "size": total_size,
+            "compressed_size": total_compressed,
+        }));
+    } else {
+        println!("Packed {} files ({} -> {} bytes) into {}", files.len(), total_size, total_compressed, output.display());
+        if volumes.len() > 1 {
+            println!("Split into {} volumes", volumes.len());
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn convert(args: &ConvertArgs, json: bool) -> io::Result<ExitCode> {
+    let metadata = load_pack_metadata(&args.metadata, None)?;
+    let output = args.output.clone().unwrap_or_else(|| {
+        let name = args.archive.file_name().unwrap_or_default().to_string_lossy();
+        let stem = [".tar.gz", ".tar.xz", ".tar.zst"]
+            .iter()
+            .find_map(|suffix| name.strip_suffix(suffix))
+            .map(str::to_string)
+            .unwrap_or_else(|| Path::new(name.as_ref()).with_extension("").to_string_lossy().into_owned());
+        args.archive.with_file_name(format!("{stem}.vmpak"))
+    });
+
+    // Written next to the output and renamed over it once it's complete, a failed conversion leaves nothing behind
+    let mut temp = output.as_os_str().to_owned();
+    temp.push(".tmp");
+    let temp = PathBuf::from(temp);
+    let converted = (|| {
+        let mut writer = VmpakWriter::new(BufWriter::new(File::create(&temp)?), &metadata, VmpakFlags::empty())?;
+        writer.set_compression(parse_compression(&args.compression)?);
+        if let Some(key_file) = &args.sign_key_file {
+            writer.add_signer(read_signing_key(key_file)?);
+        }
+        let entries = convert_archive(&args.archive, &mut writer)?;
+        writer.finish()?.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        Ok(entries)
+    })();
+    let entries = match converted {
+        Ok(entries) => entries,
+        Err(e) => {
+            let _ = fs::remove_file(&temp);
+            return Err(e);
+        }
+    };
+    // The output is a single file, volumes of a split pack that was there before would be stale
+    remove_volumes(&output)?;
+    fs::rename(&temp, &output)?;
+
+    if json {
+        println!("{}", json!({ "output": output.display().to_string(), "entries": entries }));
+    } else {
+        println!("Converted {} files from {} into {}", entries, args.archive.display(), output.display());
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Joins an entry path onto `root`, refusing anything that would land outside it
+fn safe_join(root: &Path, entry_path: &str) -> io::Result<PathBuf> {
+    let relative = Path::new(entry_path);
+    let escapes = relative.components().any(|c| !matches!(c, Component::Normal(_)));
+    if escapes || entry_path.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Refusing to extract {entry_path}, it points outside the target directory"),
+        ));
+    }
+    Ok(root.join(relative))
+}
+
+fn unpack(file: &Path, dir: &Path, unlock: &UnlockArgs, json: bool) -> io::Result<ExitCode> {
+    let mut reader = VmpakReader::new(VolumeReader::open(file)?, unlock.unlock()?.as_ref())?;
+    let entries = reader.entries().to_vec();
+
+    for entry in &entries {
+        let target = safe_join(dir, &entry.path)?;
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut output = BufWriter::new(File::create(&target)?);
+        io::copy(&mut reader.open_entry(&entry.path)?, &mut output)?;
+        entry.attributes.apply(&output.into_inner().map_err(|e| e.into_error())?)?;
+    }
+
+    if json {
+        println!("{}", json!({ "output": dir.display().to_string(), "entries": entries.len() }));
+    } else {
+        println!("Extracted {} files into {}", entries.len(), dir.display());
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn install(args: &InstallArgs, json: bool) -> io::Result<ExitCode> {
+    let mut reader = VmpakReader::new(VolumeReader::open(&args.file)?, args.unlock.unlock()?.as_ref())?;
+
+    let roots = [
+        (InstallRoot::Game, Some(&args.game_dir)),
+        (InstallRoot::Config, args.config_dir.as_ref()),
+        (InstallRoot::Save, args.save_dir.as_ref()),
+    ];
+    let context = InstallContext {
+        roots: roots.into_iter().filter_map(|(root, dir)| Some((root, dir?.clone()))).collect(),
+        game_version: args.game_version.clone(),
+        installed_mods: args.installed_mods.iter().cloned().collect(),
+        components: (!args.components.is_empty()).then(|| args.components.clone()),
+    };
+    let plan = reader.plan_install(&context)?;
+    if !args.dry_run {
+        reader.install(&plan)?;
+    }
+
+    if json {
+        println!("{}", json!({ "dry_run": args.dry_run, "components": plan.components, "files": plan.files }));
+    } else {
+        for file in &plan.files {
+            println!("{} -> {}", file.entry, file.destination.display());
+        }
+        if !plan.components.is_empty() {
+            println!("Components: {}", plan.components.join(", "));
+        }
+        let verb = if args.dry_run { "Would install" } else { "Installed" };
+        println!("{verb} {} files", plan.files.len());
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn list(file: &Path, unlock: &UnlockArgs, json: bool) -> io::Result<ExitCode> {
+    let reader = VmpakReader::new(VolumeReader::open(file)?, unlock.unlock()?.as_ref())?;
+
+    if json {
+        let entries: Vec<_> = reader.entries().iter().map(|e| json!({
+            "path": e.path,
+            "size": e.size,
+            "compressed_size": e.compressed_size,
+            "compression": format!("{:?}", e.compression),
+            "checksum": e.checksum.map(|c| format!("{c:08x}")),
+            "modified": e.attributes.modified,
+            "executable": e.attributes.executable,
+        })).collect();
+        println!("{}", serde_json::Value::Array(entries));
+    } else {
+        for e in reader.entries() {
+            println!("{:>12} {:>12}  {:<12} {}", e.size, e.compressed_size, format!("{:?}", e.compression), e.path);
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn inspect(file: &Path, unlock: &UnlockArgs, json: bool) -> io::Result<ExitCode> {
+    let mut source = VolumeReader::open(file)?;
+    let volumes = source.volume_count();
+    let header = VmpakHeader::read_unchecked(&mut source)?;
+    header.check_version()?;
+    let encryption = VmpakEncryptionHeader::read_for(&mut source, &header)?;
+    let signatures = read_signatures(&mut source, &header)?;
+
+    // The metadata might be encrypted, in which case only the header is shown without a key
+    let unlock = unlock.unlock()?;
+    let (metadata, dedup, assets) = if header.flags.contains(VmpakFlags::ENCRYPTED_INDEX) && unlock.is_none() {
+        (None, None, None)
+    } else {
+        let reader = VmpakReader::new(&mut source, unlock.as_ref())?;
+        let (metadata, entries, assets) = (reader.metadata().clone(), reader.entries().to_vec(), reader.assets());
+        (Some(metadata), Some(dedup_stats(&mut source, &entries)?), Some(assets))
+    };
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&json!({
+            "header": {
+                "format_version": header.format_version,
+                "manager_version": header.manager_version,
+                "index_table_offset": header.index_table_offset,
+                "metadata_size": header.metadata_size,
+                "flags": header.flags.bits(),
+                "flag_names": format!("{:?}", header.flags),
+            },
+            "volumes": volumes,
+            "encryption": encryption.map(|e| json!({ "passphrase": e.is_passphrase() })),
+            "signatures": signatures.iter().map(|s| hex::encode(s.public_key)).collect::<Vec<_>>(),
+            "metadata": metadata,
+            "dedup": dedup.as_ref().map(|d| json!({ "stats": d, "saved": d.saved() })),
+            "assets": assets,
+        }))?);
+    } else {
+        println!("Format version:  {}", header.format_version);
+        println!("Manager version: {}", header.manager_version);
+        println!("Flags:           {:?}", header.flags);
+        if volumes > 1 {
+            println!("Volumes:         {volumes}");
+        }
+        if let Some(encryption) = encryption {
+            println!("Encryption:      {}", if encryption.is_passphrase() { "passphrase" } else { "key" });
+        }
+        for signature in &signatures {
+            println!("Signed by:       {}", hex::encode(signature.public_key));
+        }
+        if let Some(dedup) = &dedup {
+            println!(
+                "Payloads:        {} bytes stored, {} saved by deduplication ({} shared entries)",
+                dedup.stored_size, dedup.saved(), dedup.shared_entries,
+            );
+        }
+        if let Some(assets) = assets.filter(|a| !a.is_empty()) {
+            let listed: Vec<_> = [&assets.thumbnail, &assets.readme, &assets.changelog, &assets.names]
+                .into_iter()
+                .flatten()
+                .chain(&assets.screenshots)
+                .map(String::as_str)
+                .collect();
+            println!("Assets:          {}", listed.join(", "));
+        }
+        match metadata {
+            Some(metadata) => println!("Metadata:\n{}", serde_json::to_string_pretty(&metadata)?),
+            None => println!("Metadata:        encrypted"),
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn verify_pack(file: &Path, unlock: &UnlockArgs, json: bool) -> io::Result<ExitCode> {
+    let mut source = VolumeReader::open(file)?;
+    let key = match unlock.unlock()? {
+        Some(unlock) => {
+            let header = VmpakHeader::read(&mut source)?;
+            match VmpakEncryptionHeader::read_for(&mut source, &header)? {
+                Some(encryption) => Some(encryption.unlock(&unlock)?),
+                None => None,
+            }
+        }
+        None => None,
+    };
+
+    let report = verify(&mut source, key.as_ref())?;
+
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        println!("Index:   {}", if report.index_ok { "ok" } else { "CORRUPT" });
+        match report.digest_ok {
+            Some(true) => println!("Digest:  ok"),
+            Some(false) => println!("Digest:  MISMATCH"),
+            None => println!("Digest:  not present (format version {})", report.format_version),
+        }
+        println!("Entries: {} checked, {} skipped (encrypted)", report.entries_checked, report.entries_skipped);
+        for corrupt in &report.corrupt_entries {
+            println!("  CORRUPT {}: {}", corrupt.path, corrupt.reason);
+        }
+    }
+
+    Ok(if report.is_ok() { ExitCode::SUCCESS } else { ExitCode::from(2) })
+}
+
+fn diff(base: &Path, target: &Path, output: &Path, options: &PatchOptions, json: bool) -> io::Result<ExitCode> {
+    let summary = create_patch(base, target, output, options)?;
+
+    if json {
+        println!("{}", json!({ "output": output.display().to_string(), "summary": summary }));
+    } else {
+        println!(
+            "Wrote {}: {} added, {} changed ({} as diffs), {} removed, {} unchanged",
+            output.display(), summary.added, summary.changed, summary.diffed, summary.removed, summary.unchanged,
+        );
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn apply(patch: &Path, base: Option<&Path>, output: Option<&Path>, in_place: Option<&Path>, json: bool) -> io::Result<ExitCode> {
+    let target = match (base, output, in_place) {
+        (_, _, Some(dir)) => {
+            apply_patch_in_place(patch, dir)?;
+            dir
+        }
+        (Some(base), Some(output), None) => {
+            apply_patch(base, patch, output)?;
+            output
+        }
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "Pass --base and --output, or --in-place")),
+    };
+
+    if json {
+        println!("{}", json!({ "output": target.display().to_string() }));
+    } else {
+        println!("Patched {}", target.display());
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+fn sign(file: &Path, key_file: &Path, json: bool) -> io::Result<ExitCode> {
+    let key = read_signing_key(key_file)?;
+    if VolumeReader::open(file)?.volume_count() > 1 {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "Split VMPAKs have to be signed when they're packed (--sign-key-file)"));
+    }
+    let mut pack = OpenOptions::new().read(true).write(true).open(file)?;
+    sign_pack(&mut pack, &key)?;
+
+    let public_key = hex::encode(key.verifying_key().to_bytes());
+    if json {
+        println!("{}", json!({ "signed_by": public_key }));
+    } else {
+        println!("Signed {} with {}", file.display(), public_key);
+    }
+    Ok(ExitCode::SUCCESS)
+}
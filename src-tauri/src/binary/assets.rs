@@ -0,0 +1,192 @@
+use std::{
+    collections::BTreeMap,
+    io::{self, Read, Seek},
+    path::Path,
+};
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use lib_vmm::traits::discovery::{ModExtendedMetadata, ModSummary};
+use serde::Serialize;
+
+use super::{VmpakEntry, VmpakReader};
+
+/// Entries under this directory describe the pack rather than being part of the mod, they're never installed
+pub const VMPAK_ASSETS_DIR: &str = ".vmpak/";
+/// `thumbnail.png`, `thumbnail.jpg`, ... shown on mod cards
+const THUMBNAIL_STEM: &str = "thumbnail";
+/// Every image in here is shown in the carousel, ordered by file name
+const SCREENSHOTS_DIR: &str = "screenshots/";
+const README: &str = "README.md";
+const CHANGELOG: &str = "CHANGELOG.md";
+/// JSON object of locale to display name, e.g. `{ "de": "Bessere Texturen" }`
+const NAMES: &str = "names.json";
+
+/// Images are handed to the frontend inline, so anything bigger is ignored rather than loaded
+const MAX_IMAGE_SIZE: u64 = 8 * 1024 * 1024;
+const MAX_TEXT_SIZE: u64 = 1024 * 1024;
+
+const IMAGE_TYPES: &[(&str, &str)] = &[
+    ("png", "image/png"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("webp", "image/webp"),
+    ("gif", "image/gif"),
+];
+
+/// Whether `path` is one of the pack's own assets rather than a file of the mod
+pub fn is_asset_path(path: &str) -> bool {
+    path.starts_with(VMPAK_ASSETS_DIR)
+}
+
+fn image_type(path: &str) -> Option<&'static str> {
+    let extension = Path::new(path).extension()?.to_str()?;
+    IMAGE_TYPES.iter().find(|(known, _)| known.eq_ignore_ascii_case(extension)).map(|(_, mime)| *mime)
+}
+
+/// Where a pack keeps its presentational assets, found by looking through the index
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct VmpakAssets {
+    pub thumbnail: Option<String>,
+    pub screenshots: Vec<String>,
+    pub readme: Option<String>,
+    pub changelog: Option<String>,
+    pub names: Option<String>,
+}
+
+impl VmpakAssets {
+    pub fn find(entries: &[VmpakEntry]) -> Self {
+        let mut assets = Self::default();
+        for entry in entries {
+            let Some(name) = entry.path.strip_prefix(VMPAK_ASSETS_DIR) else {
+                continue;
+            };
+
+            if let Some(screenshot) = name.strip_prefix(SCREENSHOTS_DIR) {
+                if !screenshot.contains('/') && image_type(screenshot).is_some() {
+                    assets.screenshots.push(entry.path.clone());
+                }
+            } else if name.eq_ignore_ascii_case(README) {
+                assets.readme = Some(entry.path.clone());
+            } else if name.eq_ignore_ascii_case(CHANGELOG) {
+                assets.changelog = Some(entry.path.clone());
+            } else if name == NAMES {
+                assets.names = Some(entry.path.clone());
+            } else if Path::new(name).file_stem().is_some_and(|stem| stem == THUMBNAIL_STEM) && image_type(name).is_some() {
+                // First one wins if someone ships both a png and a jpg
+                assets.thumbnail.get_or_insert_with(|| entry.path.clone());
+            }
+        }
+        assets.screenshots.sort();
+        assets
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl<R: Read + Seek> VmpakReader<R> {
+    pub fn assets(&self) -> VmpakAssets {
+        VmpakAssets::find(self.entries())
+    }
+
+    fn read_asset(&mut self, path: &str, limit: u64) -> io::Result<Option<Vec<u8>>> {
+        let Some(entry) = self.entry(path) else {
+            return Ok(None);
+        };
+        if entry.size > limit {
+            tracing::warn!("Ignoring VMPAK asset {path}, {} bytes is more than the allowed {limit}", entry.size);
+            return Ok(None);
+        }
+        let mut buffer = Vec::new();
+        self.open_entry(path)?.read_to_end(&mut buffer)?;
+        Ok(Some(buffer))
+    }
+
+    /// Markdown or other text asset, lossily decoded
+    pub fn read_text_asset(&mut self, path: &str) -> io::Result<Option<String>> {
+        Ok(self.read_asset(path, MAX_TEXT_SIZE)?.map(|bytes| String::from_utf8_lossy(&bytes).into_owned()))
+    }
+
+    /// An image asset as a `data:` URI, which is what the frontend takes in place of a remote URL
+    pub fn read_image_asset(&mut self, path: &str) -> io::Result<Option<String>> {
+        let Some(mime) = image_type(path) else {
+            return Ok(None);
+        };
+        Ok(self.read_asset(path, MAX_IMAGE_SIZE)?.map(|bytes| format!("data:{mime};base64,{}", STANDARD.encode(bytes))))
+    }
+
+    /// The localized names shipped in the pack, keyed by locale
+    pub fn localized_names(&mut self) -> io::Result<BTreeMap<String, String>> {
+        let Some(path) = self.assets().names else {
+            return Ok(BTreeMap::new());
+        };
+        match self.read_asset(&path, MAX_TEXT_SIZE)? {
+            Some(bytes) => serde_json::from_slice(&bytes)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid VMPAK {path}: {e}"))),
+            None => Ok(BTreeMap::new()),
+        }
+    }
+
+    /// Display name for `locale` (e.g. `de-AT`), falling back to the language and then to the metadata name
+    pub fn localized_name(&mut self, locale: Option<&str>) -> io::Result<String> {
+        let names = self.localized_names()?;
+        let name = locale.and_then(|locale| {
+            let language = locale.split(['-', '_']).next().unwrap_or(locale);
+            names.get(locale).or_else(|| names.get(language))
+        });
+        Ok(name.cloned().unwrap_or_else(|| self.metadata().name.clone()))
+    }
+
+    /// Shapes the pack like a discovered mod, so a local pack renders the same way in the browser
+    pub fn summary(&mut self, locale: Option<&str>) -> io::Result<ModSummary> {
+        let assets = self.assets();
+        let metadata = self.metadata().clone();
+        let thumbnail = match &assets.thumbnail {
+            Some(path) => self.read_image_asset(path)?,
+            None => None,
+        };
+
+        Ok(ModSummary {
+            id: metadata.mod_id.clone(),
+            name: self.localized_name(locale)?,
+            short_description: metadata.description.lines().next().unwrap_or_default().to_string(),
+            description: metadata.description,
+            downloads: Default::default(),
+            views: Default::default(),
+            likes: Default::default(),
+            thumbnail_image: thumbnail.unwrap_or_default(),
+            tags: metadata.tags,
+            user_name: metadata.authors.join(", "),
+            user_avatar: String::new(),
+        })
+    }
+
+    /// The detail view of the pack: readme as the description and screenshots as the carousel.
+    /// The header image is the first screenshot, or the thumbnail if there are none
+    pub fn extended_metadata(&mut self) -> io::Result<ModExtendedMetadata> {
+        let assets = self.assets();
+        let mut carousel_images = Vec::with_capacity(assets.screenshots.len());
+        for path in &assets.screenshots {
+            carousel_images.extend(self.read_image_asset(path)?);
+        }
+
+        let header_image = match (carousel_images.first(), &assets.thumbnail) {
+            (Some(first), _) => Some(first.clone()),
+            (None, Some(thumbnail)) => self.read_image_asset(thumbnail)?,
+            (None, None) => None,
+        };
+        let readme = match &assets.readme {
+            Some(path) => self.read_text_asset(path)?,
+            None => None,
+        };
+
+        Ok(ModExtendedMetadata {
+            header_image: header_image.unwrap_or_default(),
+            carousel_images,
+            version: self.metadata().version.clone(),
+            installed: false,
+            description: readme.unwrap_or_else(|| self.metadata().description.clone()),
+        })
+    }
+}
@@ -0,0 +1,96 @@
+use std::{io::{self, Read, Write}, path::Path};
+
+/// Extensions of assets that are already compressed, deflating or zstd'ing
+/// these again just burns CPU for a handful of bytes
+const PRECOMPRESSED_EXTENSIONS: &[&str] = &[
+    "png", "jpg", "jpeg", "webp", "gif",
+    "ogg", "mp3", "opus", "flac", "wem", "bnk",
+    "mp4", "webm", "bk2", "usm",
+    "zip", "7z", "rar", "gz", "xz", "zst", "bz2", "vmpak",
+];
+
+/// How a single entry's payload is stored inside the pack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Stored,
+    /// Level 0-9
+    Deflate(u32),
+    /// Level 1-22 (negative levels are the "fast" modes)
+    Zstd(i32),
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Compression::Zstd(3)
+    }
+}
+
+impl Compression {
+    const METHOD_STORED: u8 = 0;
+    const METHOD_DEFLATE: u8 = 1;
+    const METHOD_ZSTD: u8 = 2;
+
+    /// Picks `Stored` for assets that are already compressed, `preferred` for everything else
+    pub fn for_path(path: &str, preferred: Compression) -> Compression {
+        let is_precompressed = Path::new(path)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| PRECOMPRESSED_EXTENSIONS.iter().any(|known| known.eq_ignore_ascii_case(ext)))
+            .unwrap_or(false);
+
+        if is_precompressed {
+            Compression::Stored
+        } else {
+            preferred
+        }
+    }
+
+    /// On-disk representation: (method, level)
+    pub fn to_raw(self) -> (u8, i8) {
+        match self {
+            Compression::Stored => (Self::METHOD_STORED, 0),
+            Compression::Deflate(level) => (Self::METHOD_DEFLATE, level.min(9) as i8),
+            Compression::Zstd(level) => (Self::METHOD_ZSTD, level.clamp(i8::MIN as i32, i8::MAX as i32) as i8),
+        }
+    }
+
+    pub fn from_raw(method: u8, level: i8) -> io::Result<Self> {
+        match method {
+            Self::METHOD_STORED => Ok(Compression::Stored),
+            Self::METHOD_DEFLATE => Ok(Compression::Deflate(level.max(0) as u32)),
+            Self::METHOD_ZSTD => Ok(Compression::Zstd(level as i32)),
+            other => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Unknown VMPAK compression method {other}"),
+            )),
+        }
+    }
+
+    /// Streams `source` into `sink` using this method, returns the number of uncompressed bytes read
+    pub fn compress<R: Read, W: Write>(self, source: &mut R, sink: &mut W) -> io::Result<u64> {
+        match self {
+            Compression::Stored => io::copy(source, sink),
+            Compression::Deflate(level) => {
+                let mut encoder = flate2::write::DeflateEncoder::new(sink, flate2::Compression::new(level.min(9)));
+                let read = io::copy(source, &mut encoder)?;
+                encoder.finish()?;
+                Ok(read)
+            }
+            Compression::Zstd(level) => {
+                let mut encoder = zstd::stream::write::Encoder::new(sink, level)?;
+                let read = io::copy(source, &mut encoder)?;
+                encoder.finish()?;
+                Ok(read)
+            }
+        }
+    }
+
+    /// Wraps `source` (which must yield exactly the compressed payload) in a streaming decoder
+    pub fn decompress<'a, R: Read + 'a>(self, source: R) -> io::Result<Box<dyn Read + 'a>> {
+        Ok(match self {
+            Compression::Stored => Box::new(source),
+            Compression::Deflate(_) => Box::new(flate2::read::DeflateDecoder::new(source)),
+            Compression::Zstd(_) => Box::new(zstd::stream::read::Decoder::new(source)?),
+        })
+    }
+}
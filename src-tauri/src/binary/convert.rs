@@ -0,0 +1,421 @@
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter, Read, Seek, SeekFrom, Write},
+    path::{Component, Path},
+};
+
+use sevenz_rust::{Password, SevenZReader};
+use tracing::{debug, warn};
+
+use super::{EntryAttributes, VmpakFlags, VmpakMetadata, VmpakWriter};
+
+/// Archive formats mods get distributed in that can be repacked into a VMPAK
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveKind {
+    Zip,
+    SevenZip,
+    Tar,
+    TarGz,
+    TarXz,
+    TarZst,
+    /// Recognised so it can be refused with a useful error, there's no decoder for it
+    Rar,
+}
+
+#[allow(dead_code)]
+impl ArchiveKind {
+    /// Sniffs the format from the first bytes of the file, falling back to the extension for plain tars
+    pub fn detect(path: &Path) -> io::Result<Option<Self>> {
+        let mut magic = [0u8; 262];
+        let mut file = File::open(path)?;
+        let read = read_up_to(&mut file, &mut magic)?;
+        let magic = &magic[..read];
+
+        let kind = if magic.starts_with(b"PK\x03\x04") || magic.starts_with(b"PK\x05\x06") {
+            Some(Self::Zip)
+        } else if magic.starts_with(b"7z\xBC\xAF\x27\x1C") {
+            Some(Self::SevenZip)
+        } else if magic.starts_with(&[0x1F, 0x8B]) {
+            Some(Self::TarGz)
+        } else if magic.starts_with(b"\xFD7zXZ\x00") {
+            Some(Self::TarXz)
+        } else if magic.starts_with(&[0x28, 0xB5, 0x2F, 0xFD]) {
+            Some(Self::TarZst)
+        } else if magic.starts_with(b"Rar!\x1A\x07") {
+            Some(Self::Rar)
+        } else if magic.get(257..262) == Some(b"ustar") {
+            Some(Self::Tar)
+        } else {
+            Self::from_extension(path)
+        };
+
+        Ok(kind)
+    }
+
+    fn from_extension(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?.to_ascii_lowercase();
+        if name.ends_with(".zip") {
+            Some(Self::Zip)
+        } else if name.ends_with(".7z") {
+            Some(Self::SevenZip)
+        } else if name.ends_with(".tar") {
+            Some(Self::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(Self::TarGz)
+        } else if name.ends_with(".tar.xz") || name.ends_with(".txz") {
+            Some(Self::TarXz)
+        } else if name.ends_with(".tar.zst") || name.ends_with(".tzst") {
+            Some(Self::TarZst)
+        } else if name.ends_with(".rar") {
+            Some(Self::Rar)
+        } else {
+            None
+        }
+    }
+}
+
+fn read_up_to<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..])? {
+            0 => break,
+            n => read += n,
+        }
+    }
+    Ok(read)
+}
+
+/// Caps that keep a hostile archive from filling the disk or taking forever to unpack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtractLimits {
+    /// Most entries, files or not, one archive may hold
+    pub max_entries: usize,
+    /// Most bytes all files together may unpack to
+    pub max_total_size: u64,
+    /// How many times its own size an archive may unpack to. Archives always get at least `RATIO_FLOOR`,
+    /// small ones full of text reach silly ratios without being malicious
+    pub max_ratio: u64,
+}
+
+impl Default for ExtractLimits {
+    fn default() -> Self {
+        Self { max_entries: 100_000, max_total_size: 64 << 30, max_ratio: 200 }
+    }
+}
+
+const RATIO_FLOOR: u64 = 64 << 20;
+
+/// What's left of the limits while an archive is unpacked
+struct Budget {
+    max_entries: usize,
+    entries: usize,
+    max_bytes: u64,
+    bytes: u64,
+}
+
+impl Budget {
+    fn new(limits: &ExtractLimits, archive_len: u64) -> Self {
+        let max_bytes = limits.max_total_size.min(archive_len.saturating_mul(limits.max_ratio).max(RATIO_FLOOR));
+        Self { max_entries: limits.max_entries, entries: 0, max_bytes, bytes: 0 }
+    }
+
+    fn check_entries(&self, count: usize) -> io::Result<()> {
+        if count > self.max_entries {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Archive has more than {} entries, refusing to unpack it", self.max_entries),
+            ));
+        }
+        Ok(())
+    }
+
+    fn take_entry(&mut self) -> io::Result<()> {
+        self.entries += 1;
+        self.check_entries(self.entries)
+    }
+
+    fn take_bytes(&mut self, count: usize) -> io::Result<()> {
+        self.bytes += count as u64;
+        if self.bytes > self.max_bytes {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Archive unpacks to more than {} bytes, refusing what looks like a zip bomb", self.max_bytes),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Charges everything read through it to the budget, failing once it's used up
+struct Metered<'a, R> {
+    inner: R,
+    budget: &'a mut Budget,
+}
+
+impl<R: Read> Read for Metered<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.budget.take_bytes(read)?;
+        Ok(read)
+    }
+}
+
+/// The archive file, reporting how far into it reading has got as `(position, length)`
+struct Tracked<'a> {
+    file: File,
+    position: u64,
+    len: u64,
+    progress: &'a mut dyn FnMut(u64, u64),
+}
+
+impl Read for Tracked<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.file.read(buf)?;
+        self.position += read as u64;
+        (self.progress)(self.position.min(self.len), self.len);
+        Ok(read)
+    }
+}
+
+impl Seek for Tracked<'_> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.position = self.file.seek(pos)?;
+        Ok(self.position)
+    }
+}
+
+/// Where the files of an archive end up
+trait EntrySink {
+    fn add(&mut self, path: &str, source: &mut dyn Read, attributes: EntryAttributes) -> io::Result<()>;
+}
+
+impl<W: Write + Seek> EntrySink for VmpakWriter<W> {
+    fn add(&mut self, path: &str, mut source: &mut dyn Read, attributes: EntryAttributes) -> io::Result<()> {
+        self.add_entry_with_attributes(path, &mut source, None, attributes)?;
+        Ok(())
+    }
+}
+
+/// Writes plain files below a directory the extraction created itself, so nothing in it can be a link leading elsewhere
+struct DirSink<'a> {
+    root: &'a Path,
+}
+
+impl EntrySink for DirSink<'_> {
+    fn add(&mut self, path: &str, source: &mut dyn Read, attributes: EntryAttributes) -> io::Result<()> {
+        let destination = self.root.join(path);
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = File::create(&destination)?;
+        io::copy(source, &mut file)?;
+        attributes.apply(&file)
+    }
+}
+
+fn unpack(source: &Path, sink: &mut dyn EntrySink, limits: &ExtractLimits, progress: &mut dyn FnMut(u64, u64)) -> io::Result<usize> {
+    let kind = ArchiveKind::detect(source)?.ok_or_else(|| {
+        io::Error::new(io::ErrorKind::Unsupported, format!("{} isn't a zip, 7z or tar archive", source.display()))
+    })?;
+    debug!("Unpacking {} ({:?})", source.display(), kind);
+
+    let file = File::open(source)?;
+    let len = file.metadata()?.len();
+    let mut budget = Budget::new(limits, len);
+    let tracked = Tracked { file, position: 0, len, progress };
+
+    match kind {
+        ArchiveKind::Zip => convert_zip(BufReader::new(tracked), sink, &mut budget),
+        ArchiveKind::SevenZip => convert_7z(tracked, len, sink, &mut budget),
+        ArchiveKind::Tar => convert_tar(BufReader::new(tracked), sink, &mut budget),
+        ArchiveKind::TarGz => convert_tar(flate2::read::MultiGzDecoder::new(BufReader::new(tracked)), sink, &mut budget),
+        ArchiveKind::TarXz => convert_tar(xz2::read::XzDecoder::new_multi_decoder(BufReader::new(tracked)), sink, &mut budget),
+        ArchiveKind::TarZst => convert_tar(zstd::Decoder::new(tracked)?, sink, &mut budget),
+        ArchiveKind::Rar => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{} is a RAR archive, those aren't supported yet. Repack it as zip or 7z", source.display()),
+        )),
+    }
+}
+
+/// Repacks every regular file of `source` into `writer`, keeping paths, modification times and executable bits.
+/// Directories and links are skipped, returns how many entries were added
+#[allow(dead_code)]
+pub fn convert_archive<W: Write + Seek>(source: &Path, writer: &mut VmpakWriter<W>) -> io::Result<usize> {
+    unpack(source, writer, &ExtractLimits::default(), &mut |_, _| {})
+}
+
+/// Converts `source` into a new pack at `output`, returns how many entries it holds
+#[allow(dead_code)]
+pub fn convert_to_vmpak(source: &Path, output: &Path, metadata: &VmpakMetadata, flags: VmpakFlags) -> io::Result<usize> {
+    convert_to_vmpak_with(source, output, metadata, flags, &ExtractLimits::default(), &mut |_, _| {})
+}
+
+/// `convert_to_vmpak` within `limits`, reporting how much of the archive was read to `progress`
+pub fn convert_to_vmpak_with(
+    source: &Path,
+    output: &Path,
+    metadata: &VmpakMetadata,
+    flags: VmpakFlags,
+    limits: &ExtractLimits,
+    progress: &mut dyn FnMut(u64, u64),
+) -> io::Result<usize> {
+    let mut writer = VmpakWriter::new(BufWriter::new(File::create(output)?), metadata, flags)?;
+    let added = unpack(source, &mut writer, limits, progress)?;
+    writer.finish()?;
+    Ok(added)
+}
+
+/// Unpacks every regular file of `source` into `destination`, which must not exist yet. Entries that would
+/// escape it and links are skipped like when converting, returns how many files were written
+pub fn extract_archive(source: &Path, destination: &Path, limits: &ExtractLimits, progress: &mut dyn FnMut(u64, u64)) -> io::Result<usize> {
+    if let Some(parent) = destination.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::create_dir(destination)?;
+    let extracted = unpack(source, &mut DirSink { root: destination }, limits, progress);
+    if extracted.is_err() {
+        let _ = fs::remove_dir_all(destination);
+    }
+    extracted
+}
+
+/// Normalises an archive path to `/` separated relative form, `None` if it would escape the mod root
+fn entry_path(path: &Path) -> Option<String> {
+    let mut parts = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_str()?),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    (!parts.is_empty()).then(|| parts.join("/"))
+}
+
+fn skip_unsafe(name: &str) {
+    warn!("Skipping archive entry {name}, it points outside the mod root");
+}
+
+fn convert_zip<R: Read + Seek>(source: R, sink: &mut dyn EntrySink, budget: &mut Budget) -> io::Result<usize> {
+    let mut archive = zip::ZipArchive::new(source)?;
+    budget.check_entries(archive.len())?;
+    let mut added = 0;
+
+    for i in 0..archive.len() {
+        let file = archive.by_index(i)?;
+        if !file.is_file() || file.is_symlink() {
+            continue;
+        }
+        let Some(path) = file.enclosed_name().as_deref().and_then(entry_path) else {
+            skip_unsafe(file.name());
+            continue;
+        };
+
+        let attributes = EntryAttributes {
+            modified: file.last_modified().map_or(0, |t| {
+                unix_time(t.year().into(), t.month().into(), t.day().into(), t.hour().into(), t.minute().into(), t.second().into())
+            }),
+            executable: file.unix_mode().is_some_and(|mode| mode & 0o111 != 0),
+        };
+        sink.add(&path, &mut Metered { inner: file, budget: &mut *budget }, attributes)?;
+        added += 1;
+    }
+
+    Ok(added)
+}
+
+fn convert_7z<R: Read + Seek>(source: R, len: u64, sink: &mut dyn EntrySink, budget: &mut Budget) -> io::Result<usize> {
+    /// Set in the windows attributes when the high 16 bits hold a unix mode
+    const UNIX_EXTENSION: u32 = 0x8000;
+    const REPARSE_POINT: u32 = 0x400;
+    const UNIX_FILE_TYPE: u32 = 0o170000;
+    const UNIX_SYMLINK: u32 = 0o120000;
+
+    let mut archive = SevenZReader::new(source, len, Password::empty())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+    budget.check_entries(archive.archive().files.len())?;
+    let mut added = 0;
+    // Errors from inside the callback lose their kind in sevenz_rust's error type, so they're kept here
+    let mut failure = None;
+
+    let result = archive.for_each_entries(|entry, reader| {
+        if entry.is_directory() || !entry.has_stream() || entry.is_anti_item() {
+            return Ok(true);
+        }
+        let attributes_bits = entry.windows_attributes();
+        let is_link = attributes_bits & REPARSE_POINT != 0
+            || attributes_bits & UNIX_EXTENSION != 0 && (attributes_bits >> 16) & UNIX_FILE_TYPE == UNIX_SYMLINK;
+        let path = entry_path(Path::new(&entry.name().replace('\\', "/"))).filter(|_| !is_link);
+        let Some(path) = path else {
+            match is_link {
+                true => debug!("Skipping link {} in archive", entry.name()),
+                false => skip_unsafe(entry.name()),
+            }
+            // The stream still has to be drained to reach the next entry in a solid block, and it counts towards the limits
+            if let Err(e) = io::copy(&mut Metered { inner: reader, budget: &mut *budget }, &mut io::sink()) {
+                failure = Some(e);
+                return Ok(false);
+            }
+            return Ok(true);
+        };
+
+        let attributes = EntryAttributes {
+            modified: entry.last_modified_date().to_unix_time().max(0) as u64,
+            executable: attributes_bits & UNIX_EXTENSION != 0 && (attributes_bits >> 16) & 0o111 != 0,
+        };
+        if let Err(e) = sink.add(&path, &mut Metered { inner: reader, budget: &mut *budget }, attributes) {
+            failure = Some(e);
+            return Ok(false);
+        }
+        added += 1;
+        Ok(true)
+    });
+    if let Some(e) = failure {
+        return Err(e);
+    }
+    result.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))?;
+
+    Ok(added)
+}
+
+fn convert_tar<R: Read>(source: R, sink: &mut dyn EntrySink, budget: &mut Budget) -> io::Result<usize> {
+    let mut archive = tar::Archive::new(source);
+    let mut added = 0;
+
+    for entry in archive.entries()? {
+        let entry = entry?;
+        budget.take_entry()?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let raw_path = entry.path()?.into_owned();
+        let Some(path) = entry_path(&raw_path) else {
+            skip_unsafe(&raw_path.to_string_lossy());
+            continue;
+        };
+
+        let attributes = EntryAttributes {
+            modified: entry.header().mtime().unwrap_or(0),
+            executable: entry.header().mode().is_ok_and(|mode| mode & 0o111 != 0),
+        };
+        sink.add(&path, &mut Metered { inner: entry, budget: &mut *budget }, attributes)?;
+        added += 1;
+    }
+
+    Ok(added)
+}
+
+/// Seconds since the Unix epoch for a civil UTC date, zip timestamps carry no timezone so they're taken as UTC
+fn unix_time(year: i64, month: i64, day: i64, hour: i64, minute: i64, second: i64) -> u64 {
+    // Howard Hinnant's days_from_civil
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
+
+    (days * 86_400 + hour * 3_600 + minute * 60 + second).max(0) as u64
+}
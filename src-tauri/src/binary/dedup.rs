@@ -0,0 +1,274 @@
+use std::{
+    collections::HashSet,
+    io::{self, Cursor, Read, Seek, SeekFrom},
+};
+
+use serde::Serialize;
+
+use super::{Binding, Compression, DecryptReader, VmpakEntry, VmpakKey};
+
+/// How the writer shares identical data between entries
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum VmpakDedup {
+    /// Every entry gets its own payload
+    Off,
+    /// Identical files share one payload. Files over `FILE_CHUNK_SIZE` are split into fixed-size chunks
+    #[default]
+    Files,
+    /// Content-defined chunks, so files that only differ in places still share most of their data
+    Chunks,
+}
+
+/// Chunk size in `Files` mode, also the largest chunk a reader will accept
+pub const FILE_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+const CDC_MIN_SIZE: usize = 16 * 1024;
+const CDC_MAX_SIZE: usize = 256 * 1024;
+/// Cut when the low 16 bits of the rolling hash are zero, ~64 KiB average chunks
+const CDC_MASK: u64 = (1 << 16) - 1;
+/// Room for incompressible data growing under compression plus encryption tags
+const MAX_STORED_CHUNK_SIZE: u64 = FILE_CHUNK_SIZE as u64 * 2;
+
+const fn gear_table() -> [u64; 256] {
+    // splitmix64, the table just has to be fixed and well mixed
+    let mut table = [0u64; 256];
+    let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+static GEAR: [u64; 256] = gear_table();
+
+/// Splits a stream into chunks for deduplication
+pub(super) struct Chunker<R: Read> {
+    source: R,
+    mode: VmpakDedup,
+    buffer: Vec<u8>,
+    eof: bool,
+}
+
+impl<R: Read> Chunker<R> {
+    pub fn new(source: R, mode: VmpakDedup) -> Self {
+        Self { source, mode, buffer: Vec::new(), eof: false }
+    }
+
+    fn max_size(&self) -> usize {
+        match self.mode {
+            VmpakDedup::Chunks => CDC_MAX_SIZE,
+            _ => FILE_CHUNK_SIZE,
+        }
+    }
+
+    /// Gear-hash cut point in `data`, the whole slice if none is found
+    fn cut_point(data: &[u8]) -> usize {
+        if data.len() <= CDC_MIN_SIZE {
+            return data.len();
+        }
+        let mut hash = 0u64;
+        for (i, &byte) in data.iter().enumerate().skip(CDC_MIN_SIZE) {
+            hash = (hash << 1).wrapping_add(GEAR[byte as usize]);
+            if hash & CDC_MASK == 0 {
+                return i + 1;
+            }
+        }
+        data.len()
+    }
+
+    pub fn next_chunk(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let max_size = self.max_size();
+        while !self.eof && self.buffer.len() < max_size {
+            let start = self.buffer.len();
+            self.buffer.resize(max_size, 0);
+            let read = self.source.read(&mut self.buffer[start..])?;
+            self.buffer.truncate(start + read);
+            self.eof = read == 0;
+        }
+        if self.buffer.is_empty() {
+            return Ok(None);
+        }
+
+        let cut = match self.mode {
+            VmpakDedup::Chunks => Self::cut_point(&self.buffer[..self.buffer.len().min(max_size)]),
+            _ => self.buffer.len().min(max_size),
+        };
+        Ok(Some(self.buffer.drain(..cut).collect()))
+    }
+}
+
+/// Where one chunk of a chunked entry is stored. A chunk is compressed (and encrypted) on its own,
+/// exactly like an unchunked entry's payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct ChunkRef {
+    pub offset: u64,
+    pub compressed_size: u64,
+    pub size: u64,
+}
+
+impl ChunkRef {
+    const SIZE: u64 = 8 + 8 + 8;
+
+    /// A chunked entry's payload: `u32` chunk count followed by each chunk. Stored as is, even in encrypted packs
+    pub fn encode_table(chunks: &[ChunkRef]) -> Vec<u8> {
+        let mut table = Vec::with_capacity(4 + chunks.len() * Self::SIZE as usize);
+        table.extend_from_slice(&(chunks.len() as u32).to_le_bytes());
+        for chunk in chunks {
+            table.extend_from_slice(&chunk.offset.to_le_bytes());
+            table.extend_from_slice(&chunk.compressed_size.to_le_bytes());
+            table.extend_from_slice(&chunk.size.to_le_bytes());
+        }
+        table
+    }
+
+    /// Reads the chunk table of `entry`, checking it adds up to the entry's size
+    pub fn read_table<R: Read + Seek>(reader: &mut R, entry: &VmpakEntry) -> io::Result<Vec<ChunkRef>> {
+        let invalid = |reason: &str| io::Error::new(io::ErrorKind::InvalidData, format!("VMPAK entry {}: {reason}", entry.path));
+
+        reader.seek(SeekFrom::Start(entry.offset))?;
+        let mut count_buf = [0u8; 4];
+        reader.read_exact(&mut count_buf)?;
+        let count = u32::from_le_bytes(count_buf) as u64;
+        if 4 + count * Self::SIZE != entry.compressed_size {
+            return Err(invalid("chunk table size doesn't match the index"));
+        }
+        // Chunks are never empty, so a table can't list more chunks than the entry has bytes
+        if count > entry.size {
+            return Err(invalid("chunk table lists more chunks than the entry has bytes"));
+        }
+
+        let mut table = Vec::new();
+        reader.take(count * Self::SIZE).read_to_end(&mut table)?;
+        if table.len() as u64 != count * Self::SIZE {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, format!("VMPAK entry {}: chunk table ended early", entry.path)));
+        }
+        let chunks: Vec<_> = table
+            .chunks_exact(Self::SIZE as usize)
+            .map(|raw| ChunkRef {
+                offset: u64::from_le_bytes(raw[0..8].try_into().unwrap()),
+                compressed_size: u64::from_le_bytes(raw[8..16].try_into().unwrap()),
+                size: u64::from_le_bytes(raw[16..24].try_into().unwrap()),
+            })
+            .collect();
+
+        if chunks.iter().any(|c| c.size > FILE_CHUNK_SIZE as u64 || c.compressed_size > MAX_STORED_CHUNK_SIZE) {
+            return Err(invalid("chunk is larger than allowed"));
+        }
+        if chunks.iter().map(|c| c.size).sum::<u64>() != entry.size {
+            return Err(invalid("chunk sizes don't add up to the entry size"));
+        }
+        Ok(chunks)
+    }
+}
+
+/// Decodes a chunked entry one chunk at a time, so at most one chunk is held in memory
+pub(super) struct ChunkedReader<R: Read + Seek> {
+    source: R,
+    /// Each chunk along with what it's encrypted against
+    chunks: std::vec::IntoIter<(ChunkRef, Binding)>,
+    current: Cursor<Vec<u8>>,
+    compression: Compression,
+    key: Option<VmpakKey>,
+}
+
+impl<R: Read + Seek> ChunkedReader<R> {
+    pub fn new(mut source: R, entry: &VmpakEntry, key: Option<&VmpakKey>) -> io::Result<Self> {
+        let chunks = ChunkRef::read_table(&mut source, entry)?;
+        Ok(Self {
+            source,
+            chunks: chunks.into_iter().map(|chunk| (chunk, entry.binding_at(chunk.offset))).collect::<Vec<_>>().into_iter(),
+            current: Cursor::new(Vec::new()),
+            compression: entry.compression,
+            key: key.cloned(),
+        })
+    }
+
+    fn load(&mut self, (chunk, binding): (ChunkRef, Binding)) -> io::Result<()> {
+        self.source.seek(SeekFrom::Start(chunk.offset))?;
+        let mut stored = vec![0u8; chunk.compressed_size as usize];
+        self.source.read_exact(&mut stored)?;
+
+        let decoder = match &self.key {
+            Some(key) => self.compression.decompress(DecryptReader::new(&stored[..], key, chunk.compressed_size, binding)?)?,
+            None => self.compression.decompress(&stored[..])?,
+        };
+        let mut data = Vec::with_capacity(chunk.size as usize);
+        decoder.take(chunk.size + 1).read_to_end(&mut data)?;
+        if data.len() as u64 != chunk.size {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "VMPAK chunk has the wrong size"));
+        }
+
+        self.current = Cursor::new(data);
+        Ok(())
+    }
+}
+
+impl<R: Read + Seek> Read for ChunkedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let read = self.current.read(buf)?;
+            if read > 0 || buf.is_empty() {
+                return Ok(read);
+            }
+            match self.chunks.next() {
+                Some(chunk) => self.load(chunk)?,
+                None => return Ok(0),
+            }
+        }
+    }
+}
+
+/// How much space sharing payloads saved in a pack
+#[allow(dead_code)]
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DedupStats {
+    /// Sum of every entry's decompressed size
+    pub logical_size: u64,
+    /// What the payloads would take up if nothing was shared
+    pub referenced_size: u64,
+    /// What the payloads actually take up
+    pub stored_size: u64,
+    /// Entries pointing at a payload some other entry already uses
+    pub shared_entries: usize,
+}
+
+#[allow(dead_code)]
+impl DedupStats {
+    pub fn saved(&self) -> u64 {
+        self.referenced_size.saturating_sub(self.stored_size)
+    }
+}
+
+/// Walks the index (and any chunk tables) to work out how much deduplication saved
+#[allow(dead_code)]
+pub fn dedup_stats<R: Read + Seek>(reader: &mut R, entries: &[VmpakEntry]) -> io::Result<DedupStats> {
+    let mut stats = DedupStats::default();
+    let mut payloads = HashSet::new();
+
+    for entry in entries {
+        stats.logical_size += entry.size;
+        stats.referenced_size += entry.compressed_size;
+        if payloads.insert(entry.offset) {
+            stats.stored_size += entry.compressed_size;
+        } else {
+            stats.shared_entries += 1;
+        }
+
+        if entry.chunked {
+            for chunk in ChunkRef::read_table(reader, entry)? {
+                stats.referenced_size += chunk.compressed_size;
+                if payloads.insert(chunk.offset) {
+                    stats.stored_size += chunk.compressed_size;
+                }
+            }
+        }
+    }
+
+    Ok(stats)
+}
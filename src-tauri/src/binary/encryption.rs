@@ -0,0 +1,379 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, stream::{DecryptorBE32, EncryptorBE32}, Aead, AeadCore, KeyInit, OsRng, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+
+use super::{VmpakFlags, VmpakHeader};
+
+/// Plaintext bytes per AEAD chunk of an entry payload
+const CHUNK_SIZE: usize = 64 * 1024;
+const TAG_SIZE: usize = 16;
+/// XChaCha20's 24 byte nonce minus the 5 bytes the STREAM construction uses for its counter
+const STREAM_NONCE_SIZE: usize = 19;
+const NONCE_SIZE: usize = 24;
+
+#[allow(dead_code)]
+const KDF_RAW_KEY: u8 = 0;
+const KDF_ARGON2ID: u8 = 1;
+/// Key derivation costs are read from the pack, so they're capped before anything is allocated.
+/// Well above `Params::default()`, which is what the writer uses
+const MAX_KDF_MEMORY_COST: u32 = 1024 * 1024;
+const MAX_KDF_TIME_COST: u32 = 32;
+const MAX_KDF_PARALLELISM: u32 = 16;
+
+/// A 256-bit pack key, either random (kept in the keyring) or derived from a passphrase
+#[derive(Clone, PartialEq, Eq)]
+pub struct VmpakKey([u8; 32]);
+
+#[allow(dead_code)]
+impl VmpakKey {
+    pub fn generate() -> Self {
+        let mut key = [0u8; 32];
+        OsRng.fill_bytes(&mut key);
+        VmpakKey(key)
+    }
+
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        VmpakKey(bytes)
+    }
+
+    pub fn from_hex(hex_key: &str) -> io::Result<Self> {
+        hex::decode(hex_key.trim())
+            .ok()
+            .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+            .map(VmpakKey)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "VMPAK key must be 64 hex characters"))
+    }
+
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.0)
+    }
+
+    fn cipher(&self) -> XChaCha20Poly1305 {
+        XChaCha20Poly1305::new(&self.0.into())
+    }
+}
+
+impl std::fmt::Debug for VmpakKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("VmpakKey(..)")
+    }
+}
+
+/// What an encrypted block or payload is bound to as associated data, so it doesn't decrypt anywhere else in the pack.
+/// With a public index nothing stops the index pointing an entry at another entry's payload, only a signature covers that
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Binding {
+    /// Packs before format version 7 bound nothing
+    Unbound,
+    Metadata,
+    Index,
+    /// An entry payload or chunk, by the offset it's stored at
+    Payload(u64),
+}
+
+impl Binding {
+    /// `self`, or `Unbound` if the pack predates bindings
+    pub fn for_format(self, format_version: u16) -> Self {
+        if format_version >= 7 {
+            self
+        } else {
+            Binding::Unbound
+        }
+    }
+
+    fn aad(self) -> Vec<u8> {
+        match self {
+            Binding::Unbound => Vec::new(),
+            Binding::Metadata => b"metadata".to_vec(),
+            Binding::Index => b"index".to_vec(),
+            Binding::Payload(offset) => [b"payload".as_slice(), &offset.to_le_bytes()].concat(),
+        }
+    }
+}
+
+fn crypto_error(context: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("VMPAK decryption failed: {context}"))
+}
+
+/// How the user is unlocking an encrypted pack
+#[derive(Debug, Clone)]
+#[allow(dead_code)]
+pub enum VmpakUnlock {
+    Key(VmpakKey),
+    Passphrase(String),
+}
+
+/// Sits right after the header when `VmpakFlags::ENCRYPTED` is set, says how to get the key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VmpakEncryptionHeader {
+    pub kdf: u8,
+    pub memory_cost: u32,
+    pub time_cost: u32,
+    pub parallelism: u32,
+    pub salt: [u8; 16],
+    /// Nonce + tag of an empty message, lets us reject a wrong key before touching any payload
+    pub key_check: [u8; NONCE_SIZE + TAG_SIZE],
+}
+
+#[allow(dead_code)]
+impl VmpakEncryptionHeader {
+    pub const SIZE: usize = 1 + 4 + 4 + 4 + 16 + NONCE_SIZE + TAG_SIZE;
+
+    fn new(kdf: u8, params: Option<&Params>, salt: [u8; 16], key: &VmpakKey) -> io::Result<Self> {
+        let mut header = Self {
+            kdf,
+            memory_cost: params.map_or(0, |p| p.m_cost()),
+            time_cost: params.map_or(0, |p| p.t_cost()),
+            parallelism: params.map_or(0, |p| p.p_cost()),
+            salt,
+            key_check: [0u8; NONCE_SIZE + TAG_SIZE],
+        };
+        let sealed = seal(key, &[], Binding::Unbound)?;
+        header.key_check.copy_from_slice(&sealed);
+        Ok(header)
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&[self.kdf])?;
+        writer.write_all(&self.memory_cost.to_le_bytes())?;
+        writer.write_all(&self.time_cost.to_le_bytes())?;
+        writer.write_all(&self.parallelism.to_le_bytes())?;
+        writer.write_all(&self.salt)?;
+        writer.write_all(&self.key_check)?;
+        Ok(())
+    }
+
+    pub fn read<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let mut buffer = [0u8; Self::SIZE];
+        reader.read_exact(&mut buffer)?;
+
+        Ok(Self {
+            kdf: buffer[0],
+            memory_cost: u32::from_le_bytes(buffer[1..5].try_into().unwrap()),
+            time_cost: u32::from_le_bytes(buffer[5..9].try_into().unwrap()),
+            parallelism: u32::from_le_bytes(buffer[9..13].try_into().unwrap()),
+            salt: buffer[13..29].try_into().unwrap(),
+            key_check: buffer[29..].try_into().unwrap(),
+        })
+    }
+
+    /// Reads the encryption header of `header`'s pack, `None` if the pack isn't encrypted
+    pub fn read_for<R: Read + Seek>(reader: &mut R, header: &VmpakHeader) -> io::Result<Option<Self>> {
+        if !header.flags.contains(VmpakFlags::ENCRYPTED) {
+            return Ok(None);
+        }
+        reader.seek(SeekFrom::Start(header.encoded_len() as u64))?;
+        Self::read(reader).map(Some)
+    }
+
+    pub fn is_passphrase(&self) -> bool {
+        self.kdf == KDF_ARGON2ID
+    }
+
+    /// Derives the key from `passphrase` and checks it against the pack
+    pub fn unlock_with_passphrase(&self, passphrase: &str) -> io::Result<VmpakKey> {
+        if self.kdf != KDF_ARGON2ID {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "VMPAK was encrypted with a key, not a passphrase"));
+        }
+        if self.memory_cost > MAX_KDF_MEMORY_COST || self.time_cost > MAX_KDF_TIME_COST || self.parallelism > MAX_KDF_PARALLELISM {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "VMPAK key derivation parameters are unreasonably expensive"));
+        }
+        let params = Params::new(self.memory_cost, self.time_cost, self.parallelism, Some(32))
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid VMPAK key derivation parameters: {e}")))?;
+        let key = derive_key(passphrase, &self.salt, params)?;
+        self.unlock_with_key(key)
+    }
+
+    pub fn unlock(&self, unlock: &VmpakUnlock) -> io::Result<VmpakKey> {
+        match unlock {
+            VmpakUnlock::Key(key) => self.unlock_with_key(key.clone()),
+            VmpakUnlock::Passphrase(passphrase) => self.unlock_with_passphrase(passphrase),
+        }
+    }
+
+    /// Checks `key` against the pack, handing it back if it's the right one
+    pub fn unlock_with_key(&self, key: VmpakKey) -> io::Result<VmpakKey> {
+        open(&key, &self.key_check, Binding::Unbound).map_err(|_| {
+            io::Error::new(io::ErrorKind::PermissionDenied, "Wrong key or passphrase for this VMPAK")
+        })?;
+        Ok(key)
+    }
+}
+
+fn derive_key(passphrase: &str, salt: &[u8; 16], params: Params) -> io::Result<VmpakKey> {
+    let mut key = [0u8; 32];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, format!("Failed to derive VMPAK key: {e}")))?;
+    Ok(VmpakKey(key))
+}
+
+/// Everything the writer needs to produce an encrypted pack
+#[derive(Debug, Clone)]
+pub struct VmpakEncryption {
+    pub key: VmpakKey,
+    pub header: VmpakEncryptionHeader,
+    /// Leave the metadata and index readable so people can see what's inside without the key
+    pub public_index: bool,
+}
+
+#[allow(dead_code)]
+impl VmpakEncryption {
+    pub fn from_passphrase(passphrase: &str, public_index: bool) -> io::Result<Self> {
+        let mut salt = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        let params = Params::default();
+        let key = derive_key(passphrase, &salt, params.clone())?;
+        let header = VmpakEncryptionHeader::new(KDF_ARGON2ID, Some(&params), salt, &key)?;
+        Ok(Self { key, header, public_index })
+    }
+
+    /// For keys kept in the keyring rather than typed in
+    pub fn from_key(key: VmpakKey, public_index: bool) -> io::Result<Self> {
+        let header = VmpakEncryptionHeader::new(KDF_RAW_KEY, None, [0u8; 16], &key)?;
+        Ok(Self { key, header, public_index })
+    }
+
+    pub fn flags(&self) -> VmpakFlags {
+        if self.public_index {
+            VmpakFlags::ENCRYPTED
+        } else {
+            VmpakFlags::ENCRYPTED | VmpakFlags::ENCRYPTED_INDEX
+        }
+    }
+}
+
+/// One-shot encryption for small blocks (metadata, index): random nonce followed by the ciphertext
+pub fn seal(key: &VmpakKey, plaintext: &[u8], binding: Binding) -> io::Result<Vec<u8>> {
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = key.cipher().encrypt(&nonce, Payload { msg: plaintext, aad: &binding.aad() })
+        .map_err(|_| io::Error::other("VMPAK encryption failed"))?;
+    Ok([nonce.as_slice(), &ciphertext].concat())
+}
+
+pub fn open(key: &VmpakKey, sealed: &[u8], binding: Binding) -> io::Result<Vec<u8>> {
+    if sealed.len() < NONCE_SIZE + TAG_SIZE {
+        return Err(crypto_error("block is too short"));
+    }
+    let (nonce, ciphertext) = sealed.split_at(NONCE_SIZE);
+    key.cipher().decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad: &binding.aad() })
+        .map_err(|_| crypto_error("block was tampered with or the key is wrong"))
+}
+
+/// Encrypts an entry payload as a series of authenticated chunks (the STREAM construction),
+/// so neither side ever has to hold a whole entry in memory
+pub struct EncryptWriter<W: Write> {
+    inner: W,
+    encryptor: Option<EncryptorBE32<XChaCha20Poly1305>>,
+    buffer: Vec<u8>,
+    aad: Vec<u8>,
+}
+
+impl<W: Write> EncryptWriter<W> {
+    pub fn new(mut inner: W, key: &VmpakKey, binding: Binding) -> io::Result<Self> {
+        let mut nonce = [0u8; STREAM_NONCE_SIZE];
+        OsRng.fill_bytes(&mut nonce);
+        inner.write_all(&nonce)?;
+
+        Ok(Self {
+            inner,
+            encryptor: Some(EncryptorBE32::from_aead(key.cipher(), nonce.as_slice().into())),
+            buffer: Vec::with_capacity(CHUNK_SIZE),
+            aad: binding.aad(),
+        })
+    }
+
+    /// Seals the final chunk, without this the stream can't be decrypted
+    pub fn finish(mut self) -> io::Result<W> {
+        let encryptor = self.encryptor.take().expect("finish() is only called once");
+        let chunk = encryptor.encrypt_last(Payload { msg: &self.buffer, aad: &self.aad })
+            .map_err(|_| io::Error::other("VMPAK encryption failed"))?;
+        self.inner.write_all(&chunk)?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for EncryptWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // Only seal a full chunk once we know more data follows it, the last chunk is sealed differently
+        if self.buffer.len() == CHUNK_SIZE {
+            let encryptor = self.encryptor.as_mut().expect("write() after finish()");
+            let chunk = encryptor.encrypt_next(Payload { msg: &self.buffer, aad: &self.aad })
+                .map_err(|_| io::Error::other("VMPAK encryption failed"))?;
+            self.inner.write_all(&chunk)?;
+            self.buffer.clear();
+        }
+
+        let take = buf.len().min(CHUNK_SIZE - self.buffer.len());
+        self.buffer.extend_from_slice(&buf[..take]);
+        Ok(take)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reverse of `EncryptWriter`, `len` is the full on-disk size of the payload
+pub struct DecryptReader<R: Read> {
+    inner: R,
+    decryptor: Option<DecryptorBE32<XChaCha20Poly1305>>,
+    remaining: u64,
+    plaintext: Vec<u8>,
+    position: usize,
+    aad: Vec<u8>,
+}
+
+impl<R: Read> DecryptReader<R> {
+    pub fn new(mut inner: R, key: &VmpakKey, len: u64, binding: Binding) -> io::Result<Self> {
+        let mut nonce = [0u8; STREAM_NONCE_SIZE];
+        inner.read_exact(&mut nonce)?;
+
+        Ok(Self {
+            inner,
+            decryptor: Some(DecryptorBE32::from_aead(key.cipher(), nonce.as_slice().into())),
+            remaining: len.checked_sub(STREAM_NONCE_SIZE as u64).ok_or_else(|| crypto_error("payload is too short"))?,
+            plaintext: Vec::new(),
+            position: 0,
+            aad: binding.aad(),
+        })
+    }
+
+    fn next_chunk(&mut self) -> io::Result<()> {
+        let chunk_len = (self.remaining as usize).min(CHUNK_SIZE + TAG_SIZE);
+        let mut chunk = vec![0u8; chunk_len];
+        self.inner.read_exact(&mut chunk)?;
+        self.remaining -= chunk_len as u64;
+
+        self.plaintext = if self.remaining == 0 {
+            let decryptor = self.decryptor.take().ok_or_else(|| crypto_error("stream ended twice"))?;
+            decryptor.decrypt_last(Payload { msg: &chunk, aad: &self.aad })
+        } else {
+            let decryptor = self.decryptor.as_mut().ok_or_else(|| crypto_error("stream ended early"))?;
+            decryptor.decrypt_next(Payload { msg: &chunk, aad: &self.aad })
+        }
+        .map_err(|_| crypto_error("payload was tampered with or the key is wrong"))?;
+        self.position = 0;
+        Ok(())
+    }
+}
+
+impl<R: Read> Read for DecryptReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.position == self.plaintext.len() {
+            if self.decryptor.is_none() {
+                return Ok(0);
+            }
+            self.next_chunk()?;
+        }
+
+        let read = buf.len().min(self.plaintext.len() - self.position);
+        buf[..read].copy_from_slice(&self.plaintext[self.position..self.position + read]);
+        self.position += read;
+        Ok(read)
+    }
+}
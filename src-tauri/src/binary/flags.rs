@@ -0,0 +1,116 @@
+use std::{fmt, io};
+
+/// Typed view over `VmpakHeader.flags`.
+///
+/// Bits 0-3 are advisory, a reader that doesn't know one can still read the pack correctly.
+/// Bits 4-7 change how the pack has to be read, so an unknown one always makes the pack unreadable.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct VmpakFlags(u8);
+
+/// What to do with flag bits this build doesn't know about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[allow(dead_code)]
+pub enum UnknownFlagPolicy {
+    /// Accept unknown advisory bits, reject unknown required bits
+    #[default]
+    Lenient,
+    /// Reject any unknown bit
+    Strict,
+}
+
+#[allow(dead_code)]
+impl VmpakFlags {
+    /// The pack ships config files the user may want to edit
+    pub const HAS_CONFIGS: VmpakFlags = VmpakFlags(1 << 0);
+    /// Entry payloads are encrypted, an encryption header follows the main header
+    pub const ENCRYPTED: VmpakFlags = VmpakFlags(1 << 4);
+    /// The metadata block and index table are encrypted too, so nothing is visible without the key
+    pub const ENCRYPTED_INDEX: VmpakFlags = VmpakFlags(1 << 5);
+    /// The pack only holds the differences to another pack, see `VmpakPatchInfo`
+    pub const PATCH: VmpakFlags = VmpakFlags(1 << 6);
+
+    const ADVISORY_MASK: u8 = 0b0000_1111;
+    const KNOWN: u8 = Self::HAS_CONFIGS.0 | Self::ENCRYPTED.0 | Self::ENCRYPTED_INDEX.0 | Self::PATCH.0;
+
+    pub const fn empty() -> Self {
+        VmpakFlags(0)
+    }
+
+    pub const fn from_bits_retain(bits: u8) -> Self {
+        VmpakFlags(bits)
+    }
+
+    pub const fn bits(self) -> u8 {
+        self.0
+    }
+
+    pub const fn contains(self, other: VmpakFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn insert(&mut self, other: VmpakFlags) {
+        self.0 |= other.0;
+    }
+
+    pub fn remove(&mut self, other: VmpakFlags) {
+        self.0 &= !other.0;
+    }
+
+    pub fn set(&mut self, other: VmpakFlags, value: bool) {
+        if value {
+            self.insert(other);
+        } else {
+            self.remove(other);
+        }
+    }
+
+    pub const fn unknown_bits(self) -> u8 {
+        self.0 & !Self::KNOWN
+    }
+
+    /// Checks the unknown bits against `policy`. Accepted advisory bits are kept so a rewrite doesn't drop them
+    pub fn validate(self, policy: UnknownFlagPolicy) -> io::Result<Self> {
+        let unknown = self.unknown_bits();
+        let rejected = match policy {
+            UnknownFlagPolicy::Strict => unknown,
+            UnknownFlagPolicy::Lenient => unknown & !Self::ADVISORY_MASK,
+        };
+
+        if rejected != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("VMPAK uses unsupported flags {rejected:#010b}, a newer version of the manager is required"),
+            ));
+        }
+
+        Ok(self)
+    }
+}
+
+impl std::ops::BitOr for VmpakFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        VmpakFlags(self.0 | rhs.0)
+    }
+}
+
+impl fmt::Debug for VmpakFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names = Vec::new();
+        for (flag, name) in [
+            (Self::HAS_CONFIGS, "HAS_CONFIGS"),
+            (Self::ENCRYPTED, "ENCRYPTED"),
+            (Self::ENCRYPTED_INDEX, "ENCRYPTED_INDEX"),
+            (Self::PATCH, "PATCH"),
+        ] {
+            if self.contains(flag) {
+                names.push(name.to_string());
+            }
+        }
+        if self.unknown_bits() != 0 {
+            names.push(format!("{:#010b}", self.unknown_bits()));
+        }
+        write!(f, "VmpakFlags({})", if names.is_empty() { "empty".to_string() } else { names.join(" | ") })
+    }
+}
@@ -0,0 +1,253 @@
+use std::{
+    fs::File,
+    io::{self, Read, Seek, SeekFrom, Write},
+    time::{Duration, UNIX_EPOCH},
+};
+
+use super::{open, Binding, ChecksumReader, ChunkedReader, Compression, DecryptReader, VmpakFlags, VmpakHeader, VmpakKey, VmpakTrailer, VMPAK_MAX_INDEX_SIZE};
+
+/// File attributes preserved from wherever the entry came from
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EntryAttributes {
+    /// Last modification time in seconds since the Unix epoch, 0 if unknown
+    pub modified: u64,
+    pub executable: bool,
+}
+
+impl EntryAttributes {
+    const EXECUTABLE: u8 = 1 << 0;
+    /// Shares the attribute byte, marks the payload as a chunk table
+    const CHUNKED: u8 = 1 << 1;
+
+    /// Sets the modification time and executable bit on an extracted file
+    pub fn apply(&self, file: &File) -> io::Result<()> {
+        if self.modified != 0 {
+            file.set_modified(UNIX_EPOCH + Duration::from_secs(self.modified))?;
+        }
+
+        #[cfg(unix)]
+        if self.executable {
+            use std::os::unix::fs::PermissionsExt;
+            let mut permissions = file.metadata()?.permissions();
+            permissions.set_mode(permissions.mode() | 0o111);
+            file.set_permissions(permissions)?;
+        }
+        Ok(())
+    }
+}
+
+/// A single record in the index table, describing where a file's payload lives
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VmpakEntry {
+    /// Relative path inside the pack, always `/` separated
+    pub path: String,
+    /// Absolute offset of the payload from the start of the file
+    pub offset: u64,
+    /// Size of the payload as stored on disk
+    pub compressed_size: u64,
+    /// Size of the file once decompressed
+    pub size: u64,
+    pub compression: Compression,
+    /// CRC32 of the decompressed contents, `None` for packs older than format version 3
+    pub checksum: Option<u32>,
+    /// Default for packs older than format version 5
+    pub attributes: EntryAttributes,
+    /// The payload is a table of deduplicated chunks rather than the data itself (format version 6+)
+    pub chunked: bool,
+    /// Whether the payload is encrypted, not stored per entry but taken from the header flags
+    pub encrypted: bool,
+    /// Whether an encrypted payload is bound to its offset (format version 7+), taken from the header like `encrypted`
+    pub bound: bool,
+}
+
+impl VmpakEntry {
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let path = self.path.as_bytes();
+        let path_len = u16::try_from(path.len())
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, format!("Entry path too long: {}", self.path)))?;
+        let (method, level) = self.compression.to_raw();
+
+        writer.write_all(&path_len.to_le_bytes())?;
+        writer.write_all(path)?;
+        writer.write_all(&self.offset.to_le_bytes())?;
+        writer.write_all(&self.compressed_size.to_le_bytes())?;
+        writer.write_all(&self.size.to_le_bytes())?;
+        writer.write_all(&method.to_le_bytes())?;
+        writer.write_all(&level.to_le_bytes())?;
+        writer.write_all(&self.checksum.unwrap_or_default().to_le_bytes())?;
+        writer.write_all(&self.attributes.modified.to_le_bytes())?;
+        let mut attribute_bits = 0;
+        if self.attributes.executable {
+            attribute_bits |= EntryAttributes::EXECUTABLE;
+        }
+        if self.chunked {
+            attribute_bits |= EntryAttributes::CHUNKED;
+        }
+        writer.write_all(&[attribute_bits])?;
+        Ok(())
+    }
+
+    pub fn read<R: Read>(reader: &mut R, format_version: u16) -> io::Result<Self> {
+        let mut u16_buf = [0u8; 2];
+        reader.read_exact(&mut u16_buf)?;
+        let path_len = u16::from_le_bytes(u16_buf) as usize;
+
+        let mut path_buf = vec![0u8; path_len];
+        reader.read_exact(&mut path_buf)?;
+        let path = String::from_utf8(path_buf)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "VMPAK entry path is not valid UTF-8"))?;
+
+        let mut buffer = [0u8; 8 + 8 + 8 + 1 + 1];
+        reader.read_exact(&mut buffer)?;
+
+        let offset = u64::from_le_bytes(buffer[0..8].try_into().unwrap());
+        let compressed_size = u64::from_le_bytes(buffer[8..16].try_into().unwrap());
+        let size = u64::from_le_bytes(buffer[16..24].try_into().unwrap());
+        let compression = Compression::from_raw(buffer[24], buffer[25] as i8)?;
+
+        let checksum = if format_version >= 3 {
+            let mut crc_buf = [0u8; 4];
+            reader.read_exact(&mut crc_buf)?;
+            Some(u32::from_le_bytes(crc_buf))
+        } else {
+            None
+        };
+
+        let (attributes, chunked) = if format_version >= 5 {
+            let mut attribute_buf = [0u8; 8 + 1];
+            reader.read_exact(&mut attribute_buf)?;
+            let attributes = EntryAttributes {
+                modified: u64::from_le_bytes(attribute_buf[0..8].try_into().unwrap()),
+                executable: attribute_buf[8] & EntryAttributes::EXECUTABLE != 0,
+            };
+            (attributes, format_version >= 6 && attribute_buf[8] & EntryAttributes::CHUNKED != 0)
+        } else {
+            (EntryAttributes::default(), false)
+        };
+
+        Ok(Self {
+            path,
+            offset,
+            compressed_size,
+            size,
+            compression,
+            checksum,
+            attributes,
+            chunked,
+            encrypted: false,
+            bound: false,
+        })
+    }
+
+    /// What a payload of this entry stored at `offset` is bound to, chunks are bound by their own offset
+    pub(super) fn binding_at(&self, offset: u64) -> Binding {
+        if self.bound {
+            Binding::Payload(offset)
+        } else {
+            Binding::Unbound
+        }
+    }
+
+    /// Seeks to this entry's payload and returns a reader yielding the decompressed bytes.
+    /// Nothing is buffered beyond what the decoder needs, so large entries can be streamed straight to disk.
+    /// The size and checksum are checked as the stream ends, a mismatch surfaces as an `InvalidData` error.
+    /// `key` is only needed for encrypted payloads
+    pub fn open<'a, R: Read + Seek + 'a>(&self, mut reader: R, key: Option<&VmpakKey>) -> io::Result<Box<dyn Read + 'a>> {
+        if self.chunked {
+            let key = match (self.encrypted, key) {
+                (true, None) => {
+                    return Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("VMPAK entry {} is encrypted, a key is required", self.path)));
+                }
+                (true, key) => key,
+                (false, _) => None,
+            };
+            let chunks = ChunkedReader::new(reader, self, key)?;
+            return Ok(Box::new(ChecksumReader::new(chunks, self.path.clone(), self.size, self.checksum)));
+        }
+
+        reader.seek(SeekFrom::Start(self.offset))?;
+        let payload = reader.take(self.compressed_size);
+
+        let decoder = if self.encrypted {
+            let key = key.ok_or_else(|| {
+                io::Error::new(io::ErrorKind::PermissionDenied, format!("VMPAK entry {} is encrypted, a key is required", self.path))
+            })?;
+            self.compression.decompress(DecryptReader::new(payload, key, self.compressed_size, self.binding_at(self.offset))?)?
+        } else {
+            self.compression.decompress(payload)?
+        };
+
+        Ok(Box::new(ChecksumReader::new(decoder, self.path.clone(), self.size, self.checksum)))
+    }
+}
+
+/// Writes the index table: a `u32` entry count followed by each entry
+pub fn write_index<W: Write>(writer: &mut W, entries: &[VmpakEntry]) -> io::Result<()> {
+    let count = u32::try_from(entries.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Too many entries for a VMPAK index"))?;
+    writer.write_all(&count.to_le_bytes())?;
+    for entry in entries {
+        entry.write(writer)?;
+    }
+    Ok(())
+}
+
+pub fn read_index<R: Read>(reader: &mut R, format_version: u16) -> io::Result<Vec<VmpakEntry>> {
+    let mut count_buf = [0u8; 4];
+    reader.read_exact(&mut count_buf)?;
+    let count = u32::from_le_bytes(count_buf);
+
+    (0..count).map(|_| VmpakEntry::read(reader, format_version)).collect()
+}
+
+/// Locates, checks and parses the index table for `header`.
+///
+/// From format version 3 the index is checked against the trailer's CRC32 and every entry
+/// has to point inside the payload section, so garbage offsets are caught before anything seeks to them.
+/// `key` is only needed if the index is encrypted
+pub fn load_index<R: Read + Seek>(reader: &mut R, header: &VmpakHeader, key: Option<&VmpakKey>) -> io::Result<Vec<VmpakEntry>> {
+    if header.format_version < 3 {
+        reader.seek(SeekFrom::Start(header.index_table_offset))?;
+        return read_index(&mut reader.take(VMPAK_MAX_INDEX_SIZE), header.format_version);
+    }
+
+    let (trailer, trailer_start) = VmpakTrailer::read_from_end(reader, header.format_version)?;
+    let index_len = trailer.index_end(trailer_start)?.checked_sub(header.index_table_offset)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "VMPAK index offset points past the end of the file"))?;
+    if index_len > VMPAK_MAX_INDEX_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, format!("VMPAK index is {index_len} bytes, more than the allowed {VMPAK_MAX_INDEX_SIZE}")));
+    }
+
+    reader.seek(SeekFrom::Start(header.index_table_offset))?;
+    let mut index_bytes = Vec::new();
+    reader.take(index_len).read_to_end(&mut index_bytes)?;
+
+    let actual = crc32fast::hash(&index_bytes);
+    if actual != trailer.index_checksum {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("VMPAK index is corrupt (checksum {actual:#010x}, expected {:#010x})", trailer.index_checksum),
+        ));
+    }
+
+    if header.flags.contains(VmpakFlags::ENCRYPTED_INDEX) {
+        let key = key.ok_or_else(|| io::Error::new(io::ErrorKind::PermissionDenied, "VMPAK index is encrypted, a key is required"))?;
+        index_bytes = open(key, &index_bytes, Binding::Index.for_format(header.format_version))?;
+    }
+
+    let mut entries = read_index(&mut index_bytes.as_slice(), header.format_version)?;
+    let payload_start = header.metadata_offset() + header.metadata_size;
+    for entry in &mut entries {
+        entry.encrypted = header.flags.contains(VmpakFlags::ENCRYPTED);
+        entry.bound = entry.encrypted && header.format_version >= 7;
+        let end = entry.offset.checked_add(entry.compressed_size);
+        if entry.offset < payload_start || end.map_or(true, |end| end > header.index_table_offset) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("VMPAK entry {} points outside the payload section", entry.path),
+            ));
+        }
+    }
+
+    Ok(entries)
+}
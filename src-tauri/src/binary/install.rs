@@ -0,0 +1,243 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{self, File},
+    io::{self, BufWriter, Read, Seek},
+    path::{Component, Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::{is_asset_path, VmpakReader};
+
+/// Directory a mapping installs into. Where each one actually is comes from the game, not the pack
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default, Serialize, Deserialize, specta::Type)]
+#[serde(rename_all = "snake_case")]
+pub enum InstallRoot {
+    #[default]
+    Game,
+    Config,
+    Save,
+}
+
+/// Installs entries under `source` to `target` inside `root`.
+/// A `source` ending in `/` maps a whole directory, an empty one maps every entry of the pack
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstallMapping {
+    pub source: String,
+    #[serde(default)]
+    pub target: String,
+    #[serde(default)]
+    pub root: InstallRoot,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when: Option<InstallCondition>,
+}
+
+/// Everything listed has to hold for the mapping or component to apply
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstallCondition {
+    /// Matches if the game version is any of these. A trailing `*` matches by prefix, e.g. `1.2.*`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub game_versions: Vec<String>,
+    /// Ids of mods that have to be installed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mods_present: Vec<String>,
+    /// Ids of mods that must not be installed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mods_absent: Vec<String>,
+}
+
+/// A part of the mod the user can opt in or out of
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InstallComponent {
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Installed when the user hasn't picked components themselves
+    #[serde(default)]
+    pub default: bool,
+    pub files: Vec<InstallMapping>,
+    /// Components whose condition doesn't hold aren't offered at all
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when: Option<InstallCondition>,
+}
+
+/// Where a pack's files go, carried in the metadata. Mappings apply in order and components after the base files,
+/// so a later mapping to the same target replaces an earlier one. Entries nothing maps aren't installed.
+/// Packs without a manifest install every entry into the game directory as is
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VmpakInstallManifest {
+    #[serde(default)]
+    pub files: Vec<InstallMapping>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub components: Vec<InstallComponent>,
+}
+
+/// What the installer knows about the game and what the user picked
+#[derive(Debug, Clone, Default)]
+pub struct InstallContext {
+    pub roots: BTreeMap<InstallRoot, PathBuf>,
+    pub game_version: Option<String>,
+    pub installed_mods: BTreeSet<String>,
+    /// Ids of the components to install, `None` for each component's default
+    pub components: Option<Vec<String>>,
+}
+
+impl InstallCondition {
+    pub fn matches(&self, context: &InstallContext) -> bool {
+        let version_ok = self.game_versions.is_empty()
+            || context.game_version.as_deref().is_some_and(|version| {
+                self.game_versions.iter().any(|pattern| match pattern.strip_suffix('*') {
+                    Some(prefix) => version.starts_with(prefix),
+                    None => version == pattern,
+                })
+            });
+
+        version_ok
+            && self.mods_present.iter().all(|id| context.installed_mods.contains(id))
+            && !self.mods_absent.iter().any(|id| context.installed_mods.contains(id))
+    }
+}
+
+fn applies(condition: &Option<InstallCondition>, context: &InstallContext) -> bool {
+    condition.as_ref().map_or(true, |condition| condition.matches(context))
+}
+
+/// One file the installer will write
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PlannedFile {
+    /// Entry in the pack
+    pub entry: String,
+    pub root: InstallRoot,
+    /// Path relative to the root
+    pub target: PathBuf,
+    pub destination: PathBuf,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct InstallPlan {
+    pub files: Vec<PlannedFile>,
+    /// Components that ended up being installed
+    pub components: Vec<String>,
+}
+
+/// `/` separated relative path as a `PathBuf`, refusing anything that could climb out of its root
+fn relative_path(path: &str) -> io::Result<PathBuf> {
+    let relative = Path::new(path);
+    if path.is_empty() || relative.components().any(|c| !matches!(c, Component::Normal(_))) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Refusing to install to {path}, it points outside the install root"),
+        ));
+    }
+    Ok(relative.to_path_buf())
+}
+
+impl InstallMapping {
+    /// Where `entry` ends up relative to the root, `None` if this mapping doesn't cover it
+    fn target_for(&self, entry: &str) -> Option<String> {
+        if self.source.is_empty() || self.source.ends_with('/') {
+            let rest = entry.strip_prefix(&self.source)?;
+            return Some(match self.target.trim_end_matches('/') {
+                "" => rest.to_string(),
+                target => format!("{target}/{rest}"),
+            });
+        }
+
+        (entry == self.source).then(|| match self.target.as_str() {
+            // Empty target keeps the file name, a directory target puts it inside
+            "" => entry.rsplit('/').next().unwrap_or(entry).to_string(),
+            target if target.ends_with('/') => format!("{target}{}", entry.rsplit('/').next().unwrap_or(entry)),
+            target => target.to_string(),
+        })
+    }
+}
+
+#[allow(dead_code)]
+impl VmpakInstallManifest {
+    /// Works out which files go where for `context`. Assets under `.vmpak/` are never installed
+    pub fn plan(&self, entries: &[String], context: &InstallContext) -> io::Result<InstallPlan> {
+        let known: BTreeSet<_> = self.components.iter().map(|c| c.id.as_str()).collect();
+        if let Some(unknown) = context.components.iter().flatten().find(|id| !known.contains(id.as_str())) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Pack has no component {unknown}")));
+        }
+
+        let selected: Vec<_> = self
+            .components
+            .iter()
+            .filter(|component| match &context.components {
+                Some(ids) => ids.contains(&component.id),
+                None => component.default,
+            })
+            .filter(|component| applies(&component.when, context))
+            .collect();
+
+        let mappings = self.files.iter().chain(selected.iter().flat_map(|c| &c.files));
+        let mut targets: BTreeMap<(InstallRoot, PathBuf), usize> = BTreeMap::new();
+        let mut files: Vec<PlannedFile> = Vec::new();
+
+        for mapping in mappings.filter(|m| applies(&m.when, context)) {
+            for entry in entries.iter().filter(|e| !is_asset_path(e)) {
+                let Some(target) = mapping.target_for(entry) else {
+                    continue;
+                };
+                let target = relative_path(&target)?;
+                let root = context.roots.get(&mapping.root).ok_or_else(|| {
+                    io::Error::new(io::ErrorKind::NotFound, format!("Pack installs to the {:?} directory, which isn't known for this game", mapping.root))
+                })?;
+
+                let planned = PlannedFile { entry: entry.clone(), root: mapping.root, destination: root.join(&target), target };
+                match targets.get(&(mapping.root, planned.target.clone())) {
+                    Some(&index) => files[index] = planned,
+                    None => {
+                        targets.insert((mapping.root, planned.target.clone()), files.len());
+                        files.push(planned);
+                    }
+                }
+            }
+        }
+
+        Ok(InstallPlan { files, components: selected.iter().map(|c| c.id.clone()).collect() })
+    }
+
+    /// What packs without a manifest do: everything into the game directory
+    pub fn everything() -> Self {
+        Self {
+            files: vec![InstallMapping { source: String::new(), target: String::new(), root: InstallRoot::Game, when: None }],
+            components: Vec::new(),
+        }
+    }
+}
+
+#[allow(dead_code)]
+impl<R: Read + Seek> VmpakReader<R> {
+    /// The pack's install manifest, or the implicit "everything into the game directory" one
+    pub fn install_manifest(&self) -> VmpakInstallManifest {
+        self.metadata().install.clone().unwrap_or_else(VmpakInstallManifest::everything)
+    }
+
+    pub fn plan_install(&self, context: &InstallContext) -> io::Result<InstallPlan> {
+        let entries: Vec<_> = self.entries().iter().map(|e| e.path.clone()).collect();
+        self.install_manifest().plan(&entries, context)
+    }
+
+    /// Writes every file of `plan`, returning the paths written
+    pub fn install(&mut self, plan: &InstallPlan) -> io::Result<Vec<PathBuf>> {
+        let mut written = Vec::with_capacity(plan.files.len());
+        for file in &plan.files {
+            let attributes = self
+                .entry(&file.entry)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("VMPAK has no entry {}", file.entry)))?
+                .attributes;
+
+            if let Some(parent) = file.destination.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut output = BufWriter::new(File::create(&file.destination)?);
+            io::copy(&mut self.open_entry(&file.entry)?, &mut output)?;
+            attributes.apply(&output.into_inner().map_err(|e| e.into_error())?)?;
+            written.push(file.destination.clone());
+        }
+        Ok(written)
+    }
+}
@@ -0,0 +1,297 @@
+use std::io::{self, Read, Seek, SeekFrom, Write};
+
+use serde::Serialize;
+
+use super::{load_index, VmpakFlags, VmpakHeader, VmpakKey};
+
+/// Trailer identifier: V M P T
+const VMPAK_TRAILER_MAGIC: u32 = 0x54504D56;
+
+/// Fixed-size block at the very end of a pack (format version 3+)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VmpakTrailer {
+    /// CRC32 of the whole index table
+    pub index_checksum: u32,
+    /// Size of the signature section between the index and the trailer (format version 4+)
+    pub signatures_size: u32,
+    /// See `archive_digest`
+    pub digest: [u8; 32],
+}
+
+impl VmpakTrailer {
+    pub const SIZE: usize = 4 + 4 + 32 + 4;
+    /// Format version 3 had no signature section
+    pub const V3_SIZE: usize = 4 + 32 + 4;
+
+    pub fn encoded_len(format_version: u16) -> usize {
+        if format_version >= 4 {
+            Self::SIZE
+        } else {
+            Self::V3_SIZE
+        }
+    }
+
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.index_checksum.to_le_bytes())?;
+        writer.write_all(&self.signatures_size.to_le_bytes())?;
+        writer.write_all(&self.digest)?;
+        writer.write_all(&VMPAK_TRAILER_MAGIC.to_le_bytes())?;
+        Ok(())
+    }
+
+    pub fn read<R: Read>(reader: &mut R, format_version: u16) -> io::Result<Self> {
+        let mut buffer = [0u8; Self::SIZE];
+        let buffer = &mut buffer[..Self::encoded_len(format_version)];
+        reader.read_exact(buffer)?;
+
+        let magic = u32::from_le_bytes(buffer[buffer.len() - 4..].try_into().unwrap());
+        if magic != VMPAK_TRAILER_MAGIC {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "Missing VMPAK trailer, the file is probably truncated"));
+        }
+
+        let index_checksum = u32::from_le_bytes(buffer[0..4].try_into().unwrap());
+        let (signatures_size, digest) = if format_version >= 4 {
+            (u32::from_le_bytes(buffer[4..8].try_into().unwrap()), &buffer[8..40])
+        } else {
+            (0, &buffer[4..36])
+        };
+
+        Ok(Self {
+            index_checksum,
+            signatures_size,
+            digest: digest.try_into().unwrap(),
+        })
+    }
+
+    /// Reads the trailer at the end of the pack, returning it along with its offset
+    pub fn read_from_end<R: Read + Seek>(reader: &mut R, format_version: u16) -> io::Result<(Self, u64)> {
+        let start = reader.seek(SeekFrom::End(-(Self::encoded_len(format_version) as i64)))?;
+        Ok((Self::read(reader, format_version)?, start))
+    }
+
+    /// Offset where the index table (and the digested body) ends
+    pub fn index_end(&self, trailer_start: u64) -> io::Result<u64> {
+        trailer_start.checked_sub(self.signatures_size as u64)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "VMPAK signature section is larger than the file"))
+    }
+}
+
+/// The archive digest is `BLAKE3(header bytes || BLAKE3(body))`, where the body is everything
+/// between the header and the end of the index table. The signature section and trailer are
+/// left out so a pack can be signed after it's written. Hashing the body separately lets the writer stream it
+/// before the header (which is patched last) is known
+pub fn archive_digest(header: &VmpakHeader, body_digest: &blake3::Hash) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(&header.to_bytes());
+    hasher.update(body_digest.as_bytes());
+    *hasher.finalize().as_bytes()
+}
+
+/// Recomputes the archive digest of a pack from its contents
+pub fn compute_archive_digest<R: Read + Seek>(reader: &mut R, header: &VmpakHeader) -> io::Result<[u8; 32]> {
+    let (trailer, trailer_start) = VmpakTrailer::read_from_end(reader, header.format_version)?;
+    let body_start = header.encoded_len() as u64;
+    let body_len = trailer.index_end(trailer_start)?.checked_sub(body_start)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "VMPAK is smaller than its header"))?;
+
+    reader.seek(SeekFrom::Start(body_start))?;
+    let mut hasher = blake3::Hasher::new();
+    let copied = io::copy(&mut reader.take(body_len), &mut hasher)?;
+    if copied != body_len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "VMPAK body ended early"));
+    }
+
+    Ok(archive_digest(header, &hasher.finalize()))
+}
+
+/// Writer that hashes and counts everything passing through it
+pub(super) struct DigestWriter<W: Write> {
+    inner: W,
+    hasher: blake3::Hasher,
+    written: u64,
+}
+
+impl<W: Write> DigestWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, hasher: blake3::Hasher::new(), written: 0 }
+    }
+
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    pub fn finish(self) -> (W, blake3::Hash) {
+        (self.inner, self.hasher.finalize())
+    }
+}
+
+impl<W: Write> Write for DigestWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Reader that computes the CRC32 of everything read through it
+pub(super) struct Crc32Reader<R: Read> {
+    inner: R,
+    hasher: crc32fast::Hasher,
+}
+
+impl<R: Read> Crc32Reader<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner, hasher: crc32fast::Hasher::new() }
+    }
+
+    pub fn finish(self) -> u32 {
+        self.hasher.finalize()
+    }
+}
+
+impl<R: Read> Read for Crc32Reader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+/// Wraps an entry's decoder, failing the read as soon as the data stops matching the index
+pub struct ChecksumReader<R: Read> {
+    inner: R,
+    path: String,
+    hasher: crc32fast::Hasher,
+    read: u64,
+    expected_size: u64,
+    expected_checksum: Option<u32>,
+}
+
+impl<R: Read> ChecksumReader<R> {
+    pub fn new(inner: R, path: String, expected_size: u64, expected_checksum: Option<u32>) -> Self {
+        Self {
+            inner,
+            path,
+            hasher: crc32fast::Hasher::new(),
+            read: 0,
+            expected_size,
+            expected_checksum
+        }
+    }
+
+    fn corrupt(&self, reason: String) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, format!("VMPAK entry {} is corrupt: {reason}", self.path))
+    }
+}
+
+impl<R: Read> Read for ChecksumReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.read += read as u64;
+
+        if self.read > self.expected_size {
+            return Err(self.corrupt(format!("more than the expected {} bytes", self.expected_size)));
+        }
+
+        if read == 0 && !buf.is_empty() {
+            if self.read != self.expected_size {
+                return Err(self.corrupt(format!("{} bytes, expected {}", self.read, self.expected_size)));
+            }
+            if let Some(expected) = self.expected_checksum {
+                let actual = self.hasher.clone().finalize();
+                if actual != expected {
+                    return Err(self.corrupt(format!("checksum {actual:#010x}, expected {expected:#010x}")));
+                }
+            }
+            return Ok(0);
+        }
+
+        self.hasher.update(&buf[..read]);
+        Ok(read)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CorruptEntry {
+    pub path: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VerifyReport {
+    pub format_version: u16,
+    /// Whether the index table could be read and matched its checksum
+    pub index_ok: bool,
+    /// `None` for packs without a trailer (format version < 3)
+    pub digest_ok: Option<bool>,
+    pub entries_checked: usize,
+    /// Encrypted entries that couldn't be checked because no key was given
+    pub entries_skipped: usize,
+    pub corrupt_entries: Vec<CorruptEntry>,
+}
+
+#[allow(dead_code)]
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.index_ok && self.digest_ok != Some(false) && self.corrupt_entries.is_empty()
+    }
+}
+
+/// Checks every part of a pack, reporting exactly which entries are corrupt.
+///
+/// Only fails outright when the header itself can't be read, or the index is encrypted and there's
+/// no `key`. Everything else ends up in the report
+#[allow(dead_code)]
+pub fn verify<R: Read + Seek>(reader: &mut R, key: Option<&VmpakKey>) -> io::Result<VerifyReport> {
+    reader.seek(SeekFrom::Start(0))?;
+    let header = VmpakHeader::read(reader)?;
+
+    if header.flags.contains(VmpakFlags::ENCRYPTED_INDEX) && key.is_none() {
+        return Err(io::Error::new(io::ErrorKind::PermissionDenied, "VMPAK index is encrypted, a key is required to verify it"));
+    }
+
+    let mut report = VerifyReport {
+        format_version: header.format_version,
+        index_ok: false,
+        digest_ok: None,
+        entries_checked: 0,
+        entries_skipped: 0,
+        corrupt_entries: Vec::new(),
+    };
+
+    if header.format_version >= 3 {
+        let digest_ok = VmpakTrailer::read_from_end(reader, header.format_version)
+            .and_then(|(trailer, _)| Ok(compute_archive_digest(reader, &header)? == trailer.digest))
+            .unwrap_or(false);
+        report.digest_ok = Some(digest_ok);
+    }
+
+    let entries = match load_index(reader, &header, key) {
+        Ok(entries) => entries,
+        Err(_) => return Ok(report),
+    };
+    report.index_ok = true;
+
+    for entry in &entries {
+        if entry.encrypted && key.is_none() {
+            report.entries_skipped += 1;
+            continue;
+        }
+
+        report.entries_checked += 1;
+        let result = entry.open(&mut *reader, key).and_then(|mut data| io::copy(&mut data, &mut io::sink()));
+        if let Err(e) = result {
+            report.corrupt_entries.push(CorruptEntry {
+                path: entry.path.clone(),
+                reason: e.to_string(),
+            });
+        }
+    }
+
+    Ok(report)
+}
@@ -0,0 +1,187 @@
+use std::{collections::BTreeMap, io::{self, Read, Seek, SeekFrom}};
+
+use lib_vmm::traits::discovery::{ModExtendedMetadata, ModSummary};
+use serde::{Deserialize, Serialize};
+
+use super::{open, Binding, VmpakFlags, VmpakInstallManifest, VmpakHeader, VmpakKey, VMPAK_MAX_METADATA_SIZE};
+
+/// Bumped whenever a field changes meaning, so metadata with a newer schema is refused.
+/// New optional fields don't need a bump, older readers keep them around in `extra`
+pub const VMPAK_METADATA_SCHEMA_VERSION: u32 = 1;
+
+fn default_schema_version() -> u32 {
+    VMPAK_METADATA_SCHEMA_VERSION
+}
+
+/// Describes the mod inside a pack. Stored as JSON in the metadata block
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VmpakMetadata {
+    #[serde(default = "default_schema_version")]
+    pub schema_version: u32,
+    pub mod_id: String,
+    pub name: String,
+    pub version: String,
+    #[serde(default)]
+    pub authors: Vec<String>,
+    pub game_id: String,
+    /// Game versions this mod is known to work with, empty means "any"
+    #[serde(default)]
+    pub game_versions: Vec<String>,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub dependencies: Vec<VmpakDependency>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub homepage: Option<String>,
+    /// Only set on patch packs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub patch: Option<VmpakPatchInfo>,
+    /// Where the files go, `None` installs everything into the game directory
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub install: Option<VmpakInstallManifest>,
+    /// Fields written by a newer manager that we don't understand yet, kept so re-packing doesn't lose them
+    #[serde(flatten)]
+    pub extra: BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VmpakDependency {
+    pub mod_id: String,
+    /// Free-form requirement as the provider describes it (e.g. ">=1.2"), `None` means any version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    #[serde(default)]
+    pub optional: bool,
+}
+
+/// Describes what a patch pack applies to and what applying it has to produce
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VmpakPatchInfo {
+    /// Hex archive digest of the pack this patch was made against
+    pub base_digest: String,
+    /// Hex content digest of the base pack's files, checked when patching an installed copy
+    pub base_content_digest: String,
+    /// Hex content digest of the files after patching
+    pub target_digest: String,
+    #[serde(default)]
+    pub removed: Vec<String>,
+    /// Entries stored as a zstd diff against the base file with the same path, everything else is stored whole
+    #[serde(default)]
+    pub diffs: Vec<String>,
+}
+
+impl VmpakMetadata {
+    pub fn new(mod_id: impl Into<String>, name: impl Into<String>, version: impl Into<String>, game_id: impl Into<String>) -> Self {
+        Self {
+            schema_version: VMPAK_METADATA_SCHEMA_VERSION,
+            mod_id: mod_id.into(),
+            name: name.into(),
+            version: version.into(),
+            authors: Vec::new(),
+            game_id: game_id.into(),
+            game_versions: Vec::new(),
+            description: String::new(),
+            dependencies: Vec::new(),
+            tags: Vec::new(),
+            homepage: None,
+            patch: None,
+            install: None,
+            extra: BTreeMap::new(),
+        }
+    }
+
+    /// Fills what a mod provider knows about a mod, used for downloads that carry no metadata of their own.
+    /// Without a summary the mod id doubles as its name
+    pub fn from_provider(
+        game_id: impl Into<String>,
+        mod_id: impl Into<String>,
+        summary: Option<&ModSummary>,
+        extended: Option<&ModExtendedMetadata>,
+    ) -> Self {
+        let mod_id = mod_id.into();
+        let name = summary.map_or_else(|| mod_id.clone(), |s| s.name.clone());
+        let version = extended.map(|e| e.version.clone()).unwrap_or_default();
+        let mut metadata = Self::new(mod_id, name, version, game_id);
+
+        metadata.description = match (extended, summary) {
+            (Some(extended), _) if !extended.description.is_empty() => extended.description.clone(),
+            (_, Some(summary)) => summary.description.clone(),
+            _ => String::new(),
+        };
+        if let Some(summary) = summary {
+            if !summary.user_name.is_empty() {
+                metadata.authors.push(summary.user_name.clone());
+            }
+            metadata.tags = summary.tags.clone();
+        }
+
+        metadata
+    }
+
+    pub fn to_bytes(&self) -> io::Result<Vec<u8>> {
+        serde_json::to_vec(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    }
+
+    /// Decodes a metadata block, `format_version` decides how it's laid out
+    pub fn from_bytes(bytes: &[u8], format_version: u16) -> io::Result<Self> {
+        if format_version < 2 {
+            return Ok(Self::from_legacy(bytes));
+        }
+
+        let metadata: Self = serde_json::from_slice(bytes)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid VMPAK metadata: {e}")))?;
+        if metadata.schema_version > VMPAK_METADATA_SCHEMA_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "VMPAK metadata schema {} is newer than the supported schema {}, a newer version of the manager is required",
+                    metadata.schema_version, VMPAK_METADATA_SCHEMA_VERSION
+                ),
+            ));
+        }
+        Ok(metadata)
+    }
+
+    /// Format version 1 packs carried `"Creator: ...\nDescription: ..."`, anything else is dropped
+    fn from_legacy(bytes: &[u8]) -> Self {
+        let text = String::from_utf8_lossy(bytes);
+        let mut metadata = Self::new("", "", "", "");
+
+        for line in text.lines() {
+            match line.split_once(':') {
+                Some((key, value)) if key.trim().eq_ignore_ascii_case("creator") => {
+                    metadata.authors.push(value.trim().to_string());
+                }
+                Some((key, value)) if key.trim().eq_ignore_ascii_case("description") => {
+                    metadata.description = value.trim().to_string();
+                }
+                _ => {}
+            }
+        }
+
+        metadata
+    }
+
+    /// Reads the metadata block that sits after the header, `key` is only needed if the index is encrypted
+    pub fn read<R: Read + Seek>(reader: &mut R, header: &VmpakHeader, key: Option<&VmpakKey>) -> io::Result<Self> {
+        if header.metadata_size > VMPAK_MAX_METADATA_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "VMPAK metadata is larger than allowed"));
+        }
+        reader.seek(SeekFrom::Start(header.metadata_offset()))?;
+        // Grows with what's actually there rather than trusting the header's size up front
+        let mut buffer = Vec::new();
+        reader.take(header.metadata_size).read_to_end(&mut buffer)?;
+        if buffer.len() as u64 != header.metadata_size {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "VMPAK metadata ended early"));
+        }
+
+        if header.flags.contains(VmpakFlags::ENCRYPTED_INDEX) {
+            let key = key.ok_or_else(|| io::Error::new(io::ErrorKind::PermissionDenied, "VMPAK metadata is encrypted, a key is required"))?;
+            buffer = open(key, &buffer, Binding::Metadata.for_format(header.format_version))?;
+        }
+
+        Self::from_bytes(&buffer, header.format_version)
+    }
+}
@@ -1,4 +1,58 @@
 mod vmpak;
+mod assets;
+mod compression;
+mod convert;
+mod dedup;
+mod encryption;
+mod flags;
+mod index;
+mod install;
+mod integrity;
+mod metadata;
+mod patch;
+mod reader;
+mod signature;
+mod volume;
+mod writer;
+
+#[cfg(test)]
+mod tests;
 
 #[allow(unused_imports)]
 pub use vmpak::*;
+#[allow(unused_imports)]
+pub use assets::{is_asset_path, VmpakAssets, VMPAK_ASSETS_DIR};
+#[allow(unused_imports)]
+pub use compression::Compression;
+#[allow(unused_imports)]
+pub use convert::{convert_archive, convert_to_vmpak, convert_to_vmpak_with, extract_archive, ArchiveKind, ExtractLimits};
+#[allow(unused_imports)]
+pub use dedup::{dedup_stats, DedupStats, VmpakDedup, FILE_CHUNK_SIZE};
+use dedup::{ChunkRef, ChunkedReader, Chunker};
+#[allow(unused_imports)]
+pub use encryption::{open, seal, Binding, DecryptReader, EncryptWriter, VmpakEncryption, VmpakEncryptionHeader, VmpakKey, VmpakUnlock};
+#[allow(unused_imports)]
+pub use flags::{UnknownFlagPolicy, VmpakFlags};
+#[allow(unused_imports)]
+pub use index::{load_index, read_index, write_index, EntryAttributes, VmpakEntry};
+#[allow(unused_imports)]
+pub use install::{
+    InstallComponent, InstallCondition, InstallContext, InstallMapping, InstallPlan, InstallRoot, PlannedFile, VmpakInstallManifest,
+};
+#[allow(unused_imports)]
+pub use integrity::{
+    archive_digest, compute_archive_digest, verify, ChecksumReader, CorruptEntry, VerifyReport, VmpakTrailer,
+};
+use integrity::{Crc32Reader, DigestWriter};
+#[allow(unused_imports)]
+pub use metadata::{VmpakDependency, VmpakMetadata, VmpakPatchInfo, VMPAK_METADATA_SCHEMA_VERSION};
+#[allow(unused_imports)]
+pub use patch::{apply_patch, apply_patch_in_place, content_digest, create_patch, FileHash, PatchOptions, PatchSummary};
+#[allow(unused_imports)]
+pub use reader::{AsyncEntryReader, AsyncVmpakReader, VmpakReader};
+#[allow(unused_imports)]
+pub use signature::{check_signatures, read_signatures, sign_pack, write_signatures, SignatureStatus, VmpakSignature};
+#[allow(unused_imports)]
+pub use volume::{remove_volumes, volume_path, VolumeReader, VolumeWriter};
+#[allow(unused_imports)]
+pub use writer::VmpakWriter;
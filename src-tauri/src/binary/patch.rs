@@ -0,0 +1,484 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{self, File},
+    io::{self, BufWriter, Read},
+    path::{Component, Path, PathBuf},
+};
+
+use serde::Serialize;
+use tracing::{debug, info, warn};
+
+use super::{
+    Compression, VmpakFlags, VmpakHeader, VmpakMetadata, VmpakPatchInfo, VmpakReader, VmpakTrailer, VmpakWriter, VolumeReader,
+};
+
+/// Files bigger than this are always shipped whole, diffing needs both versions in memory
+#[allow(dead_code)]
+pub const MAX_DIFF_SIZE: u64 = 256 * 1024 * 1024;
+/// Covers a base and target of `MAX_DIFF_SIZE` each
+const DIFF_WINDOW_LOG: u32 = 29;
+const DIFF_LEVEL: i32 = 19;
+/// Scratch directory inside the mod directory while patching in place
+const STAGING_DIR: &str = ".vmpak-patch";
+/// Originals replaced or removed by an in-place patch, kept until every file has been swapped
+const BACKUP_DIR: &str = ".vmpak-patch-backup";
+
+/// Size and BLAKE3 hash of a file's contents
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileHash {
+    pub size: u64,
+    pub hash: [u8; 32],
+}
+
+/// BLAKE3 over every `(path, size, hash)` in path order. Unlike the archive digest it only depends on
+/// the files themselves, so it matches for a rebuilt pack or an extracted copy of the same files
+#[allow(dead_code)]
+pub fn content_digest(files: &BTreeMap<String, FileHash>) -> [u8; 32] {
+    let mut hasher = blake3::Hasher::new();
+    for (path, file) in files {
+        hasher.update(&(path.len() as u64).to_le_bytes());
+        hasher.update(path.as_bytes());
+        hasher.update(&file.size.to_le_bytes());
+        hasher.update(&file.hash);
+    }
+    *hasher.finalize().as_bytes()
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Copy)]
+pub struct PatchOptions {
+    /// Store changed files as binary diffs where both versions fit under `MAX_DIFF_SIZE`
+    pub binary_diffs: bool,
+    pub compression: Compression,
+}
+
+impl Default for PatchOptions {
+    fn default() -> Self {
+        Self { binary_diffs: true, compression: Compression::default() }
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct PatchSummary {
+    pub added: usize,
+    pub changed: usize,
+    /// How many of the changed entries were stored as diffs
+    pub diffed: usize,
+    pub removed: usize,
+    pub unchanged: usize,
+}
+
+/// Reader that hashes everything passing through it
+struct HashReader<R: Read> {
+    inner: R,
+    hasher: blake3::Hasher,
+    size: u64,
+}
+
+impl<R: Read> HashReader<R> {
+    fn new(inner: R) -> Self {
+        Self { inner, hasher: blake3::Hasher::new(), size: 0 }
+    }
+
+    fn finish(self) -> FileHash {
+        FileHash { size: self.size, hash: *self.hasher.finalize().as_bytes() }
+    }
+}
+
+impl<R: Read> Read for HashReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.hasher.update(&buf[..read]);
+        self.size += read as u64;
+        Ok(read)
+    }
+}
+
+fn hash_reader<R: Read>(reader: R) -> io::Result<FileHash> {
+    let mut reader = HashReader::new(reader);
+    io::copy(&mut reader, &mut io::sink())?;
+    Ok(reader.finish())
+}
+
+/// Archive digest recorded in a pack's trailer
+fn pack_digest(path: &Path) -> io::Result<[u8; 32]> {
+    let mut file = VolumeReader::open(path)?;
+    let header = VmpakHeader::read(&mut file)?;
+    if header.format_version < 3 {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{} predates archive digests (format version {}), it can't be patched", path.display(), header.format_version),
+        ));
+    }
+    Ok(VmpakTrailer::read_from_end(&mut file, header.format_version)?.0.digest)
+}
+
+fn open_plain(path: &Path) -> io::Result<VmpakReader> {
+    let reader = VmpakReader::open(path)?;
+    if reader.header().flags.contains(VmpakFlags::ENCRYPTED) {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, format!("{} is encrypted, patches only work on plain packs", path.display())));
+    }
+    Ok(reader)
+}
+
+fn hash_entries(reader: &mut VmpakReader) -> io::Result<BTreeMap<String, FileHash>> {
+    let paths: Vec<_> = reader.entries().iter().map(|e| e.path.clone()).collect();
+    paths.into_iter()
+        .map(|path| Ok((path.clone(), hash_reader(reader.open_entry(&path)?)?)))
+        .collect()
+}
+
+fn read_entry(reader: &mut VmpakReader, path: &str) -> io::Result<Vec<u8>> {
+    let mut buffer = Vec::new();
+    reader.open_entry(path)?.read_to_end(&mut buffer)?;
+    Ok(buffer)
+}
+
+fn encode_diff(base: &[u8], target: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = zstd::Encoder::with_ref_prefix(Vec::new(), DIFF_LEVEL, base)?;
+    encoder.window_log(DIFF_WINDOW_LOG)?;
+    encoder.long_distance_matching(true)?;
+    io::copy(&mut &target[..], &mut encoder)?;
+    encoder.finish()
+}
+
+fn decode_diff<'a, R: Read + 'a>(base: &'a [u8], diff: R) -> io::Result<impl Read + 'a> {
+    let mut decoder = zstd::Decoder::with_ref_prefix(io::BufReader::new(diff), base)?;
+    decoder.window_log_max(DIFF_WINDOW_LOG)?;
+    Ok(decoder)
+}
+
+fn parse_digest(hex_digest: &str) -> io::Result<[u8; 32]> {
+    hex::decode(hex_digest)
+        .ok()
+        .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid digest in patch metadata: {hex_digest}")))
+}
+
+fn read_patch_info(reader: &VmpakReader) -> io::Result<VmpakPatchInfo> {
+    match &reader.metadata().patch {
+        Some(info) if reader.header().flags.contains(VmpakFlags::PATCH) => Ok(info.clone()),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "Not a VMPAK patch")),
+    }
+}
+
+/// Writes a patch to `output` that turns the pack at `base` into the pack at `target`
+#[allow(dead_code)]
+pub fn create_patch(base: &Path, target: &Path, output: &Path, options: &PatchOptions) -> io::Result<PatchSummary> {
+    let base_digest = pack_digest(base)?;
+    let mut base_reader = open_plain(base)?;
+    let mut target_reader = open_plain(target)?;
+
+    let base_files = hash_entries(&mut base_reader)?;
+    let target_files = hash_entries(&mut target_reader)?;
+    let mut summary = PatchSummary::default();
+
+    let target_entries = target_reader.entries().to_vec();
+    let mut changed = Vec::new();
+    let mut diffs = Vec::new();
+    for entry in &target_entries {
+        match base_files.get(&entry.path) {
+            Some(base_file) if *base_file == target_files[&entry.path] => summary.unchanged += 1,
+            Some(base_file) => {
+                summary.changed += 1;
+                if options.binary_diffs && base_file.size <= MAX_DIFF_SIZE && entry.size <= MAX_DIFF_SIZE {
+                    diffs.push(entry.path.clone());
+                }
+                changed.push(entry);
+            }
+            None => {
+                summary.added += 1;
+                changed.push(entry);
+            }
+        }
+    }
+    let removed: Vec<_> = base_files.keys().filter(|path| !target_files.contains_key(*path)).cloned().collect();
+    summary.removed = removed.len();
+    summary.diffed = diffs.len();
+
+    let mut metadata = target_reader.metadata().clone();
+    metadata.patch = Some(VmpakPatchInfo {
+        base_digest: hex::encode(base_digest),
+        base_content_digest: hex::encode(content_digest(&base_files)),
+        target_digest: hex::encode(content_digest(&target_files)),
+        removed,
+        diffs: diffs.clone(),
+    });
+    let mut flags = target_reader.header().flags;
+    flags.insert(VmpakFlags::PATCH);
+
+    let mut writer = VmpakWriter::new(BufWriter::new(File::create(output)?), &metadata, flags)?;
+    writer.set_compression(options.compression);
+    for entry in changed {
+        if diffs.contains(&entry.path) {
+            let diff = encode_diff(&read_entry(&mut base_reader, &entry.path)?, &read_entry(&mut target_reader, &entry.path)?)?;
+            debug!("{} diffed to {} bytes (was {})", entry.path, diff.len(), entry.size);
+            writer.add_entry_with_attributes(&entry.path, &mut &diff[..], Some(Compression::Stored), entry.attributes)?;
+        } else {
+            writer.add_entry_with_attributes(&entry.path, &mut target_reader.open_entry(&entry.path)?, None, entry.attributes)?;
+        }
+    }
+    writer.finish()?;
+
+    info!("Created patch {} ({:?})", output.display(), summary);
+    Ok(summary)
+}
+
+/// Applies the patch at `patch` to the pack at `base`, writing the full new pack to `output`.
+/// The output is removed again if its contents don't match the patch's target digest
+#[allow(dead_code)]
+pub fn apply_patch(base: &Path, patch: &Path, output: &Path) -> io::Result<VmpakMetadata> {
+    let mut patch_reader = open_plain(patch)?;
+    let info = read_patch_info(&patch_reader)?;
+    if hex::encode(pack_digest(base)?) != info.base_digest {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} isn't the pack this patch was made for", base.display()),
+        ));
+    }
+    let mut base_reader = open_plain(base)?;
+
+    let mut metadata = patch_reader.metadata().clone();
+    metadata.patch = None;
+    let mut flags = patch_reader.header().flags;
+    flags.remove(VmpakFlags::PATCH);
+
+    let result = (|| {
+        let mut writer = VmpakWriter::new(BufWriter::new(File::create(output)?), &metadata, flags)?;
+        let mut files = BTreeMap::new();
+        let patched: BTreeSet<_> = patch_reader.entries().iter().map(|e| e.path.clone()).collect();
+
+        for entry in base_reader.entries().to_vec() {
+            if patched.contains(&entry.path) || info.removed.contains(&entry.path) {
+                continue;
+            }
+            let mut source = HashReader::new(base_reader.open_entry(&entry.path)?);
+            writer.add_entry_with_attributes(&entry.path, &mut source, Some(entry.compression), entry.attributes)?;
+            files.insert(entry.path, source.finish());
+        }
+
+        for entry in patch_reader.entries().to_vec() {
+            let hash = if info.diffs.contains(&entry.path) {
+                let base_contents = read_entry(&mut base_reader, &entry.path)?;
+                let mut source = HashReader::new(decode_diff(&base_contents, patch_reader.open_entry(&entry.path)?)?);
+                writer.add_entry_with_attributes(&entry.path, &mut source, None, entry.attributes)?;
+                source.finish()
+            } else {
+                let mut source = HashReader::new(patch_reader.open_entry(&entry.path)?);
+                writer.add_entry_with_attributes(&entry.path, &mut source, None, entry.attributes)?;
+                source.finish()
+            };
+            files.insert(entry.path, hash);
+        }
+        writer.finish()?;
+
+        check_target_digest(&files, &info)
+    })();
+
+    if result.is_err() {
+        let _ = fs::remove_file(output);
+    }
+    result.map(|_| metadata)
+}
+
+fn check_target_digest(files: &BTreeMap<String, FileHash>, info: &VmpakPatchInfo) -> io::Result<()> {
+    if content_digest(files) != parse_digest(&info.target_digest)? {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "Patched files don't match the patch's target digest"));
+    }
+    Ok(())
+}
+
+/// Turns a `/` separated entry path into a path under `root`, refusing anything that would land outside it
+fn path_under(root: &Path, entry_path: &str) -> io::Result<PathBuf> {
+    let relative = Path::new(entry_path);
+    if entry_path.is_empty() || relative.components().any(|c| !matches!(c, Component::Normal(_))) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Patch entry {entry_path} points outside the mod directory"),
+        ));
+    }
+    Ok(root.join(relative))
+}
+
+/// Hashes every regular file under `dir` by `/` separated relative path
+fn hash_dir(dir: &Path, relative: &str, files: &mut BTreeMap<String, FileHash>) -> io::Result<()> {
+    for child in fs::read_dir(dir)? {
+        let child = child?;
+        let name = child.file_name().to_string_lossy().into_owned();
+        let path = if relative.is_empty() { name } else { format!("{relative}/{name}") };
+        let file_type = child.file_type()?;
+
+        if file_type.is_dir() && path != STAGING_DIR && path != BACKUP_DIR {
+            hash_dir(&child.path(), &path, files)?;
+        } else if file_type.is_file() {
+            files.insert(path, hash_reader(File::open(child.path())?)?);
+        }
+    }
+    Ok(())
+}
+
+/// Applies the patch at `patch` to an extracted copy of its base pack in `mod_dir`.
+/// `mod_dir` must hold exactly the base pack's files. Everything is staged and checked against the
+/// target digest first, so a failed patch leaves the directory as it was
+#[allow(dead_code)]
+pub fn apply_patch_in_place(patch: &Path, mod_dir: &Path) -> io::Result<()> {
+    let mut patch_reader = open_plain(patch)?;
+    let info = read_patch_info(&patch_reader)?;
+
+    let backup = mod_dir.join(BACKUP_DIR);
+    if backup.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("An interrupted patch left the original files in {}, restore them first", backup.display()),
+        ));
+    }
+    // Only ever holds our own output, a leftover from an interrupted run is stale
+    let staging = mod_dir.join(STAGING_DIR);
+    if staging.exists() {
+        fs::remove_dir_all(&staging)?;
+    }
+
+    let mut files = BTreeMap::new();
+    hash_dir(mod_dir, "", &mut files)?;
+    if content_digest(&files) != parse_digest(&info.base_content_digest)? {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} doesn't match the files this patch was made for", mod_dir.display()),
+        ));
+    }
+
+    let result = (|| {
+        for removed in &info.removed {
+            files.remove(removed);
+        }
+
+        for entry in patch_reader.entries().to_vec() {
+            let staged = path_under(&staging, &entry.path)?;
+            if let Some(parent) = staged.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut output = BufWriter::new(File::create(&staged)?);
+
+            let hash = if info.diffs.contains(&entry.path) {
+                let base_contents = fs::read(path_under(mod_dir, &entry.path)?)?;
+                let mut source = HashReader::new(decode_diff(&base_contents, patch_reader.open_entry(&entry.path)?)?);
+                io::copy(&mut source, &mut output)?;
+                source.finish()
+            } else {
+                let mut source = HashReader::new(patch_reader.open_entry(&entry.path)?);
+                io::copy(&mut source, &mut output)?;
+                source.finish()
+            };
+            entry.attributes.apply(&output.into_inner().map_err(|e| e.into_error())?)?;
+            files.insert(entry.path, hash);
+        }
+
+        check_target_digest(&files, &info)
+    })();
+
+    if let Err(e) = result {
+        let _ = fs::remove_dir_all(&staging);
+        return Err(e);
+    }
+
+    let mut swap = Swap::new(mod_dir, &backup);
+    let swapped = (|| {
+        // Removals first, a new entry can live where a removed file was a directory
+        for removed in &info.removed {
+            swap.set_aside(removed)?;
+        }
+        for entry in patch_reader.entries() {
+            swap.replace(&entry.path, &path_under(&staging, &entry.path)?)?;
+        }
+        Ok(())
+    })();
+    if let Err(e) = swapped {
+        swap.roll_back();
+        let _ = fs::remove_dir_all(&staging);
+        return Err(e);
+    }
+    for dir in [&staging, &backup] {
+        match fs::remove_dir_all(dir) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+    }
+
+    info!("Patched {} in place", mod_dir.display());
+    Ok(())
+}
+
+/// Swaps patched files into a mod directory, moving each original aside first so the directory can be put back
+pub(super) struct Swap<'a> {
+    mod_dir: &'a Path,
+    backup: &'a Path,
+    /// Entry paths in the order they were swapped, with where their original was moved if there was one
+    moved: Vec<(String, Option<PathBuf>)>,
+}
+
+impl<'a> Swap<'a> {
+    pub(super) fn new(mod_dir: &'a Path, backup: &'a Path) -> Self {
+        Self { mod_dir, backup, moved: Vec::new() }
+    }
+
+    /// Moves the original of `entry_path`, if there is one, into the backup directory.
+    /// Originals are numbered there, one entry's path can be another one's directory
+    pub(super) fn set_aside(&mut self, entry_path: &str) -> io::Result<()> {
+        let target = path_under(self.mod_dir, entry_path)?;
+        let aside = self.backup.join(self.moved.len().to_string());
+        fs::create_dir_all(self.backup)?;
+        let aside = match fs::rename(&target, &aside) {
+            Ok(()) => Some(aside),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e),
+        };
+        self.moved.push((entry_path.to_string(), aside));
+        Ok(())
+    }
+
+    pub(super) fn replace(&mut self, entry_path: &str, staged: &Path) -> io::Result<()> {
+        self.set_aside(entry_path)?;
+        let target = path_under(self.mod_dir, entry_path)?;
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::rename(staged, target)
+    }
+
+    /// Puts every original back, newest first. Failures are only logged so as many files as possible come back,
+    /// the backup directory is kept if anything couldn't be restored
+    pub(super) fn roll_back(self) {
+        let mut restored = true;
+        for (entry_path, aside) in self.moved.iter().rev() {
+            let Ok(target) = path_under(self.mod_dir, entry_path) else {
+                continue;
+            };
+            if let Err(e) = fs::remove_file(&target) {
+                if e.kind() != io::ErrorKind::NotFound {
+                    warn!("Failed to remove patched {}: {}", target.display(), e);
+                }
+            }
+            // Directories the patch created for its own files
+            let mut parent = target.parent();
+            while let Some(dir) = parent.filter(|dir| *dir != self.mod_dir && fs::remove_dir(dir).is_ok()) {
+                parent = dir.parent();
+            }
+            if let Some(aside) = aside {
+                if let Err(e) = target.parent().map_or(Ok(()), fs::create_dir_all).and_then(|()| fs::rename(aside, &target)) {
+                    warn!("Failed to restore {} from {}: {}", target.display(), aside.display(), e);
+                    restored = false;
+                }
+            }
+        }
+        if !restored {
+            return;
+        }
+        if let Err(e) = fs::remove_dir_all(self.backup) {
+            if e.kind() != io::ErrorKind::NotFound {
+                warn!("Failed to remove {}: {}", self.backup.display(), e);
+            }
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, Cursor, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use memmap2::Mmap;
+use tokio::{io::{AsyncRead, ReadBuf}, sync::mpsc};
+
+use super::{
+    load_index, VmpakEncryptionHeader, VmpakEntry, VmpakHeader, VmpakKey, VmpakMetadata, VmpakUnlock,
+    VolumeReader,
+};
+
+/// Chunk size used when pumping an entry into an async stream
+const ASYNC_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Everything about a pack except its payloads, shared by the sync and async readers
+#[derive(Debug, Clone)]
+struct VmpakContents {
+    header: VmpakHeader,
+    metadata: VmpakMetadata,
+    entries: Vec<VmpakEntry>,
+    lookup: HashMap<String, usize>,
+    key: Option<VmpakKey>,
+}
+
+impl VmpakContents {
+    fn load<R: Read + Seek>(source: &mut R, unlock: Option<&VmpakUnlock>) -> io::Result<Self> {
+        source.seek(SeekFrom::Start(0))?;
+        let header = VmpakHeader::read(source)?;
+
+        let key = match (VmpakEncryptionHeader::read_for(source, &header)?, unlock) {
+            (Some(encryption), Some(unlock)) => Some(encryption.unlock(unlock)?),
+            _ => None,
+        };
+
+        let metadata = VmpakMetadata::read(source, &header, key.as_ref())?;
+        let entries = load_index(source, &header, key.as_ref())?;
+        let lookup = entries.iter().enumerate().map(|(i, e)| (e.path.clone(), i)).collect();
+
+        Ok(Self { header, metadata, entries, lookup, key })
+    }
+
+    fn entry(&self, path: &str) -> io::Result<&VmpakEntry> {
+        self.lookup
+            .get(path)
+            .map(|&i| &self.entries[i])
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("No entry {path} in VMPAK")))
+    }
+}
+
+/// Random-access reader over a pack. Opening only reads the header, metadata and index,
+/// payloads are streamed on demand through `open_entry`
+pub struct VmpakReader<R: Read + Seek = VolumeReader> {
+    source: R,
+    contents: VmpakContents,
+}
+
+#[allow(dead_code)]
+impl VmpakReader<VolumeReader> {
+    /// Opens the pack at `path`, along with any volumes it was split into
+    pub fn open(path: &Path) -> io::Result<Self> {
+        Self::new(VolumeReader::open(path)?, None)
+    }
+
+    pub fn open_with_unlock(path: &Path, unlock: &VmpakUnlock) -> io::Result<Self> {
+        Self::new(VolumeReader::open(path)?, Some(unlock))
+    }
+}
+
+#[allow(dead_code)]
+impl VmpakReader<Cursor<Mmap>> {
+    /// Memory maps the pack instead of going through `read` calls, much faster for random access into large packs.
+    /// Entries can be read concurrently with `open_entry_shared`
+    pub fn open_mmap(path: &Path, unlock: Option<&VmpakUnlock>) -> io::Result<Self> {
+        if VolumeReader::open(path)?.volume_count() > 1 {
+            return Err(io::Error::new(io::ErrorKind::Unsupported, "Split VMPAKs can't be memory mapped"));
+        }
+        let file = File::open(path)?;
+        // SAFETY: the map is read-only. If another process truncates the pack while it's mapped we'll
+        // fault, which is the same trade-off every mmap-based archive reader makes
+        let map = unsafe { Mmap::map(&file)? };
+        Self::new(Cursor::new(map), unlock)
+    }
+
+    /// Opens an entry without borrowing the reader mutably
+    pub fn open_entry_shared(&self, path: &str) -> io::Result<Box<dyn Read + '_>> {
+        let entry = self.contents.entry(path)?;
+        entry.open(Cursor::new(self.source.get_ref().as_ref()), self.contents.key.as_ref())
+    }
+}
+
+#[allow(dead_code)]
+impl<R: Read + Seek> VmpakReader<R> {
+    pub fn new(mut source: R, unlock: Option<&VmpakUnlock>) -> io::Result<Self> {
+        let contents = VmpakContents::load(&mut source, unlock)?;
+        Ok(Self { source, contents })
+    }
+
+    pub fn header(&self) -> &VmpakHeader {
+        &self.contents.header
+    }
+
+    pub fn metadata(&self) -> &VmpakMetadata {
+        &self.contents.metadata
+    }
+
+    pub fn entries(&self) -> &[VmpakEntry] {
+        &self.contents.entries
+    }
+
+    pub fn entry(&self, path: &str) -> Option<&VmpakEntry> {
+        self.contents.entry(path).ok()
+    }
+
+    /// Streams the decompressed (and decrypted) contents of `path`
+    pub fn open_entry(&mut self, path: &str) -> io::Result<Box<dyn Read + '_>> {
+        let entry = self.contents.entry(path)?.clone();
+        entry.open(&mut self.source, self.contents.key.as_ref())
+    }
+
+    pub fn into_inner(self) -> R {
+        self.source
+    }
+}
+
+/// Async counterpart of `VmpakReader`. Decoding is CPU and disk bound so it runs on the
+/// blocking pool, entries come back as `AsyncRead` streams that never block a runtime worker
+#[derive(Debug, Clone)]
+pub struct AsyncVmpakReader {
+    path: PathBuf,
+    contents: VmpakContents,
+}
+
+#[allow(dead_code)]
+impl AsyncVmpakReader {
+    pub async fn open(path: PathBuf, unlock: Option<VmpakUnlock>) -> io::Result<Self> {
+        let load_path = path.clone();
+        let contents = tokio::task::spawn_blocking(move || {
+            let mut volumes = VolumeReader::open(&load_path)?;
+            VmpakContents::load(&mut volumes, unlock.as_ref())
+        })
+        .await
+        .map_err(io::Error::other)??;
+
+        Ok(Self { path, contents })
+    }
+
+    pub fn header(&self) -> &VmpakHeader {
+        &self.contents.header
+    }
+
+    pub fn metadata(&self) -> &VmpakMetadata {
+        &self.contents.metadata
+    }
+
+    pub fn entries(&self) -> &[VmpakEntry] {
+        &self.contents.entries
+    }
+
+    /// Each call opens its own file handles, so any number of entries can be streamed at once
+    pub fn open_entry(&self, path: &str) -> io::Result<AsyncEntryReader> {
+        let entry = self.contents.entry(path)?.clone();
+        let key = self.contents.key.clone();
+        let pack_path = self.path.clone();
+        let (tx, rx) = mpsc::channel(4);
+
+        tokio::task::spawn_blocking(move || {
+            let result = VolumeReader::open(&pack_path).and_then(|mut volumes| {
+                let mut reader = entry.open(&mut volumes, key.as_ref())?;
+                loop {
+                    let mut chunk = vec![0u8; ASYNC_CHUNK_SIZE];
+                    let read = reader.read(&mut chunk)?;
+                    if read == 0 {
+                        return Ok(());
+                    }
+                    chunk.truncate(read);
+                    if tx.blocking_send(Ok(chunk)).is_err() {
+                        // Reader was dropped, nobody wants the rest
+                        return Ok(());
+                    }
+                }
+            });
+
+            if let Err(e) = result {
+                let _ = tx.blocking_send(Err(e));
+            }
+        });
+
+        Ok(AsyncEntryReader { rx, chunk: Vec::new(), position: 0 })
+    }
+}
+
+/// An entry being decoded on the blocking pool. Corruption surfaces as a read error, never as an early EOF
+pub struct AsyncEntryReader {
+    rx: mpsc::Receiver<io::Result<Vec<u8>>>,
+    chunk: Vec<u8>,
+    position: usize,
+}
+
+impl AsyncRead for AsyncEntryReader {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        while self.position == self.chunk.len() {
+            match self.rx.poll_recv(cx) {
+                Poll::Ready(Some(Ok(chunk))) => {
+                    self.chunk = chunk;
+                    self.position = 0;
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(e)),
+                Poll::Ready(None) => return Poll::Ready(Ok(())),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+
+        let read = buf.remaining().min(self.chunk.len() - self.position);
+        let position = self.position;
+        buf.put_slice(&self.chunk[position..position + read]);
+        self.position += read;
+        Poll::Ready(Ok(()))
+    }
+}
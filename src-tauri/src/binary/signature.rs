@@ -0,0 +1,155 @@
+use std::{fs::File, io::{self, Read, Seek, SeekFrom, Write}};
+
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+
+use super::{compute_archive_digest, VmpakHeader, VmpakTrailer};
+
+/// Domain separation so a VMPAK signature can't be replayed as a signature over anything else
+const SIGNATURE_CONTEXT: &[u8] = b"VMPAK-SIGNATURE-V1";
+
+/// An Ed25519 signature over a pack's archive digest
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VmpakSignature {
+    pub public_key: [u8; 32],
+    pub signature: [u8; 64],
+}
+
+/// Result of checking a pack's signatures against a set of trusted keys
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureStatus {
+    Unsigned,
+    /// Signed by this trusted public key
+    Trusted([u8; 32]),
+    /// Validly signed, but by nobody we trust
+    Untrusted,
+    /// A signature doesn't match, or the contents don't match the signed digest
+    Invalid(String),
+}
+
+fn signed_message(digest: &[u8; 32]) -> Vec<u8> {
+    [SIGNATURE_CONTEXT, digest.as_slice()].concat()
+}
+
+impl VmpakSignature {
+    pub const SIZE: usize = 32 + 64;
+
+    pub fn sign(key: &SigningKey, digest: &[u8; 32]) -> Self {
+        Self {
+            public_key: key.verifying_key().to_bytes(),
+            signature: key.sign(&signed_message(digest)).to_bytes(),
+        }
+    }
+
+    pub fn verify(&self, digest: &[u8; 32]) -> bool {
+        let Ok(key) = VerifyingKey::from_bytes(&self.public_key) else {
+            return false;
+        };
+        key.verify(&signed_message(digest), &Signature::from_bytes(&self.signature)).is_ok()
+    }
+}
+
+/// Signature section: a `u16` count followed by each (public key, signature) pair
+pub fn write_signatures<W: Write>(writer: &mut W, signatures: &[VmpakSignature]) -> io::Result<u32> {
+    if signatures.is_empty() {
+        return Ok(0);
+    }
+
+    let count = u16::try_from(signatures.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "Too many VMPAK signatures"))?;
+    writer.write_all(&count.to_le_bytes())?;
+    for signature in signatures {
+        writer.write_all(&signature.public_key)?;
+        writer.write_all(&signature.signature)?;
+    }
+
+    Ok((2 + signatures.len() * VmpakSignature::SIZE) as u32)
+}
+
+pub fn read_signatures<R: Read + Seek>(reader: &mut R, header: &VmpakHeader) -> io::Result<Vec<VmpakSignature>> {
+    if header.format_version < 4 {
+        return Ok(Vec::new());
+    }
+
+    let (trailer, trailer_start) = VmpakTrailer::read_from_end(reader, header.format_version)?;
+    if trailer.signatures_size == 0 {
+        return Ok(Vec::new());
+    }
+
+    reader.seek(SeekFrom::Start(trailer.index_end(trailer_start)?))?;
+    let mut count_buf = [0u8; 2];
+    reader.read_exact(&mut count_buf)?;
+    let count = u16::from_le_bytes(count_buf) as usize;
+
+    if 2 + count * VmpakSignature::SIZE != trailer.signatures_size as usize {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "VMPAK signature section size doesn't match its count"));
+    }
+
+    (0..count)
+        .map(|_| {
+            let mut buffer = [0u8; VmpakSignature::SIZE];
+            reader.read_exact(&mut buffer)?;
+            Ok(VmpakSignature {
+                public_key: buffer[..32].try_into().unwrap(),
+                signature: buffer[32..].try_into().unwrap(),
+            })
+        })
+        .collect()
+}
+
+/// Checks the pack's contents against its digest, then its signatures against `trusted_keys`
+pub fn check_signatures<R: Read + Seek>(reader: &mut R, trusted_keys: &[[u8; 32]]) -> io::Result<SignatureStatus> {
+    reader.seek(SeekFrom::Start(0))?;
+    let header = VmpakHeader::read(reader)?;
+    let signatures = read_signatures(reader, &header)?;
+    if signatures.is_empty() {
+        return Ok(SignatureStatus::Unsigned);
+    }
+
+    let (trailer, _) = VmpakTrailer::read_from_end(reader, header.format_version)?;
+    if compute_archive_digest(reader, &header)? != trailer.digest {
+        return Ok(SignatureStatus::Invalid("contents don't match the signed digest".into()));
+    }
+
+    let mut trusted = None;
+    for signature in &signatures {
+        if !signature.verify(&trailer.digest) {
+            return Ok(SignatureStatus::Invalid(format!(
+                "signature by {} doesn't match",
+                hex::encode(signature.public_key)
+            )));
+        }
+        if trusted.is_none() && trusted_keys.contains(&signature.public_key) {
+            trusted = Some(signature.public_key);
+        }
+    }
+
+    Ok(trusted.map_or(SignatureStatus::Untrusted, SignatureStatus::Trusted))
+}
+
+/// Adds a signature to an already written pack, keeping any existing ones
+#[allow(dead_code)]
+pub fn sign_pack(file: &mut File, key: &SigningKey) -> io::Result<()> {
+    file.seek(SeekFrom::Start(0))?;
+    let header = VmpakHeader::read(file)?;
+    if header.format_version < 4 {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("VMPAK format version {} can't hold signatures, repack it first", header.format_version),
+        ));
+    }
+
+    let mut signatures = read_signatures(file, &header)?;
+    let (mut trailer, trailer_start) = VmpakTrailer::read_from_end(file, header.format_version)?;
+    let index_end = trailer.index_end(trailer_start)?;
+
+    let signature = VmpakSignature::sign(key, &trailer.digest);
+    signatures.retain(|s| s.public_key != signature.public_key);
+    signatures.push(signature);
+
+    file.seek(SeekFrom::Start(index_end))?;
+    trailer.signatures_size = write_signatures(file, &signatures)?;
+    trailer.write(file)?;
+    let end = file.stream_position()?;
+    file.set_len(end)?;
+    file.flush()
+}
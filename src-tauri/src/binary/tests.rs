@@ -0,0 +1,1087 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use proptest::prelude::*;
+
+use super::*;
+
+/// Scratch directory for one test, removed again when dropped
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("vmpak-test-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    fn join(&self, name: &str) -> PathBuf {
+        self.0.join(name)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Turns runs of zeroes into holes so multi-gigabyte packs barely touch the disk
+struct SparseFile {
+    file: File,
+    len: u64,
+}
+
+impl SparseFile {
+    fn finish(self) -> io::Result<File> {
+        self.file.set_len(self.len)?;
+        Ok(self.file)
+    }
+}
+
+impl Write for SparseFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.iter().all(|&b| b == 0) {
+            self.file.seek(SeekFrom::Current(buf.len() as i64))?;
+        } else {
+            self.file.write_all(buf)?;
+        }
+        self.len = self.len.max(self.file.stream_position()?);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+impl Seek for SparseFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.file.seek(pos)
+    }
+}
+
+fn metadata() -> VmpakMetadata {
+    VmpakMetadata::new("test-mod", "Test mod", "1.0.0", "test-game")
+}
+
+/// Anything that isn't all zeroes and doesn't compress well
+fn noise(len: usize, seed: u64) -> Vec<u8> {
+    let mut state = seed;
+    (0..len)
+        .map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state as u8
+        })
+        .collect()
+}
+
+#[test]
+fn entries_keep_their_own_compression() {
+    let text = "compresses well ".repeat(4096);
+    let mut writer = VmpakWriter::new(io::Cursor::new(Vec::new()), &metadata(), VmpakFlags::empty()).unwrap();
+    writer.set_dedup(VmpakDedup::Off);
+    writer.add_entry_with("stored.txt", &mut text.as_bytes(), Compression::Stored).unwrap();
+    writer.add_entry_with("deflate.txt", &mut text.as_bytes(), Compression::Deflate(6)).unwrap();
+    writer.add_entry_with("zstd.txt", &mut text.as_bytes(), Compression::Zstd(3)).unwrap();
+    writer.add_entry("already.png", &mut text.as_bytes()).unwrap();
+    let bytes = writer.finish().unwrap().into_inner();
+
+    let mut reader = VmpakReader::new(io::Cursor::new(bytes), None).unwrap();
+    let expected = [
+        ("stored.txt", Compression::Stored),
+        ("deflate.txt", Compression::Deflate(6)),
+        ("zstd.txt", Compression::Zstd(3)),
+        ("already.png", Compression::Stored),
+    ];
+    for (path, compression) in expected {
+        let entry = reader.entry(path).unwrap();
+        assert_eq!(entry.compression, compression, "{path}");
+        assert_eq!(entry.size, text.len() as u64);
+        match compression {
+            Compression::Stored => assert_eq!(entry.compressed_size, entry.size),
+            _ => assert!(entry.compressed_size < entry.size / 10, "{path} barely compressed"),
+        }
+
+        let mut read = String::new();
+        reader.open_entry(path).unwrap().read_to_string(&mut read).unwrap();
+        assert_eq!(read, text, "{path}");
+    }
+
+    assert_eq!(Compression::from_raw(3, 0).unwrap_err().kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn metadata_round_trips_through_a_pack() {
+    let mut metadata = metadata();
+    metadata.authors = vec!["someone".into()];
+    metadata.game_versions = vec!["1.2.*".into()];
+    metadata.description = "Sharper textures".into();
+    metadata.dependencies = vec![VmpakDependency { mod_id: "base".into(), version: Some(">=1.0".into()), optional: false }];
+    metadata.tags = vec!["textures".into()];
+    metadata.homepage = Some("https://example.com".into());
+    let bytes = VmpakWriter::new(io::Cursor::new(Vec::new()), &metadata, VmpakFlags::empty()).unwrap().finish().unwrap().into_inner();
+    assert_eq!(VmpakReader::new(io::Cursor::new(bytes), None).unwrap().metadata(), &metadata);
+
+    // Fields a newer manager wrote are kept for the next repack
+    let newer = br#"{"schema_version":1,"mod_id":"m","name":"M","version":"1","game_id":"g","future":{"x":1}}"#;
+    let parsed = VmpakMetadata::from_bytes(newer, VMPAK_FORMAT_VERSION).unwrap();
+    assert_eq!(parsed.extra["future"], serde_json::json!({ "x": 1 }));
+    assert_eq!(VmpakMetadata::from_bytes(&parsed.to_bytes().unwrap(), VMPAK_FORMAT_VERSION).unwrap(), parsed);
+
+    // A newer schema changed what fields mean, so it isn't guessed at
+    let changed = br#"{"schema_version":2,"mod_id":"m","name":"M","version":"1","game_id":"g"}"#;
+    let err = VmpakMetadata::from_bytes(changed, VMPAK_FORMAT_VERSION).unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::Unsupported);
+    assert!(err.to_string().contains("schema 2"), "{err}");
+
+    let legacy = VmpakMetadata::from_bytes(b"Creator: someone\nDescription: An old pack", 1).unwrap();
+    assert_eq!(legacy.authors, ["someone"]);
+    assert_eq!(legacy.description, "An old pack");
+    assert_eq!(VmpakMetadata::from_bytes(b"Creator: someone", VMPAK_FORMAT_VERSION).unwrap_err().kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn unknown_advisory_flags_survive_a_read() {
+    let advisory = VmpakFlags::from_bits_retain(1 << 3);
+    let key = ed25519_dalek::SigningKey::from_bytes(&[7; 32]);
+    let mut writer = VmpakWriter::new(io::Cursor::new(Vec::new()), &metadata(), advisory | VmpakFlags::HAS_CONFIGS).unwrap();
+    writer.add_signer(key.clone());
+    let mut pack = writer.finish().unwrap();
+
+    pack.set_position(0);
+    let header = VmpakHeader::read(&mut pack).unwrap();
+    assert_eq!(header.flags, advisory | VmpakFlags::HAS_CONFIGS);
+    assert_eq!(header.flags.unknown_bits(), 1 << 3);
+    // The signed digest covers the header, so keeping the bit keeps the signature valid
+    assert_eq!(check_signatures(&mut pack, &[key.verifying_key().to_bytes()]).unwrap(), SignatureStatus::Trusted(key.verifying_key().to_bytes()));
+
+    pack.set_position(0);
+    assert_eq!(VmpakHeader::read_with_policy(&mut pack, UnknownFlagPolicy::Strict).unwrap_err().kind(), io::ErrorKind::Unsupported);
+    assert_eq!(VmpakFlags::from_bits_retain(1 << 7).validate(UnknownFlagPolicy::Lenient).unwrap_err().kind(), io::ErrorKind::Unsupported);
+}
+
+#[test]
+fn verify_reports_corruption() {
+    let mut writer = VmpakWriter::new(io::Cursor::new(Vec::new()), &metadata(), VmpakFlags::empty()).unwrap();
+    writer.set_dedup(VmpakDedup::Off);
+    writer.add_entry_with("data.bin", &mut &noise(4096, 3)[..], Compression::Stored).unwrap();
+    writer.add_entry("readme.txt", &mut "hello ".repeat(100).as_bytes()).unwrap();
+    let mut pack = writer.finish().unwrap().into_inner();
+
+    let report = verify(&mut io::Cursor::new(&pack), None).unwrap();
+    assert!(report.is_ok());
+    assert_eq!((report.entries_checked, report.digest_ok), (2, Some(true)));
+
+    let offset = VmpakReader::new(io::Cursor::new(&pack), None).unwrap().entries()[0].offset as usize;
+    pack[offset + 100] ^= 0xFF;
+    let report = verify(&mut io::Cursor::new(&pack), None).unwrap();
+    assert!(report.index_ok);
+    assert_eq!(report.digest_ok, Some(false));
+    assert_eq!(report.corrupt_entries.iter().map(|e| e.path.as_str()).collect::<Vec<_>>(), ["data.bin"]);
+
+    // The header is covered by its own checksum
+    pack[10] ^= 0xFF;
+    assert_eq!(VmpakHeader::read(&mut &pack[..]).unwrap_err().kind(), io::ErrorKind::InvalidData);
+}
+
+#[test]
+fn signatures_are_checked_against_trusted_keys() {
+    let dir = TempDir::new("signing");
+    let path = dir.join("signed.vmpak");
+    fs::write(&path, small_pack()).unwrap();
+    let publisher = ed25519_dalek::SigningKey::from_bytes(&[1; 32]);
+    let other = ed25519_dalek::SigningKey::from_bytes(&[2; 32]);
+    let trusted = [publisher.verifying_key().to_bytes()];
+    let status = |path: &Path| check_signatures(&mut File::open(path).unwrap(), &trusted).unwrap();
+
+    assert_eq!(status(&path), SignatureStatus::Unsigned);
+
+    let mut file = fs::OpenOptions::new().read(true).write(true).open(&path).unwrap();
+    sign_pack(&mut file, &other).unwrap();
+    drop(file);
+    assert_eq!(status(&path), SignatureStatus::Untrusted);
+
+    let mut file = fs::OpenOptions::new().read(true).write(true).open(&path).unwrap();
+    sign_pack(&mut file, &publisher).unwrap();
+    drop(file);
+    assert_eq!(status(&path), SignatureStatus::Trusted(trusted[0]));
+    // Signing doesn't disturb the contents
+    assert_eq!(VmpakReader::open(&path).unwrap().entries().len(), 3);
+
+    let mut pack = fs::read(&path).unwrap();
+    let offset = VmpakReader::new(io::Cursor::new(&pack), None).unwrap().entries()[1].offset as usize;
+    pack[offset] ^= 0xFF;
+    fs::write(&path, pack).unwrap();
+    assert!(matches!(status(&path), SignatureStatus::Invalid(_)));
+}
+
+#[test]
+fn encrypted_packs_need_the_right_key() {
+    let key = VmpakKey::generate();
+    let encryption = VmpakEncryption::from_key(key.clone(), false).unwrap();
+    let mut writer = VmpakWriter::with_encryption(io::Cursor::new(Vec::new()), &metadata(), VmpakFlags::empty(), Some(encryption)).unwrap();
+    writer.add_entry("readme.txt", &mut "secret ".repeat(100).as_bytes()).unwrap();
+    let pack = writer.finish().unwrap().into_inner();
+
+    let unlock = VmpakUnlock::Key(VmpakKey::from_hex(&key.to_hex()).unwrap());
+    let mut reader = VmpakReader::new(io::Cursor::new(&pack), Some(&unlock)).unwrap();
+    let mut contents = String::new();
+    reader.open_entry("readme.txt").unwrap().read_to_string(&mut contents).unwrap();
+    assert_eq!(contents, "secret ".repeat(100));
+
+    let wrong = VmpakUnlock::Key(VmpakKey::generate());
+    assert_eq!(VmpakReader::new(io::Cursor::new(&pack), Some(&wrong)).err().unwrap().kind(), io::ErrorKind::PermissionDenied);
+    assert!(VmpakReader::new(io::Cursor::new(&pack), None).is_err());
+}
+
+#[test]
+fn encrypted_payloads_only_decrypt_where_they_were_written() {
+    let encryption = VmpakEncryption::from_key(VmpakKey::generate(), true).unwrap();
+    let key = encryption.key.clone();
+    let mut writer = VmpakWriter::with_encryption(io::Cursor::new(Vec::new()), &metadata(), VmpakFlags::empty(), Some(encryption)).unwrap();
+    writer.set_dedup(VmpakDedup::Off);
+    writer.add_entry_with("a.txt", &mut &b"first payload"[..], Compression::Stored).unwrap();
+    writer.add_entry_with("b.txt", &mut &b"other payload"[..], Compression::Stored).unwrap();
+    let mut pack = writer.finish().unwrap().into_inner();
+
+    // Swap the two ciphertexts, the public index still points at the original offsets
+    let entries = VmpakReader::new(io::Cursor::new(&pack), None).unwrap().entries().to_vec();
+    let (a, b) = (&entries[0], &entries[1]);
+    assert_eq!(a.compressed_size, b.compressed_size);
+    let (a_range, b_range) = (a.offset as usize..(a.offset + a.compressed_size) as usize, b.offset as usize..(b.offset + b.compressed_size) as usize);
+    let a_bytes = pack[a_range.clone()].to_vec();
+    pack.copy_within(b_range.clone(), a_range.start);
+    pack[b_range].copy_from_slice(&a_bytes);
+
+    let mut reader = VmpakReader::new(io::Cursor::new(&pack), Some(&VmpakUnlock::Key(key))).unwrap();
+    let err = io::copy(&mut reader.open_entry("a.txt").unwrap(), &mut io::sink()).unwrap_err();
+    assert!(err.to_string().contains("decryption failed"), "{err}");
+}
+
+#[test]
+fn expensive_key_derivation_is_refused() {
+    let header = VmpakEncryption::from_passphrase("hunter2", false).unwrap().header;
+    assert!(header.unlock_with_passphrase("hunter2").is_ok());
+
+    for tamper in [
+        |h: &mut VmpakEncryptionHeader| h.memory_cost = u32::MAX,
+        |h: &mut VmpakEncryptionHeader| h.time_cost = u32::MAX,
+        |h: &mut VmpakEncryptionHeader| h.parallelism = u32::MAX,
+    ] {
+        let mut header = header.clone();
+        tamper(&mut header);
+        assert_eq!(header.unlock_with_passphrase("hunter2").unwrap_err().kind(), io::ErrorKind::InvalidData);
+    }
+}
+
+#[test]
+fn mmap_and_async_readers_match_the_plain_one() {
+    let dir = TempDir::new("readers");
+    let path = dir.join("pack.vmpak");
+    fs::write(&path, small_pack()).unwrap();
+
+    let mut reader = VmpakReader::open(&path).unwrap();
+    let expected: Vec<(String, Vec<u8>)> = reader
+        .entries()
+        .to_vec()
+        .into_iter()
+        .map(|entry| {
+            let mut contents = Vec::new();
+            reader.open_entry(&entry.path).unwrap().read_to_end(&mut contents).unwrap();
+            (entry.path, contents)
+        })
+        .collect();
+
+    // Shared entries can be read from several threads at once
+    let mapped = VmpakReader::open_mmap(&path, None).unwrap();
+    std::thread::scope(|scope| {
+        for (name, contents) in &expected {
+            let mapped = &mapped;
+            scope.spawn(move || {
+                let mut read = Vec::new();
+                mapped.open_entry_shared(name).unwrap().read_to_end(&mut read).unwrap();
+                assert_eq!(&read, contents);
+            });
+        }
+    });
+
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.block_on(async {
+        use tokio::io::AsyncReadExt;
+
+        let reader = AsyncVmpakReader::open(path.clone(), None).await.unwrap();
+        assert_eq!(reader.metadata(), mapped.metadata());
+        for (name, contents) in &expected {
+            let mut read = Vec::new();
+            reader.open_entry(name).unwrap().read_to_end(&mut read).await.unwrap();
+            assert_eq!(&read, contents);
+        }
+        assert_eq!(reader.open_entry("missing").err().unwrap().kind(), io::ErrorKind::NotFound);
+    });
+
+    let split = write_split_pack(&dir.join("split.vmpak"), 64 * 1024, &[("a.bin", noise(150_000, 1))]);
+    assert_eq!(VmpakReader::open_mmap(&split[0], None).err().unwrap().kind(), io::ErrorKind::Unsupported);
+}
+
+#[test]
+fn provider_metadata_fills_what_it_knows() {
+    let mut metadata = metadata();
+    metadata.description = "Sharper textures".into();
+    metadata.authors = vec!["someone".into()];
+    metadata.tags = vec!["textures".into()];
+    let mut writer = VmpakWriter::new(io::Cursor::new(Vec::new()), &metadata, VmpakFlags::empty()).unwrap();
+    writer.add_entry(".vmpak/README.md", &mut &b"# Readme"[..]).unwrap();
+    let mut reader = VmpakReader::new(io::Cursor::new(writer.finish().unwrap().into_inner()), None).unwrap();
+    let (summary, extended) = (reader.summary(None).unwrap(), reader.extended_metadata().unwrap());
+
+    let described = VmpakMetadata::from_provider("game", "remote-id", Some(&summary), Some(&extended));
+    assert_eq!((described.mod_id.as_str(), described.name.as_str(), described.version.as_str()), ("remote-id", "Test mod", "1.0.0"));
+    assert_eq!((described.game_id.as_str(), described.description.as_str()), ("game", "# Readme"));
+    assert_eq!((described.authors, described.tags), (vec!["someone".to_string()], vec!["textures".to_string()]));
+
+    // Installing a mod that was never browsed to still gets a usable record
+    let bare = VmpakMetadata::from_provider("game", "remote-id", None, None);
+    assert_eq!((bare.name.as_str(), bare.version.as_str(), bare.description.as_str()), ("remote-id", "", ""));
+}
+
+#[test]
+fn archives_convert_with_their_layout_and_attributes() {
+    let dir = TempDir::new("convert");
+    let archive = dir.join("mod.zip");
+    let mut writer = zip::ZipWriter::new(File::create(&archive).unwrap());
+    let modified = zip::DateTime::from_date_and_time(2024, 1, 2, 3, 4, 6).unwrap();
+    let options = zip::write::SimpleFileOptions::default().last_modified_time(modified);
+    writer.add_directory("Data/", options).unwrap();
+    writer.start_file("Data/run.sh", options.unix_permissions(0o755)).unwrap();
+    writer.write_all(b"#!/bin/sh").unwrap();
+    writer.start_file("./readme.txt", options.unix_permissions(0o644)).unwrap();
+    writer.write_all(b"read me").unwrap();
+    writer.finish().unwrap();
+
+    let pack = dir.join("zip.vmpak");
+    assert_eq!(convert_to_vmpak(&archive, &pack, &metadata(), VmpakFlags::empty()).unwrap(), 2);
+    let reader = VmpakReader::open(&pack).unwrap();
+    let attributes: Vec<_> = reader.entries().iter().map(|e| (e.path.as_str(), e.attributes.modified, e.attributes.executable)).collect();
+    assert_eq!(attributes, [("Data/run.sh", 1_704_164_646, true), ("readme.txt", 1_704_164_646, false)]);
+
+    let archive = dir.join("mod.tar");
+    let mut builder = tar::Builder::new(File::create(&archive).unwrap());
+    for (path, mode, contents) in [("bin/run", 0o755, &b"run"[..]), ("bin/data.txt", 0o600, &b"data"[..])] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(mode);
+        header.set_mtime(1_700_000_000);
+        header.set_entry_type(tar::EntryType::Regular);
+        builder.append_data(&mut header, path, contents).unwrap();
+    }
+    builder.into_inner().unwrap();
+
+    let pack = dir.join("tar.vmpak");
+    assert_eq!(convert_to_vmpak(&archive, &pack, &metadata(), VmpakFlags::empty()).unwrap(), 2);
+    let mut reader = VmpakReader::open(&pack).unwrap();
+    let attributes: Vec<_> = reader.entries().iter().map(|e| (e.path.clone(), e.attributes.modified, e.attributes.executable)).collect();
+    assert_eq!(attributes, [("bin/run".to_string(), 1_700_000_000, true), ("bin/data.txt".to_string(), 1_700_000_000, false)]);
+    let mut contents = String::new();
+    reader.open_entry("bin/data.txt").unwrap().read_to_string(&mut contents).unwrap();
+    assert_eq!(contents, "data");
+}
+
+fn write_pack(path: &Path, files: &[(&str, Vec<u8>)]) {
+    let mut writer = VmpakWriter::new(File::create(path).unwrap(), &metadata(), VmpakFlags::empty()).unwrap();
+    for (name, contents) in files {
+        writer.add_entry(name, &mut &contents[..]).unwrap();
+    }
+    writer.finish().unwrap();
+}
+
+fn write_dir(dir: &Path, files: &[(&str, Vec<u8>)]) {
+    for (name, contents) in files {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, contents).unwrap();
+    }
+}
+
+/// Every file under `dir` with its contents, `/` separated and sorted
+fn read_dir(dir: &Path) -> Vec<(String, Vec<u8>)> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(current) = pending.pop() {
+        for child in fs::read_dir(current).unwrap() {
+            let path = child.unwrap().path();
+            if path.is_dir() {
+                pending.push(path);
+            } else {
+                let relative = path.strip_prefix(dir).unwrap().to_string_lossy().replace('\\', "/");
+                files.push((relative, fs::read(path).unwrap()));
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+#[test]
+fn patches_apply_to_packs_and_directories() {
+    let dir = TempDir::new("patch");
+    let changed = noise(64 * 1024, 5);
+    let mut edited = changed.clone();
+    edited[1000..1010].copy_from_slice(b"0123456789");
+    let base = [("keep.txt", b"keep".to_vec()), ("change.bin", changed), ("gone.txt", b"gone".to_vec()), ("x", b"file".to_vec())];
+    let target = [("keep.txt", b"keep".to_vec()), ("change.bin", edited), ("x/y.txt", b"now a directory".to_vec())];
+    write_pack(&dir.join("base.vmpak"), &base);
+    write_pack(&dir.join("target.vmpak"), &target);
+
+    let patch = dir.join("update.vmpak");
+    let summary = create_patch(&dir.join("base.vmpak"), &dir.join("target.vmpak"), &patch, &PatchOptions::default()).unwrap();
+    assert_eq!((summary.added, summary.changed, summary.diffed, summary.removed, summary.unchanged), (1, 1, 1, 2, 1));
+
+    let expected: Vec<_> = {
+        let mut files: Vec<_> = target.iter().map(|(name, contents)| (name.to_string(), contents.clone())).collect();
+        files.sort();
+        files
+    };
+    apply_patch(&dir.join("base.vmpak"), &patch, &dir.join("patched.vmpak")).unwrap();
+    let mut patched = VmpakReader::open(&dir.join("patched.vmpak")).unwrap();
+    let mut entries: Vec<_> = patched.entries().iter().map(|e| e.path.clone()).collect();
+    entries.sort();
+    for (name, contents) in &expected {
+        let mut read = Vec::new();
+        patched.open_entry(name).unwrap().read_to_end(&mut read).unwrap();
+        assert_eq!(&read, contents);
+    }
+    assert_eq!(entries, expected.iter().map(|(name, _)| name.clone()).collect::<Vec<_>>());
+
+    // A staging directory left by an interrupted run is thrown away
+    let mod_dir = dir.join("mod");
+    write_dir(&mod_dir, &base);
+    write_dir(&mod_dir.join(".vmpak-patch"), &[("junk.txt", b"stale".to_vec())]);
+    apply_patch_in_place(&patch, &mod_dir).unwrap();
+    assert_eq!(read_dir(&mod_dir), expected);
+
+    // Already patched, so it no longer matches the base
+    assert_eq!(apply_patch_in_place(&patch, &mod_dir).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    assert_eq!(read_dir(&mod_dir), expected);
+
+    let interrupted = dir.join("interrupted");
+    write_dir(&interrupted, &base);
+    write_dir(&interrupted.join(".vmpak-patch-backup"), &[("gone.txt", b"gone".to_vec())]);
+    assert_eq!(apply_patch_in_place(&patch, &interrupted).unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+}
+
+#[test]
+fn interrupted_patch_swaps_roll_back() {
+    let dir = TempDir::new("patch-swap");
+    let mod_dir = dir.join("mod");
+    let original = [("a.txt", b"a".to_vec()), ("sub/b.txt", b"b".to_vec()), ("x", b"file".to_vec())];
+    write_dir(&mod_dir, &original);
+    write_dir(&dir.join("staged"), &[("a.txt", b"new a".to_vec()), ("x/y.txt", b"y".to_vec()), ("c.txt", b"c".to_vec())]);
+
+    let backup = dir.join("backup");
+    let mut swap = patch::Swap::new(&mod_dir, &backup);
+    swap.set_aside("sub/b.txt").unwrap();
+    swap.set_aside("x").unwrap();
+    swap.replace("a.txt", &dir.join("staged/a.txt")).unwrap();
+    swap.replace("x/y.txt", &dir.join("staged/x/y.txt")).unwrap();
+    swap.replace("c.txt", &dir.join("staged/c.txt")).unwrap();
+    assert_eq!(fs::read(mod_dir.join("x/y.txt")).unwrap(), b"y");
+    // The next file fails to swap in
+    assert!(swap.replace("d.txt", &dir.join("staged/missing.txt")).is_err());
+
+    swap.roll_back();
+    let mut expected: Vec<_> = original.iter().map(|(name, contents)| (name.to_string(), contents.clone())).collect();
+    expected.sort();
+    assert_eq!(read_dir(&mod_dir), expected);
+    assert!(!backup.exists());
+}
+
+#[test]
+fn dedup_shares_identical_and_similar_data() {
+    let base = noise(1 << 20, 9);
+    let mut edited = base.clone();
+    edited[500_000..500_016].copy_from_slice(b"edited in place!");
+    let files = [("a.bin", base.clone()), ("copy/a.bin", base), ("b.bin", edited), ("small.txt", b"small".to_vec())];
+
+    let pack = |dedup| {
+        let mut writer = VmpakWriter::new(io::Cursor::new(Vec::new()), &metadata(), VmpakFlags::empty()).unwrap();
+        writer.set_compression(Compression::Stored);
+        writer.set_dedup(dedup);
+        for (name, contents) in &files {
+            writer.add_entry(name, &mut &contents[..]).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    };
+    let stats = |bytes: &[u8]| {
+        let reader = VmpakReader::new(io::Cursor::new(bytes), None).unwrap();
+        let entries = reader.entries().to_vec();
+        dedup_stats(&mut reader.into_inner(), &entries).unwrap()
+    };
+
+    let off = pack(VmpakDedup::Off);
+    let whole_files = pack(VmpakDedup::Files);
+    let chunks = pack(VmpakDedup::Chunks);
+    assert_eq!(stats(&off).shared_entries, 0);
+    assert_eq!(stats(&off).saved(), 0);
+
+    // The copy shares the original's payload, the edited file doesn't
+    let reader = VmpakReader::new(io::Cursor::new(&whole_files), None).unwrap();
+    assert_eq!(reader.entries()[0].offset, reader.entries()[1].offset);
+    assert_ne!(reader.entries()[0].offset, reader.entries()[2].offset);
+    assert_eq!(stats(&whole_files).shared_entries, 1);
+    assert!(whole_files.len() < off.len() * 3 / 4);
+
+    // Content-defined chunks also share everything around the edit
+    let chunk_stats = stats(&chunks);
+    assert!(chunk_stats.stored_size < (1 << 20) + 300 * 1024, "{chunk_stats:?}");
+    assert_eq!(chunk_stats.logical_size, 3 * (1 << 20) + 5);
+
+    for bytes in [off, whole_files, chunks] {
+        let mut reader = VmpakReader::new(io::Cursor::new(bytes), None).unwrap();
+        for (name, contents) in &files {
+            let mut read = Vec::new();
+            reader.open_entry(name).unwrap().read_to_end(&mut read).unwrap();
+            assert_eq!(&read, contents, "{name}");
+        }
+    }
+}
+
+const OVER_4_GIB: u64 = (1 << 32) + 4096;
+
+/// Offsets and sizes past 4 GiB without writing 4 GiB: the pack is laid out by hand in a sparse file,
+/// with the payload after a hole and an entry whose size only lives in the index
+#[test]
+fn offsets_and_sizes_past_4_gib_round_trip() {
+    let dir = TempDir::new("large-offsets");
+    let path = dir.join("sparse.vmpak");
+    let metadata = metadata().to_bytes().unwrap();
+    let payload = b"past the 4 GiB mark";
+    let payload_offset = OVER_4_GIB + 512;
+
+    let entries = vec![
+        VmpakEntry {
+            path: "far.txt".into(),
+            offset: payload_offset,
+            compressed_size: payload.len() as u64,
+            size: payload.len() as u64,
+            compression: Compression::Stored,
+            checksum: Some(crc32fast::hash(payload)),
+            attributes: EntryAttributes::default(),
+            chunked: false,
+            encrypted: false,
+            bound: false,
+        },
+        VmpakEntry {
+            path: "huge.bin".into(),
+            offset: payload_offset + payload.len() as u64,
+            compressed_size: 64,
+            size: 5 << 30,
+            compression: Compression::Zstd(3),
+            checksum: Some(0),
+            attributes: EntryAttributes::default(),
+            chunked: false,
+            encrypted: false,
+            bound: false,
+        },
+    ];
+    let mut index = Vec::new();
+    write_index(&mut index, &entries).unwrap();
+
+    let header = VmpakHeader {
+        magic: VMPAK_MAGIC,
+        format_version: VMPAK_FORMAT_VERSION,
+        manager_version: VMPAK_MANAGER_VERSION,
+        index_table_offset: entries[1].offset + entries[1].compressed_size,
+        metadata_size: metadata.len() as u64,
+        flags: VmpakFlags::empty(),
+        reserved: 0,
+    };
+    let mut file = SparseFile { file: File::create(&path).unwrap(), len: 0 };
+    header.write(&mut file).unwrap();
+    file.write_all(&metadata).unwrap();
+    file.seek(SeekFrom::Start(payload_offset)).unwrap();
+    file.write_all(payload).unwrap();
+    file.seek(SeekFrom::Start(header.index_table_offset)).unwrap();
+    file.write_all(&index).unwrap();
+    VmpakTrailer { index_checksum: crc32fast::hash(&index), signatures_size: 0, digest: [0; 32] }.write(&mut file).unwrap();
+    file.finish().unwrap();
+
+    let mut reader = VmpakReader::open(&path).unwrap();
+    assert_eq!(reader.header().index_table_offset, header.index_table_offset);
+    assert_eq!(reader.entries(), &entries[..]);
+    let mut contents = Vec::new();
+    reader.open_entry("far.txt").unwrap().read_to_end(&mut contents).unwrap();
+    assert_eq!(contents, payload);
+}
+
+#[test]
+#[ignore = "streams more than 4 GiB, run with --ignored"]
+fn entry_larger_than_4_gib_round_trips() {
+    let dir = TempDir::new("large-entry");
+    let source = File::create(dir.join("zeroes.bin")).unwrap();
+    source.set_len(OVER_4_GIB).unwrap();
+
+    let output = SparseFile { file: File::create(dir.join("large.vmpak")).unwrap(), len: 0 };
+    let mut writer = VmpakWriter::new(output, &metadata(), VmpakFlags::empty()).unwrap();
+    writer.set_dedup(VmpakDedup::Off);
+    let mut zeroes = File::open(dir.join("zeroes.bin")).unwrap();
+    writer.add_entry_with("zeroes.bin", &mut zeroes, Compression::Stored).unwrap();
+    writer.add_entry("after.txt", &mut &b"after the big one"[..]).unwrap();
+    writer.finish().unwrap().finish().unwrap();
+
+    let mut reader = VmpakReader::open(&dir.join("large.vmpak")).unwrap();
+    let big = reader.entry("zeroes.bin").unwrap().clone();
+    let after = reader.entry("after.txt").unwrap().clone();
+    assert_eq!(big.size, OVER_4_GIB);
+    assert!(after.offset > u32::MAX as u64, "second entry should sit past the 4 GiB mark");
+
+    let mut contents = String::new();
+    reader.open_entry("after.txt").unwrap().read_to_string(&mut contents).unwrap();
+    assert_eq!(contents, "after the big one");
+
+    let copied = io::copy(&mut reader.open_entry("zeroes.bin").unwrap(), &mut io::sink()).unwrap();
+    assert_eq!(copied, OVER_4_GIB);
+
+    let report = verify(&mut VolumeReader::open(&dir.join("large.vmpak")).unwrap(), None).unwrap();
+    assert!(report.is_ok(), "{report:?}");
+}
+
+#[test]
+#[ignore = "streams more than 4 GiB, run with --ignored"]
+fn compressed_entry_larger_than_4_gib_keeps_its_size() {
+    let dir = TempDir::new("large-compressed");
+    let source = File::create(dir.join("zeroes.bin")).unwrap();
+    source.set_len(OVER_4_GIB).unwrap();
+
+    let mut writer = VmpakWriter::new(File::create(dir.join("large.vmpak")).unwrap(), &metadata(), VmpakFlags::empty()).unwrap();
+    writer.set_compression(Compression::Zstd(1));
+    writer.add_file("zeroes.bin", &dir.join("zeroes.bin")).unwrap();
+    writer.finish().unwrap();
+
+    let mut reader = VmpakReader::open(&dir.join("large.vmpak")).unwrap();
+    assert_eq!(reader.entry("zeroes.bin").unwrap().size, OVER_4_GIB);
+    let copied = io::copy(&mut reader.open_entry("zeroes.bin").unwrap(), &mut io::sink()).unwrap();
+    assert_eq!(copied, OVER_4_GIB);
+}
+
+fn write_split_pack(path: &Path, volume_size: u64, files: &[(&str, Vec<u8>)]) -> Vec<PathBuf> {
+    let mut writer = VmpakWriter::new(VolumeWriter::create(path, volume_size).unwrap(), &metadata(), VmpakFlags::empty()).unwrap();
+    writer.set_compression(Compression::Stored);
+    for (name, contents) in files {
+        writer.add_entry(name, &mut &contents[..]).unwrap();
+    }
+    writer.finish().unwrap().paths()
+}
+
+#[test]
+fn split_pack_reads_as_one_archive() {
+    let dir = TempDir::new("split");
+    let files = [("a.bin", noise(150_000, 1)), ("b.bin", noise(90_000, 2)), ("c.txt", b"small".to_vec())];
+    let first = dir.join("split.vmpak");
+
+    let volumes = write_split_pack(&first, 64 * 1024, &files);
+    assert_eq!(volumes.len(), 4);
+    assert_eq!(volumes[1], dir.join("split.vmpak.001"));
+    assert!(volumes.iter().all(|v| fs::metadata(v).unwrap().len() <= 64 * 1024));
+
+    let mut reader = VmpakReader::open(&first).unwrap();
+    for (name, contents) in &files {
+        let mut read = Vec::new();
+        reader.open_entry(name).unwrap().read_to_end(&mut read).unwrap();
+        assert_eq!(&read, contents, "{name}");
+    }
+
+    let report = verify(&mut VolumeReader::open(&first).unwrap(), None).unwrap();
+    assert!(report.is_ok(), "{report:?}");
+}
+
+#[test]
+fn split_pack_matches_single_file_pack() {
+    let dir = TempDir::new("split-same");
+    let files = [("a.bin", noise(200_000, 3))];
+
+    write_split_pack(&dir.join("single.vmpak"), u64::MAX, &files);
+    let volumes = write_split_pack(&dir.join("split.vmpak"), 50_000, &files);
+
+    let joined: Vec<u8> = volumes.iter().flat_map(|v| fs::read(v).unwrap()).collect();
+    let single = fs::read(dir.join("single.vmpak")).unwrap();
+    assert_eq!(joined.len(), single.len());
+    assert_eq!(joined, single);
+}
+
+#[test]
+fn missing_volume_is_an_error() {
+    let dir = TempDir::new("split-missing");
+    let first = dir.join("split.vmpak");
+    let volumes = write_split_pack(&first, 64 * 1024, &[("a.bin", noise(200_000, 4))]);
+
+    fs::remove_file(volumes.last().unwrap()).unwrap();
+    assert!(VmpakReader::open(&first).is_err());
+}
+
+#[test]
+fn repacking_removes_stale_volumes() {
+    let dir = TempDir::new("split-stale");
+    let first = dir.join("split.vmpak");
+    write_split_pack(&first, 64 * 1024, &[("a.bin", noise(200_000, 5))]);
+    let volumes = write_split_pack(&first, u64::MAX, &[("a.bin", noise(1000, 6))]);
+
+    assert_eq!(volumes.len(), 1);
+    assert!(!volume_path(&first, 1).exists());
+    VmpakReader::open(&first).unwrap();
+}
+
+#[test]
+fn stale_volumes_next_to_a_whole_pack_are_ignored() {
+    let dir = TempDir::new("split-leftover");
+    let first = dir.join("mod.vmpak");
+    fs::write(&first, small_pack()).unwrap();
+    fs::write(volume_path(&first, 1), noise(1000, 7)).unwrap();
+
+    let mut volumes = VolumeReader::open(&first).unwrap();
+    assert_eq!(volumes.volume_count(), 1);
+    assert!(verify(&mut volumes, None).unwrap().is_ok());
+    VmpakReader::open(&first).unwrap();
+}
+
+#[test]
+fn split_packs_are_signed_across_every_volume() {
+    let dir = TempDir::new("split-signed");
+    let first = dir.join("split.vmpak");
+    let key = ed25519_dalek::SigningKey::from_bytes(&[3; 32]);
+    let mut writer = VmpakWriter::new(VolumeWriter::create(&first, 64 * 1024).unwrap(), &metadata(), VmpakFlags::empty()).unwrap();
+    writer.set_compression(Compression::Stored);
+    writer.add_signer(key.clone());
+    writer.add_entry("a.bin", &mut &noise(200_000, 8)[..]).unwrap();
+    assert!(writer.finish().unwrap().paths().len() > 1);
+
+    let trusted = [key.verifying_key().to_bytes()];
+    assert_eq!(check_signatures(&mut VolumeReader::open(&first).unwrap(), &trusted).unwrap(), SignatureStatus::Trusted(trusted[0]));
+}
+
+fn compression() -> impl Strategy<Value = Compression> {
+    prop_oneof![
+        Just(Compression::Stored),
+        (0u32..=9).prop_map(Compression::Deflate),
+        (i8::MIN as i32..=i8::MAX as i32).prop_map(Compression::Zstd),
+    ]
+}
+
+fn entry() -> impl Strategy<Value = VmpakEntry> {
+    (
+        "[a-zA-Z0-9_./ -]{1,64}",
+        any::<u64>(),
+        any::<u64>(),
+        any::<u64>(),
+        compression(),
+        any::<u32>(),
+        any::<u64>(),
+        any::<bool>(),
+        any::<bool>(),
+    )
+        .prop_map(|(path, offset, compressed_size, size, compression, checksum, modified, executable, chunked)| VmpakEntry {
+            path,
+            offset,
+            compressed_size,
+            size,
+            compression,
+            checksum: Some(checksum),
+            attributes: EntryAttributes { modified, executable },
+            chunked,
+            encrypted: false,
+            bound: false,
+        })
+}
+
+fn pack_metadata() -> impl Strategy<Value = VmpakMetadata> {
+    let text = || ".{0,32}";
+    (
+        (text(), text(), text(), text(), text()),
+        prop::collection::vec(text(), 0..4),
+        prop::collection::vec(text(), 0..4),
+        prop::collection::vec((text(), prop::option::of(text()), any::<bool>()), 0..4),
+        prop::option::of(text()),
+    )
+        .prop_map(|((mod_id, name, version, game_id, description), authors, tags, dependencies, homepage)| {
+            let mut metadata = VmpakMetadata::new(mod_id, name, version, game_id);
+            metadata.description = description;
+            metadata.authors = authors;
+            metadata.tags = tags;
+            metadata.dependencies = dependencies
+                .into_iter()
+                .map(|(mod_id, version, optional)| VmpakDependency { mod_id, version, optional })
+                .collect();
+            metadata.homepage = homepage;
+            metadata
+        })
+}
+
+proptest! {
+    #[test]
+    fn header_round_trips(
+        format_version in 3..=VMPAK_FORMAT_VERSION,
+        manager_version: u16,
+        index_table_offset: u64,
+        metadata_size: u64,
+        flags: u8,
+        reserved: u8,
+    ) {
+        let header = VmpakHeader {
+            magic: VMPAK_MAGIC,
+            format_version,
+            manager_version,
+            index_table_offset,
+            metadata_size,
+            flags: VmpakFlags::from_bits_retain(flags),
+            reserved,
+        };
+        let bytes = header.to_bytes();
+        prop_assert_eq!(bytes.len(), header.encoded_len());
+        prop_assert_eq!(VmpakHeader::read_unchecked(&mut bytes.as_slice()).unwrap(), header);
+    }
+
+    #[test]
+    fn header_rejects_oversized_metadata(metadata_size in VMPAK_MAX_METADATA_SIZE + 1.., index_table_offset: u64) {
+        let header = VmpakHeader {
+            magic: VMPAK_MAGIC,
+            format_version: VMPAK_FORMAT_VERSION,
+            manager_version: VMPAK_MANAGER_VERSION,
+            index_table_offset,
+            metadata_size,
+            flags: VmpakFlags::empty(),
+            reserved: 0,
+        };
+        prop_assert!(VmpakHeader::read(&mut header.to_bytes().as_slice()).is_err());
+    }
+
+    #[test]
+    fn metadata_round_trips(metadata in pack_metadata()) {
+        let bytes = metadata.to_bytes().unwrap();
+        prop_assert_eq!(VmpakMetadata::from_bytes(&bytes, VMPAK_FORMAT_VERSION).unwrap(), metadata);
+    }
+
+    #[test]
+    fn index_round_trips(entries in prop::collection::vec(entry(), 0..16)) {
+        let mut bytes = Vec::new();
+        write_index(&mut bytes, &entries).unwrap();
+        prop_assert_eq!(read_index(&mut bytes.as_slice(), VMPAK_FORMAT_VERSION).unwrap(), entries);
+    }
+
+    #[test]
+    fn arbitrary_bytes_never_panic(bytes in prop::collection::vec(any::<u8>(), 0..4096)) {
+        if let Ok(mut reader) = VmpakReader::new(io::Cursor::new(bytes), None) {
+            for path in reader.entries().iter().map(|e| e.path.clone()).collect::<Vec<_>>() {
+                if let Ok(mut entry) = reader.open_entry(&path) {
+                    let _ = io::copy(&mut entry, &mut io::sink());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn corrupted_pack_never_panics(position: prop::sample::Index, value: u8) {
+        let mut bytes = small_pack();
+        let position = position.index(bytes.len());
+        bytes[position] = value;
+
+        if let Ok(mut reader) = VmpakReader::new(io::Cursor::new(bytes.clone()), None) {
+            for path in reader.entries().iter().map(|e| e.path.clone()).collect::<Vec<_>>() {
+                if let Ok(mut entry) = reader.open_entry(&path) {
+                    let _ = io::copy(&mut entry, &mut io::sink());
+                }
+            }
+        }
+        let _ = verify(&mut io::Cursor::new(bytes), None);
+    }
+}
+
+/// A pack with a bit of everything: compressed, stored and chunked entries
+fn small_pack() -> Vec<u8> {
+    let mut writer = VmpakWriter::new(io::Cursor::new(Vec::new()), &metadata(), VmpakFlags::HAS_CONFIGS).unwrap();
+    writer.set_dedup(VmpakDedup::Chunks);
+    writer.add_entry("readme.txt", &mut "hello ".repeat(100).as_bytes()).unwrap();
+    writer.add_entry_with("data.bin", &mut &noise(40_000, 7)[..], Compression::Stored).unwrap();
+    writer.add_entry("copy.bin", &mut &noise(40_000, 7)[..]).unwrap();
+    writer.finish().unwrap().into_inner()
+}
+
+#[test]
+fn assets_map_onto_provider_shapes() {
+    let mut metadata = metadata();
+    metadata.description = "Sharper textures\nEverything, everywhere".into();
+    metadata.authors = vec!["someone".into()];
+
+    let mut writer = VmpakWriter::new(io::Cursor::new(Vec::new()), &metadata, VmpakFlags::empty()).unwrap();
+    writer.add_entry("textures/rock.dds", &mut &b"rock"[..]).unwrap();
+    writer.add_entry(".vmpak/thumbnail.png", &mut &b"png"[..]).unwrap();
+    writer.add_entry(".vmpak/screenshots/2.jpg", &mut &b"two"[..]).unwrap();
+    writer.add_entry(".vmpak/screenshots/1.webp", &mut &b"one"[..]).unwrap();
+    writer.add_entry(".vmpak/screenshots/notes.txt", &mut &b"not an image"[..]).unwrap();
+    writer.add_entry(".vmpak/README.md", &mut &b"# Readme"[..]).unwrap();
+    writer.add_entry(".vmpak/names.json", &mut &br#"{ "de": "Schaerfere Texturen" }"#[..]).unwrap();
+    let bytes = writer.finish().unwrap().into_inner();
+
+    let mut reader = VmpakReader::new(io::Cursor::new(bytes), None).unwrap();
+    let assets = reader.assets();
+    assert_eq!(assets.thumbnail.as_deref(), Some(".vmpak/thumbnail.png"));
+    assert_eq!(assets.screenshots, [".vmpak/screenshots/1.webp", ".vmpak/screenshots/2.jpg"]);
+    assert!(is_asset_path(".vmpak/README.md") && !is_asset_path("textures/rock.dds"));
+
+    let summary = reader.summary(Some("de-AT")).unwrap();
+    assert_eq!(summary.name, "Schaerfere Texturen");
+    assert_eq!(summary.short_description, "Sharper textures");
+    assert_eq!(summary.thumbnail_image, "data:image/png;base64,cG5n");
+    assert_eq!(reader.summary(Some("fr")).unwrap().name, "Test mod");
+
+    let extended = reader.extended_metadata().unwrap();
+    assert_eq!(extended.description, "# Readme");
+    assert_eq!(extended.carousel_images, ["data:image/webp;base64,b25l", "data:image/jpeg;base64,dHdv"]);
+    assert_eq!(extended.header_image, extended.carousel_images[0]);
+    assert_eq!(extended.version, "1.0.0");
+}
+
+#[test]
+fn install_manifest_maps_files_and_components() {
+    let manifest: VmpakInstallManifest = serde_json::from_str(r#"{
+        "files": [
+            { "source": "data/", "target": "Data" },
+            { "source": "mod.ini", "target": "mods/", "root": "config" },
+            { "source": "legacy.pak", "target": "Data/legacy.pak", "when": { "game_versions": ["1.0.*"] } }
+        ],
+        "components": [
+            { "id": "hd", "name": "HD textures", "files": [{ "source": "hd/", "target": "Data" }] },
+            { "id": "compat", "name": "Compat patch", "default": true, "files": [{ "source": "compat.pak", "target": "Data/" }],
+              "when": { "mods_present": ["other-mod"] } }
+        ]
+    }"#).unwrap();
+    let entries: Vec<String> = ["data/tex.dds", "hd/tex.dds", "mod.ini", "legacy.pak", "compat.pak", ".vmpak/README.md"]
+        .into_iter()
+        .map(String::from)
+        .collect();
+    let mut context = InstallContext {
+        roots: [(InstallRoot::Game, PathBuf::from("/game")), (InstallRoot::Config, PathBuf::from("/config"))].into(),
+        game_version: Some("1.1".into()),
+        ..Default::default()
+    };
+    let targets = |plan: InstallPlan| plan.files.into_iter().map(|f| (f.entry, f.destination)).collect::<Vec<_>>();
+
+    let plan = manifest.plan(&entries, &context).unwrap();
+    assert!(plan.components.is_empty());
+    assert_eq!(targets(plan), [
+        ("data/tex.dds".to_string(), PathBuf::from("/game/Data/tex.dds")),
+        ("mod.ini".to_string(), PathBuf::from("/config/mods/mod.ini")),
+    ]);
+
+    context.game_version = Some("1.0.2".into());
+    context.installed_mods.insert("other-mod".into());
+    context.components = Some(vec!["hd".into(), "compat".into()]);
+    let plan = manifest.plan(&entries, &context).unwrap();
+    assert_eq!(plan.components, ["hd", "compat"]);
+    assert_eq!(targets(plan), [
+        ("hd/tex.dds".to_string(), PathBuf::from("/game/Data/tex.dds")),
+        ("mod.ini".to_string(), PathBuf::from("/config/mods/mod.ini")),
+        ("legacy.pak".to_string(), PathBuf::from("/game/Data/legacy.pak")),
+        ("compat.pak".to_string(), PathBuf::from("/game/Data/compat.pak")),
+    ]);
+
+    context.roots.remove(&InstallRoot::Config);
+    assert_eq!(manifest.plan(&entries, &context).unwrap_err().kind(), io::ErrorKind::NotFound);
+
+    context.components = None;
+    let escaping = VmpakInstallManifest {
+        files: vec![InstallMapping { source: "data/".into(), target: "../outside".into(), root: InstallRoot::Game, when: None }],
+        components: Vec::new(),
+    };
+    assert_eq!(escaping.plan(&entries, &context).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    assert_eq!(VmpakInstallManifest::everything().plan(&entries, &context).unwrap().files.len(), 5);
+}
+
+fn write_zip(path: &std::path::Path, files: &[(&str, &[u8])], links: &[(&str, &str)]) {
+    let options = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    let mut writer = zip::ZipWriter::new(File::create(path).unwrap());
+    for (name, contents) in files {
+        writer.start_file(*name, options).unwrap();
+        writer.write_all(contents).unwrap();
+    }
+    for (name, target) in links {
+        writer.add_symlink(*name, *target, options).unwrap();
+    }
+    writer.finish().unwrap();
+}
+
+#[test]
+fn extraction_skips_escaping_entries_and_links() {
+    let dir = TempDir::new("extract-unsafe");
+    let archive = dir.join("mod.zip");
+    write_zip(&archive, &[("data/a.txt", b"a"), ("../escape.txt", b"out"), ("/abs.txt", b"abs")], &[("data/link", "/etc/passwd")]);
+
+    let destination = dir.join("out");
+    let mut reported = 0;
+    let extracted = extract_archive(&archive, &destination, &ExtractLimits::default(), &mut |read, len| {
+        assert!(read <= len);
+        reported += 1;
+    })
+    .unwrap();
+
+    assert_eq!(extracted, 1);
+    assert!(reported > 0);
+    assert_eq!(fs::read(destination.join("data/a.txt")).unwrap(), b"a");
+    assert!(!dir.join("escape.txt").exists());
+    assert!(fs::symlink_metadata(destination.join("data/link")).is_err());
+    // Extracting never reuses a directory that might already hold links
+    assert_eq!(extract_archive(&archive, &destination, &ExtractLimits::default(), &mut |_, _| {}).unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+}
+
+#[test]
+fn extraction_enforces_limits() {
+    let dir = TempDir::new("extract-limits");
+    let bomb = dir.join("bomb.zip");
+    let zeroes = vec![0u8; 4 << 20];
+    write_zip(&bomb, &[("zeroes.bin", &zeroes)], &[]);
+
+    let limits = ExtractLimits { max_total_size: 1 << 20, ..Default::default() };
+    let error = extract_archive(&bomb, &dir.join("bomb"), &limits, &mut |_, _| {}).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    assert!(!dir.join("bomb").exists());
+
+    let many = dir.join("many.zip");
+    write_zip(&many, &[("a", b"a"), ("b", b"b"), ("c", b"c"), ("d", b"d")], &[]);
+    let limits = ExtractLimits { max_entries: 3, ..Default::default() };
+    let error = convert_to_vmpak_with(&many, &dir.join("many.vmpak"), &metadata(), VmpakFlags::empty(), &limits, &mut |_, _| {}).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(extract_archive(&many, &dir.join("many"), &ExtractLimits::default(), &mut |_, _| {}).unwrap(), 4);
+}
+
+#[test]
+fn skipped_entries_count_towards_the_limits() {
+    let dir = TempDir::new("extract-skipped");
+    let bomb = dir.join("bomb.7z");
+    let mut writer = sevenz_rust::SevenZWriter::create(&bomb).unwrap();
+    let mut entry = sevenz_rust::SevenZArchiveEntry::new();
+    entry.name = "../zeroes.bin".into();
+    entry.has_stream = true;
+    writer.push_archive_entry(entry, Some(&vec![0u8; 4 << 20][..])).unwrap();
+    writer.finish().unwrap();
+
+    let limits = ExtractLimits { max_total_size: 1 << 20, ..Default::default() };
+    let error = extract_archive(&bomb, &dir.join("bomb"), &limits, &mut |_, _| {}).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(extract_archive(&bomb, &dir.join("out"), &ExtractLimits::default(), &mut |_, _| {}).unwrap(), 0);
+}
+
+#[test]
+fn rar_archives_are_refused() {
+    let dir = TempDir::new("extract-rar");
+    let archive = dir.join("mod.bin");
+    fs::write(&archive, b"Rar!\x1A\x07\x01\x00rest of the archive").unwrap();
+
+    assert_eq!(ArchiveKind::detect(&archive).unwrap(), Some(ArchiveKind::Rar));
+    let error = extract_archive(&archive, &dir.join("out"), &ExtractLimits::default(), &mut |_, _| {}).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+}
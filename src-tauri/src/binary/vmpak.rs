@@ -1,10 +1,35 @@
-use std::{fs::File, io::{self, Read, Seek, SeekFrom, Write}, path::Path};
+use std::{fs::File, io::{self, Read, Write}, path::Path};
+
+use tracing::warn;
+
+use super::{Compression, UnknownFlagPolicy, VmpakEncryptionHeader, VmpakFlags, VmpakMetadata, VmpakReader, VmpakWriter};
 
 // File identifier: V M P K (0x56, 0x4D, 0x50, 0x4B)
 #[allow(dead_code)] // Even though its used further down
 pub const VMPAK_MAGIC: u32 = 0x4B504D56; // Sorted litte-endian: 0x564D504B
 
-#[derive(Debug, Clone, Copy)]
+/// Layout: header, metadata, entry payloads, then the index table at `index_table_offset`
+///
+/// Only bumped for breaking layout changes, additive changes go behind an advisory flag instead.
+/// A pack with a newer version is always refused, older versions are upgraded on read:
+///
+/// 1: metadata is free text (`Creator: ...\nDescription: ...`), parsed into `authors`/`description`
+/// 2: metadata is a JSON `VmpakMetadata`
+/// 3: header CRC32, per-entry CRC32 and a trailer with the index CRC32 and archive digest.
+///    Older packs are read without integrity checks
+/// 4: optional Ed25519 signature section between the index and the trailer
+/// 5: entries carry their modification time and executable bit
+/// 6: entries can share payloads and be split into deduplicated chunks
+/// 7: encrypted metadata, index and payloads are bound to their place in the pack as associated data
+pub const VMPAK_FORMAT_VERSION: u16 = 7;
+/// Oldest format we still know how to upgrade
+pub const VMPAK_MIN_FORMAT_VERSION: u16 = 1;
+pub const VMPAK_MANAGER_VERSION: u16 = 105;
+/// Sizes read from a pack are untrusted, these cap what a reader will buffer for them
+pub const VMPAK_MAX_METADATA_SIZE: u64 = 16 * 1024 * 1024;
+pub const VMPAK_MAX_INDEX_SIZE: u64 = 256 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[allow(dead_code)]
 pub struct VmpakHeader {
     pub magic: u32,
@@ -12,91 +37,172 @@ pub struct VmpakHeader {
     pub manager_version: u16,
     pub index_table_offset: u64,
     pub metadata_size: u64,
-    pub flags: u8,
+    pub flags: VmpakFlags,
     pub reserved: u8
 }
 
+/// Whether the file at `path` starts with the VMPAK magic, anything shorter than it isn't a pack
+pub fn is_vmpak(path: &Path) -> io::Result<bool> {
+    let mut magic = [0u8; 4];
+    Ok(File::open(path)?.read_exact(&mut magic).is_ok() && u32::from_le_bytes(magic) == VMPAK_MAGIC)
+}
+
 #[allow(dead_code)]
 pub fn implement_vmpak_example(filepath: &Path) -> io::Result<()> {
-    let mut file = File::create(filepath)?;
-    let metadata_string = "Creator: Test User\nDescription: My first vmpak";
-    let metadata_bytes = metadata_string.as_bytes();
-
-    let index_table_offset: u64 = VmpakHeader::SIZE as u64 + metadata_bytes.len() as u64;
-
-    let header = VmpakHeader {
-        magic: VMPAK_MAGIC,
-        format_version: 1,
-        manager_version: 105,
-        index_table_offset: index_table_offset,
-        metadata_size: metadata_bytes.iter().len() as u64,
-        flags: 0b0000_0001, // Example: bit 0 set (Has configs)
-        reserved: 0
-    };
+    let file = File::create(filepath)?;
+    let mut metadata = VmpakMetadata::new("example-mod", "Example mod", "1.0.0", "example-game");
+    metadata.authors.push("Test User".into());
+    metadata.description = "My first vmpak".into();
 
     println!("-> Creating file: {}", filepath.display());
-    header.write(&mut file)?;
-
-    file.write_all(metadata_bytes)?;
+    let mut writer = VmpakWriter::new(file, &metadata, VmpakFlags::HAS_CONFIGS)?;
 
-    let dummy_index_data = [0xAA, 0xBB, 0xCC, 0xDD, 0xEE, 0xFF, 0x01, 0x02];
-    file.write_all(&dummy_index_data)?;
+    let readme = "This is an example mod\n".repeat(64);
+    writer.add_entry("README.md", &mut readme.as_bytes())?;
+    writer.add_entry_with("config/settings.ini", &mut "[general]\nenabled=true\n".as_bytes(), Compression::Deflate(6))?;
+    // Already compressed, so this ends up stored as-is
+    writer.add_entry("textures/icon.png", &mut [0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A].as_slice())?;
+    writer.finish()?;
 
     // Now read it back
-    let mut file_reader = File::open(filepath)?;
-
     println!("\n -> Reading file header...");
-    let read_header = VmpakHeader::read(&mut file_reader)?;
-    println!("{:#?}", read_header);
+    let mut reader = VmpakReader::open(filepath)?;
+    println!("{:#?}", reader.header());
+
+    println!("-> Metadata:\n{:#?}", reader.metadata());
+
+    println!("\n-> Index Table (offset: {})", reader.header().index_table_offset);
+    for entry in reader.entries().to_vec() {
+        println!("-> {} ({:?}, {} -> {} bytes)", entry.path, entry.compression, entry.size, entry.compressed_size);
+        let mut contents = Vec::new();
+        reader.open_entry(&entry.path)?.read_to_end(&mut contents)?;
+        if contents.len() as u64 != entry.size {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Size mismatch reading {}", entry.path)));
+        }
+    }
 
-    println!("\n-> Seeking to metadata");
-    file_reader.seek(SeekFrom::Start(VmpakHeader::SIZE as u64))?;
+    println!("\nFile successfully created and read");
+    Ok(())
+}
 
-    let mut metadata_buffer = vec![0u8; read_header.metadata_size as usize];
-    file_reader.read_exact(&mut metadata_buffer)?;
 
-    let metadata_string_read = String::from_utf8(metadata_buffer)
-        .unwrap_or_else(|_| String::from("Metadata is not valid UTF-8"));
+impl VmpakHeader {
+    // Size of the VmpakHeader in bytes
+    pub const SIZE: usize = Self::FIELDS_SIZE + 4;
+    /// Format versions before 3 had no header checksum
+    pub const LEGACY_SIZE: usize = Self::FIELDS_SIZE;
+    const FIELDS_SIZE: usize = 4 + 2 + 2 + 8 + 8 + 1 + 1;
+
+    /// Size of this header on disk, depends on `format_version`
+    pub fn encoded_len(&self) -> usize {
+        if self.format_version >= 3 {
+            Self::SIZE
+        } else {
+            Self::LEGACY_SIZE
+        }
+    }
 
-    println!("-> Metadata:\n{}", metadata_string_read);
+    /// Where the metadata block starts, after the header and the encryption header if there is one
+    pub fn metadata_offset(&self) -> u64 {
+        let encryption_len = if self.flags.contains(VmpakFlags::ENCRYPTED) {
+            VmpakEncryptionHeader::SIZE
+        } else {
+            0
+        };
+        (self.encoded_len() + encryption_len) as u64
+    }
 
-    println!("\n-> Seeking to Index Table (offset: {})", read_header.index_table_offset);
-    file_reader.seek(SeekFrom::Start(read_header.index_table_offset as u64))?;
+    fn encode_fields(&self) -> [u8; Self::FIELDS_SIZE] {
+        let mut buffer = [0u8; Self::FIELDS_SIZE];
+        buffer[0..4].copy_from_slice(&self.magic.to_le_bytes());
+        buffer[4..6].copy_from_slice(&self.format_version.to_le_bytes());
+        buffer[6..8].copy_from_slice(&self.manager_version.to_le_bytes());
+        buffer[8..16].copy_from_slice(&self.index_table_offset.to_le_bytes());
+        buffer[16..24].copy_from_slice(&self.metadata_size.to_le_bytes());
+        buffer[24] = self.flags.bits();
+        buffer[25] = self.reserved;
+        buffer
+    }
 
-    let mut index_table_buffer = vec![0u8; 8];
-    file_reader.read_exact(&mut index_table_buffer)?;
+    /// Encoded header bytes, including the trailing CRC32 from format version 3 onwards
+    pub fn to_bytes(self) -> Vec<u8> {
+        let fields = self.encode_fields();
+        let mut bytes = fields.to_vec();
+        if self.format_version >= 3 {
+            bytes.extend_from_slice(&crc32fast::hash(&fields).to_le_bytes());
+        }
+        bytes
+    }
 
-    println!("-> Index table data (raw): {:?}", index_table_buffer);
+    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&self.to_bytes())
+    }
 
-    println!("\nFile successfully created and read");
-    Ok(())
-}
+    /// Reads and validates a header, refusing packs this build can't read
+    pub fn read<R: Read>(reader: &mut R) -> io::Result<Self> {
+        Self::read_with_policy(reader, UnknownFlagPolicy::default())
+    }
 
+    pub fn read_with_policy<R: Read>(reader: &mut R, policy: UnknownFlagPolicy) -> io::Result<Self> {
+        let mut header = Self::read_unchecked(reader)?;
+        header.check_version()?;
+        header.flags = header.flags.validate(policy)?;
+        header.check_layout()?;
+        Ok(header)
+    }
 
-#[derive(Debug, Clone)]
-#[allow(dead_code)]
-pub struct VmpakMetadata {
-    pub creator: String,
-    pub description: String
-}
+    /// Checks the metadata size is sane and the index sits after the metadata block
+    pub fn check_layout(&self) -> io::Result<()> {
+        if self.metadata_size > VMPAK_MAX_METADATA_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("VMPAK metadata is {} bytes, more than the allowed {VMPAK_MAX_METADATA_SIZE}", self.metadata_size),
+            ));
+        }
 
-impl VmpakHeader {
-    // Size of the VmpakHeader in bytes
-    pub const SIZE: usize = 4 + 2 + 2 + 8 + 8 + 1 + 1;
+        let payload_start = self.metadata_offset() + self.metadata_size;
+        if self.index_table_offset < payload_start {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "VMPAK index offset points into the header or metadata"));
+        }
+        Ok(())
+    }
+
+    /// Checks `format_version` against what this build supports
+    pub fn check_version(&self) -> io::Result<()> {
+        if self.format_version > VMPAK_FORMAT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "VMPAK format version {} is newer than the supported version {}, a newer version of the manager is required",
+                    self.format_version, VMPAK_FORMAT_VERSION
+                ),
+            ));
+        }
+
+        if self.format_version < VMPAK_MIN_FORMAT_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!(
+                    "VMPAK format version {} is older than the oldest supported version {}",
+                    self.format_version, VMPAK_MIN_FORMAT_VERSION
+                ),
+            ));
+        }
+
+        if self.manager_version > VMPAK_MANAGER_VERSION {
+            warn!(
+                "VMPAK was created by a newer manager ({} > {}), some information may be ignored",
+                self.manager_version, VMPAK_MANAGER_VERSION
+            );
+        }
 
-    pub fn write<W: Write>(&self, writer: &mut W) -> io::Result<()> {
-        writer.write_all(&self.magic.to_le_bytes())?;
-        writer.write_all(&self.format_version.to_le_bytes())?;
-        writer.write_all(&self.manager_version.to_le_bytes())?;
-        writer.write_all(&self.index_table_offset.to_le_bytes())?;
-        writer.write_all(&self.metadata_size.to_le_bytes())?;
-        writer.write_all(&self.flags.to_le_bytes())?;
-        writer.write_all(&self.reserved.to_le_bytes())?;
         Ok(())
     }
 
-    pub fn read<R: Read>(reader: &mut R) -> io::Result<Self> {
-        let mut buffer = [0u8; Self::SIZE];
+    /// Parses a header, only checking the magic number and (from version 3) the header checksum.
+    /// Used for inspecting packs we might not support
+    pub fn read_unchecked<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let mut buffer = [0u8; Self::FIELDS_SIZE];
         reader.read_exact(&mut buffer)?;
 
         let mut offset = 0;
@@ -120,11 +226,24 @@ impl VmpakHeader {
         let metadata_size = u64::from_le_bytes(buffer[offset..offset + 8].try_into().unwrap());
         offset += 8;
 
-        let flags = buffer[offset];
+        let flags = VmpakFlags::from_bits_retain(buffer[offset]);
         offset += 1;
 
         let reserved = buffer[offset];
 
+        if format_version >= 3 {
+            let mut checksum = [0u8; 4];
+            reader.read_exact(&mut checksum)?;
+            let expected = u32::from_le_bytes(checksum);
+            let actual = crc32fast::hash(&buffer);
+            if expected != actual {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("VMPAK header is corrupt (checksum {actual:#010x}, expected {expected:#010x})"),
+                ));
+            }
+        }
+
         Ok(Self {
             magic,
             format_version,
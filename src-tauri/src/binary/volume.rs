@@ -0,0 +1,195 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+};
+
+use tracing::warn;
+
+use super::{VmpakHeader, VmpakTrailer};
+
+/// Path of volume `index` of a split pack: `mod.vmpak`, `mod.vmpak.001`, `mod.vmpak.002`, ...
+pub fn volume_path(first: &Path, index: usize) -> PathBuf {
+    if index == 0 {
+        return first.to_path_buf();
+    }
+    let mut name = first.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{index:03}"));
+    first.with_file_name(name)
+}
+
+/// Deletes the `.001`, `.002`, ... volumes next to `first`, leaving `first` itself alone
+pub fn remove_volumes(first: &Path) -> io::Result<()> {
+    for index in 1.. {
+        match std::fs::remove_file(volume_path(first, index)) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::NotFound => break,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Splits everything written to it across volumes of at most `volume_size` bytes.
+/// The volumes together are byte for byte the pack a single file would have held
+#[allow(dead_code)]
+pub struct VolumeWriter {
+    first: PathBuf,
+    volume_size: u64,
+    volumes: Vec<File>,
+    position: u64,
+    len: u64,
+}
+
+#[allow(dead_code)]
+impl VolumeWriter {
+    pub fn create(first: &Path, volume_size: u64) -> io::Result<Self> {
+        if volume_size == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "VMPAK volume size must be above zero"));
+        }
+        // Leftovers from an earlier, bigger split would otherwise get picked up as part of this one
+        remove_volumes(first)?;
+
+        Ok(Self {
+            first: first.to_path_buf(),
+            volume_size,
+            volumes: vec![File::create(first)?],
+            position: 0,
+            len: 0,
+        })
+    }
+
+    /// Every volume written so far, in order
+    pub fn paths(&self) -> Vec<PathBuf> {
+        (0..self.volumes.len()).map(|index| volume_path(&self.first, index)).collect()
+    }
+
+    fn volume(&mut self, index: usize) -> io::Result<&mut File> {
+        while self.volumes.len() <= index {
+            let path = volume_path(&self.first, self.volumes.len());
+            self.volumes.push(OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)?);
+        }
+        Ok(&mut self.volumes[index])
+    }
+}
+
+impl Write for VolumeWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let index = (self.position / self.volume_size) as usize;
+        let offset = self.position % self.volume_size;
+        let room = (self.volume_size - offset).min(buf.len() as u64) as usize;
+
+        let volume = self.volume(index)?;
+        volume.seek(SeekFrom::Start(offset))?;
+        let written = volume.write(&buf[..room])?;
+
+        self.position += written as u64;
+        self.len = self.len.max(self.position);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.volumes.iter_mut().try_for_each(|volume| volume.flush())
+    }
+}
+
+impl Seek for VolumeWriter {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.position = resolve_seek(pos, self.position, self.len)?;
+        Ok(self.position)
+    }
+}
+
+fn resolve_seek(pos: SeekFrom, current: u64, len: u64) -> io::Result<u64> {
+    let target = match pos {
+        SeekFrom::Start(offset) => Some(offset),
+        SeekFrom::Current(delta) => current.checked_add_signed(delta),
+        SeekFrom::End(delta) => len.checked_add_signed(delta),
+    };
+    target.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Seek before the start of a VMPAK"))
+}
+
+/// Reads a pack that may be split into volumes as one continuous file.
+/// A pack that was never split is just a single volume
+pub struct VolumeReader {
+    volumes: Vec<File>,
+    /// Offset each volume starts at in the logical file
+    starts: Vec<u64>,
+    position: u64,
+    len: u64,
+}
+
+#[allow(dead_code)]
+impl VolumeReader {
+    /// Opens `first` and, if it's only the start of a split pack, the `.001`, `.002`, ... volumes after it.
+    /// Volumes next to a pack that's already whole are stale leftovers and get ignored
+    pub fn open(first: &Path) -> io::Result<Self> {
+        let mut head = File::open(first)?;
+        let split = Self::is_first_volume(&mut head);
+        let mut volumes = vec![head];
+        if split {
+            loop {
+                match File::open(volume_path(first, volumes.len())) {
+                    Ok(file) => volumes.push(file),
+                    Err(e) if e.kind() == io::ErrorKind::NotFound => break,
+                    Err(e) => return Err(e),
+                }
+            }
+        } else if volume_path(first, 1).exists() {
+            warn!("Ignoring {}, {} is a whole pack by itself", volume_path(first, 1).display(), first.display());
+        }
+
+        let mut starts = Vec::with_capacity(volumes.len());
+        let mut len = 0u64;
+        for volume in &volumes {
+            starts.push(len);
+            len += volume.metadata()?.len();
+        }
+
+        Ok(Self { volumes, starts, position: 0, len })
+    }
+
+    pub fn volume_count(&self) -> usize {
+        self.volumes.len()
+    }
+
+    /// A pack is split when it doesn't end in a trailer. Packs from before trailers (format version 3) are never split,
+    /// and anything without a readable header is left to the pack reader to refuse
+    fn is_first_volume(file: &mut File) -> bool {
+        let header = match VmpakHeader::read_unchecked(file) {
+            Ok(header) if header.format_version >= 3 => header,
+            _ => return false,
+        };
+        VmpakTrailer::read_from_end(file, header.format_version).is_err()
+    }
+}
+
+impl Read for VolumeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() || self.position >= self.len {
+            return Ok(0);
+        }
+        let index = self.starts.partition_point(|&start| start <= self.position) - 1;
+        let end = self.starts.get(index + 1).copied().unwrap_or(self.len);
+        let want = (end - self.position).min(buf.len() as u64) as usize;
+
+        let volume = &mut self.volumes[index];
+        volume.seek(SeekFrom::Start(self.position - self.starts[index]))?;
+        let read = volume.read(&mut buf[..want])?;
+        if read == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "VMPAK volume shrank while it was being read"));
+        }
+        self.position += read as u64;
+        Ok(read)
+    }
+}
+
+impl Seek for VolumeReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.position = resolve_seek(pos, self.position, self.len)?;
+        Ok(self.position)
+    }
+}
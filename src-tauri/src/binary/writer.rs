@@ -0,0 +1,258 @@
+use std::{collections::HashMap, fs::{self, File}, io::{self, Read, Seek, SeekFrom, Write}, path::Path, time::UNIX_EPOCH};
+
+use ed25519_dalek::SigningKey;
+
+use super::{
+    archive_digest, seal, write_index, Binding, write_signatures, ChunkRef, Chunker, Compression, Crc32Reader, DigestWriter,
+    EncryptWriter, EntryAttributes, VmpakDedup, VmpakEncryption, VmpakEntry, VmpakFlags, VmpakHeader, VmpakMetadata, VmpakSignature, VmpakTrailer,
+    VMPAK_FORMAT_VERSION, VMPAK_MAGIC, VMPAK_MANAGER_VERSION,
+};
+
+/// Builds a VMPAK archive in a single forward pass.
+///
+/// Layout: header, encryption header (if encrypted), metadata, entry payloads, index table, signatures, trailer. The header is written as a
+/// placeholder and patched in `finish()` once the index offset is known, everything after it
+/// is hashed on the way through for the archive digest. Identical data is only written once, see `VmpakDedup`.
+pub struct VmpakWriter<W: Write + Seek> {
+    inner: DigestWriter<W>,
+    header: VmpakHeader,
+    entries: Vec<VmpakEntry>,
+    compression: Compression,
+    signers: Vec<SigningKey>,
+    encryption: Option<VmpakEncryption>,
+    dedup: VmpakDedup,
+    /// Stored chunks by BLAKE3 of their contents and the compression they were stored with
+    chunks: HashMap<([u8; 32], (u8, i8)), ChunkRef>,
+    /// Chunk tables already written, by BLAKE3 of the table
+    chunk_tables: HashMap<[u8; 32], u64>,
+}
+
+#[allow(dead_code)]
+impl<W: Write + Seek> VmpakWriter<W> {
+    pub fn new(inner: W, metadata: &VmpakMetadata, flags: VmpakFlags) -> io::Result<Self> {
+        Self::with_encryption(inner, metadata, flags, None)
+    }
+
+    /// Like `new`, but every payload (and unless `public_index` is set, the metadata and index) gets encrypted
+    pub fn with_encryption(mut inner: W, metadata: &VmpakMetadata, mut flags: VmpakFlags, encryption: Option<VmpakEncryption>) -> io::Result<Self> {
+        let mut metadata = metadata.to_bytes()?;
+        if let Some(encryption) = &encryption {
+            flags = flags | encryption.flags();
+            if !encryption.public_index {
+                metadata = seal(&encryption.key, &metadata, Binding::Metadata)?;
+            }
+        }
+
+        let header = VmpakHeader {
+            magic: VMPAK_MAGIC,
+            format_version: VMPAK_FORMAT_VERSION,
+            manager_version: VMPAK_MANAGER_VERSION,
+            index_table_offset: 0,
+            metadata_size: metadata.len() as u64,
+            flags,
+            reserved: 0
+        };
+
+        inner.seek(SeekFrom::Start(0))?;
+        header.write(&mut inner)?;
+
+        let mut inner = DigestWriter::new(inner);
+        if let Some(encryption) = &encryption {
+            encryption.header.write(&mut inner)?;
+        }
+        inner.write_all(&metadata)?;
+
+        Ok(Self {
+            inner,
+            header,
+            entries: Vec::new(),
+            compression: Compression::default(),
+            signers: Vec::new(),
+            encryption,
+            dedup: VmpakDedup::default(),
+            chunks: HashMap::new(),
+            chunk_tables: HashMap::new(),
+        })
+    }
+
+    /// Compression used for entries that aren't already compressed assets
+    pub fn set_compression(&mut self, compression: Compression) {
+        self.compression = compression;
+    }
+
+    /// How identical data is shared between entries, `Files` unless changed
+    pub fn set_dedup(&mut self, dedup: VmpakDedup) {
+        self.dedup = dedup;
+    }
+
+    /// Signs the archive digest with `key` when the pack is finished
+    pub fn add_signer(&mut self, key: SigningKey) {
+        self.signers.push(key);
+    }
+
+    /// Current absolute offset in the output
+    fn position(&self) -> u64 {
+        self.header.encoded_len() as u64 + self.inner.written()
+    }
+
+    /// Adds an entry, picking `Stored` for already-compressed assets (png, ogg, ...)
+    pub fn add_entry<R: Read>(&mut self, path: &str, source: &mut R) -> io::Result<&VmpakEntry> {
+        let compression = Compression::for_path(path, self.compression);
+        self.add_entry_with(path, source, compression)
+    }
+
+    pub fn add_entry_with<R: Read>(&mut self, path: &str, source: &mut R, compression: Compression) -> io::Result<&VmpakEntry> {
+        self.add_entry_with_attributes(path, source, Some(compression), EntryAttributes::default())
+    }
+
+    /// `compression` of `None` picks one from the path like `add_entry` does
+    pub fn add_entry_with_attributes<R: Read>(
+        &mut self,
+        path: &str,
+        source: &mut R,
+        compression: Option<Compression>,
+        attributes: EntryAttributes,
+    ) -> io::Result<&VmpakEntry> {
+        let compression = compression.unwrap_or_else(|| Compression::for_path(path, self.compression));
+        let path = path.replace('\\', "/");
+        if self.entries.iter().any(|e| e.path == path) {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("Duplicate VMPAK entry: {path}")));
+        }
+
+        let mut source = Crc32Reader::new(source);
+        let (offset, compressed_size, size, chunked) = if self.dedup == VmpakDedup::Off {
+            let offset = self.position();
+            let size = self.write_payload(&mut source, compression)?;
+            (offset, self.position() - offset, size, false)
+        } else {
+            self.write_chunks(&mut source, compression)?
+        };
+        self.entries.push(VmpakEntry {
+            path,
+            offset,
+            compressed_size,
+            size,
+            compression,
+            checksum: Some(source.finish()),
+            attributes,
+            chunked,
+            encrypted: self.encryption.is_some(),
+            bound: self.encryption.is_some(),
+        });
+        Ok(self.entries.last().unwrap())
+    }
+
+    /// Compresses (and encrypts) `source` at the current position, returns the uncompressed size
+    fn write_payload<R: Read>(&mut self, source: &mut R, compression: Compression) -> io::Result<u64> {
+        match &self.encryption {
+            Some(encryption) => {
+                let binding = Binding::Payload(self.position());
+                let mut sink = EncryptWriter::new(&mut self.inner, &encryption.key, binding)?;
+                let size = compression.compress(source, &mut sink)?;
+                sink.finish()?;
+                Ok(size)
+            }
+            None => compression.compress(source, &mut self.inner),
+        }
+    }
+
+    /// Writes whichever chunks of `source` aren't stored yet. A single chunk becomes the entry's payload directly,
+    /// anything longer gets a chunk table. Returns the entry's offset, stored size, size and whether it's chunked
+    fn write_chunks<R: Read>(&mut self, source: &mut R, compression: Compression) -> io::Result<(u64, u64, u64, bool)> {
+        let mut chunker = Chunker::new(source, self.dedup);
+        let mut refs = Vec::new();
+        while let Some(chunk) = chunker.next_chunk()? {
+            refs.push(self.store_chunk(&chunk, compression)?);
+        }
+        if refs.is_empty() {
+            refs.push(self.store_chunk(&[], compression)?);
+        }
+        let size = refs.iter().map(|c| c.size).sum();
+
+        if let [chunk] = refs[..] {
+            return Ok((chunk.offset, chunk.compressed_size, size, false));
+        }
+
+        let table = ChunkRef::encode_table(&refs);
+        let table_hash = *blake3::hash(&table).as_bytes();
+        let offset = match self.chunk_tables.get(&table_hash) {
+            Some(&offset) => offset,
+            None => {
+                let offset = self.position();
+                self.inner.write_all(&table)?;
+                self.chunk_tables.insert(table_hash, offset);
+                offset
+            }
+        };
+        Ok((offset, table.len() as u64, size, true))
+    }
+
+    fn store_chunk(&mut self, data: &[u8], compression: Compression) -> io::Result<ChunkRef> {
+        let key = (*blake3::hash(data).as_bytes(), compression.to_raw());
+        if let Some(chunk) = self.chunks.get(&key) {
+            return Ok(*chunk);
+        }
+
+        let offset = self.position();
+        let size = self.write_payload(&mut &data[..], compression)?;
+        let chunk = ChunkRef { offset, compressed_size: self.position() - offset, size };
+        self.chunks.insert(key, chunk);
+        Ok(chunk)
+    }
+
+    /// Adds a file from disk, keeping its modification time and executable bit
+    pub fn add_file(&mut self, path: &str, file: &Path) -> io::Result<&VmpakEntry> {
+        let mut source = File::open(file)?;
+        let attributes = file_attributes(&source.metadata()?);
+        self.add_entry_with_attributes(path, &mut source, None, attributes)
+    }
+
+    /// Writes the index table, signatures and trailer, patches the header and hands back the underlying writer
+    pub fn finish(mut self) -> io::Result<W> {
+        self.header.index_table_offset = self.position();
+
+        let mut index = Vec::new();
+        write_index(&mut index, &self.entries)?;
+        if let Some(encryption) = self.encryption.as_ref().filter(|e| !e.public_index) {
+            index = seal(&encryption.key, &index, Binding::Index)?;
+        }
+        self.inner.write_all(&index)?;
+
+        let (mut inner, body_digest) = self.inner.finish();
+        let digest = archive_digest(&self.header, &body_digest);
+        let signatures: Vec<VmpakSignature> = self.signers.iter().map(|key| VmpakSignature::sign(key, &digest)).collect();
+
+        let trailer = VmpakTrailer {
+            index_checksum: crc32fast::hash(&index),
+            signatures_size: write_signatures(&mut inner, &signatures)?,
+            digest,
+        };
+        trailer.write(&mut inner)?;
+        let end = inner.stream_position()?;
+
+        inner.seek(SeekFrom::Start(0))?;
+        self.header.write(&mut inner)?;
+        inner.seek(SeekFrom::Start(end))?;
+        inner.flush()?;
+
+        Ok(inner)
+    }
+}
+
+fn file_attributes(metadata: &fs::Metadata) -> EntryAttributes {
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |duration| duration.as_secs());
+
+    #[cfg(unix)]
+    let executable = {
+        use std::os::unix::fs::PermissionsExt;
+        metadata.permissions().mode() & 0o111 != 0
+    };
+    #[cfg(not(unix))]
+    let executable = false;
+
+    EntryAttributes { modified, executable }
+}
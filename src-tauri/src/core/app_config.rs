@@ -0,0 +1,195 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tracing::{error, info, warn};
+
+use super::deploy::DeployMethod;
+use crate::binary::{check_signatures, is_vmpak, InstallRoot, SignatureStatus, VolumeReader};
+
+/// What to do when installing a VMPAK that isn't signed by a trusted key.
+/// Packs with an invalid signature are always rejected
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SignaturePolicy {
+    #[default]
+    AllowUnsigned,
+    Warn,
+    Reject,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TrustedKey {
+    /// Who the key belongs to, only used for display
+    pub name: String,
+    /// Hex encoded Ed25519 public key
+    pub public_key: String,
+}
+
+/// Where a game lives on disk. Mods are only installed by the manager itself once this is known,
+/// otherwise installing is left to the game provider
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct GamePaths {
+    pub game_dir: PathBuf,
+    #[serde(default)]
+    pub config_dir: Option<PathBuf>,
+    #[serde(default)]
+    pub save_dir: Option<PathBuf>,
+    /// Set for games that read their plugin or mod order from a file
+    #[serde(default)]
+    pub load_order_file: Option<LoadOrderFile>,
+    /// How mods get from the staging store into the game
+    #[serde(default)]
+    pub deploy_method: DeployMethod,
+    /// Version of the installed game, install manifests can have files for some versions only
+    #[serde(default)]
+    pub game_version: Option<String>,
+}
+
+/// A file the game reads its load order from, rewritten whenever the order or the enabled mods change.
+/// Only used for games whose mod provider supports one
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct LoadOrderFile {
+    #[serde(default)]
+    pub root: InstallRoot,
+    /// `/` separated, relative to the root
+    pub path: String,
+    /// The file lists the names of plugins with these extensions (e.g. `esp`), it isn't written without any
+    #[serde(default)]
+    pub plugin_extensions: Vec<String>,
+    /// Put in front of every line, e.g. `*` for games that mark active plugins that way
+    #[serde(default)]
+    pub line_prefix: String,
+}
+
+impl GamePaths {
+    /// The directories an install manifest can target
+    pub fn install_roots(&self) -> BTreeMap<InstallRoot, PathBuf> {
+        let roots = [
+            (InstallRoot::Game, Some(&self.game_dir)),
+            (InstallRoot::Config, self.config_dir.as_ref()),
+            (InstallRoot::Save, self.save_dir.as_ref()),
+        ];
+        roots.into_iter().filter_map(|(root, dir)| Some((root, dir?.clone()))).collect()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AppConfig {
+    #[serde(default)]
+    pub signature_policy: SignaturePolicy,
+    #[serde(default)]
+    pub trusted_keys: Vec<TrustedKey>,
+    /// Keyring names of pack keys to try on encrypted packs that have no key stored under their mod id
+    #[serde(default)]
+    pub pack_keys: Vec<String>,
+    /// Keyed by game id
+    #[serde(default)]
+    pub game_paths: BTreeMap<String, GamePaths>,
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Each test points its thread at its own scratch directory, for both data and config
+    pub(super) static TEST_DIR: std::cell::RefCell<Option<PathBuf>> = const { std::cell::RefCell::new(None) };
+}
+
+/// Where the manager keeps downloads, installed mod records and backups
+pub fn app_data_dir() -> PathBuf {
+    #[cfg(test)]
+    if let Some(dir) = TEST_DIR.with_borrow(Clone::clone) {
+        return dir.join("data");
+    }
+    dirs::data_local_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join("me.ghoul.void_mod_manager")
+}
+
+impl AppConfig {
+    pub fn path() -> PathBuf {
+        #[cfg(test)]
+        if let Some(dir) = TEST_DIR.with_borrow(Clone::clone) {
+            return dir.join("config.json");
+        }
+        dirs::config_dir()
+            .unwrap_or_else(|| PathBuf::from("/tmp"))
+            .join("me.ghoul.void_mod_manager")
+            .join("config.json")
+    }
+
+    /// Loads the config, falling back to defaults if it's missing or unreadable
+    pub fn load() -> Self {
+        Self::try_load().unwrap_or_else(|e| {
+            error!("Using the default config: {}", e);
+            Self::default()
+        })
+    }
+
+    /// Loads the config, a missing one is the default. Use this before saving, so a broken config isn't overwritten
+    pub fn try_load() -> io::Result<Self> {
+        let path = Self::path();
+        let contents = match fs::read(&path) {
+            Ok(c) => c,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(io::Error::new(e.kind(), format!("Failed to read config {}: {e}", path.display()))),
+        };
+
+        serde_json::from_slice(&contents)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Config {} is invalid: {e}", path.display())))
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let path = Self::path();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let contents = serde_json::to_vec_pretty(self).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        fs::write(path, contents)
+    }
+
+    /// Decoded public keys of the trust store, malformed entries are skipped
+    pub fn trusted_public_keys(&self) -> Vec<[u8; 32]> {
+        self.trusted_keys
+            .iter()
+            .filter_map(|key| {
+                let decoded = hex::decode(&key.public_key).ok().and_then(|bytes| <[u8; 32]>::try_from(bytes).ok());
+                if decoded.is_none() {
+                    warn!("Ignoring malformed trusted key '{}'", key.name);
+                }
+                decoded
+            })
+            .collect()
+    }
+
+    /// Applies the configured signature policy to a package about to be installed, `PermissionDenied` if it's refused.
+    /// A config that can't be read refuses every package, its defaults would let unsigned ones through
+    pub fn check_package(package: &Path) -> io::Result<()> {
+        let config = Self::try_load()
+            .map_err(|e| io::Error::new(io::ErrorKind::PermissionDenied, format!("Can't apply the signature policy: {e}")))?;
+        let status = match is_vmpak(package)? {
+            true => check_signatures(&mut VolumeReader::open(package)?, &config.trusted_public_keys())?,
+            // Anything that isn't a VMPAK can't carry a signature
+            false => SignatureStatus::Unsigned,
+        };
+
+        let refuse = |reason: String| Err(io::Error::new(io::ErrorKind::PermissionDenied, reason));
+        match (status, config.signature_policy) {
+            (SignatureStatus::Trusted(key), _) => {
+                info!("Package signed by trusted key {}", hex::encode(key));
+                Ok(())
+            }
+            (SignatureStatus::Invalid(reason), _) => refuse(format!("The package's signature is invalid: {reason}")),
+            (_, SignaturePolicy::AllowUnsigned) => Ok(()),
+            (status, SignaturePolicy::Warn) => {
+                warn!("Installing package that isn't signed by a trusted key ({:?})", status);
+                Ok(())
+            }
+            (status, SignaturePolicy::Reject) => refuse(format!("The package isn't signed by a trusted key ({status:?})")),
+        }
+    }
+}
@@ -0,0 +1,50 @@
+use std::{
+    fs::File,
+    io::{self, BufWriter},
+    path::Path,
+};
+
+use lib_vmm::traits::discovery::{ModExtendedMetadata, ModSummary};
+
+use super::ModInstaller;
+use crate::binary::{is_asset_path, VmpakFlags, VmpakReader, VmpakWriter};
+
+/// Metadata and `.vmpak/` assets of a mod installed from a VMPAK, kept in its staging directory so the mod can
+/// still be shown once the package is gone
+pub(super) const PACK_ASSETS: &str = "assets.vmpak";
+
+/// Copies the metadata and assets of `reader`'s pack into a pack of their own at `path`
+pub(super) fn keep_assets(reader: &mut VmpakReader, path: &Path) -> io::Result<()> {
+    let mut writer = VmpakWriter::new(BufWriter::new(File::create(path)?), reader.metadata(), VmpakFlags::empty())?;
+    let assets: Vec<String> = reader.entries().iter().map(|e| e.path.clone()).filter(|p| is_asset_path(p)).collect();
+    for asset in &assets {
+        writer.add_entry(asset, &mut reader.open_entry(asset)?)?;
+    }
+    writer.finish()?.into_inner().map_err(|e| e.into_error())?;
+    Ok(())
+}
+
+impl ModInstaller {
+    fn pack_assets(game_id: &str, mod_id: &str) -> io::Result<Option<VmpakReader>> {
+        match VmpakReader::open(&Self::staging_dir(game_id, mod_id)?.join(PACK_ASSETS)) {
+            Ok(reader) => Ok(Some(reader)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// The installed `mod_id` as its pack describes it, `None` if it wasn't installed from a VMPAK
+    pub fn pack_summary(game_id: &str, mod_id: &str, locale: Option<&str>) -> io::Result<Option<ModSummary>> {
+        Self::pack_assets(game_id, mod_id)?.map(|mut reader| reader.summary(locale)).transpose()
+    }
+
+    /// Readme, screenshots and version of the installed `mod_id`, `None` unless its pack ships assets
+    pub fn pack_details(game_id: &str, mod_id: &str) -> io::Result<Option<ModExtendedMetadata>> {
+        let Some(mut reader) = Self::pack_assets(game_id, mod_id)?.filter(|reader| !reader.assets().is_empty()) else {
+            return Ok(None);
+        };
+        let mut details = reader.extended_metadata()?;
+        details.installed = true;
+        Ok(Some(details))
+    }
+}
@@ -0,0 +1,119 @@
+use std::{
+    collections::BTreeSet,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+};
+
+use super::{
+    app_data_dir, deploy,
+    installed_registry::check_game_id,
+    mod_installer::{checked_relative, root_name},
+    InstalledRegistry, ProfileStore,
+};
+use crate::binary::{InstallRoot, VolumeReader};
+
+/// Game files that mods overwrote, one directory per game, each file named after its BLAKE3 hash.
+/// The same original overwritten by several mods is only stored once
+pub struct BackupStore;
+
+impl BackupStore {
+    pub fn dir(game_id: &str) -> io::Result<PathBuf> {
+        check_game_id(game_id)?;
+        Ok(app_data_dir().join("backups").join(game_id))
+    }
+
+    fn path(game_id: &str, hash: &str) -> io::Result<PathBuf> {
+        if hash.len() != 64 || !hash.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid backup hash '{hash}'")));
+        }
+        Ok(Self::dir(game_id)?.join(hash))
+    }
+
+    /// Copies `file` into the store unless an identical backup is already there, returns its hash
+    pub fn store(game_id: &str, file: &Path) -> io::Result<String> {
+        Self::store_hashed(game_id, file, hash_file(file)?)
+    }
+
+    /// `store` for a file whose hash is known already
+    pub(super) fn store_hashed(game_id: &str, file: &Path, hash: String) -> io::Result<String> {
+        let path = Self::path(game_id, &hash)?;
+        if path.is_file() {
+            return Ok(hash);
+        }
+
+        fs::create_dir_all(Self::dir(game_id)?)?;
+        let temp = path.with_extension("tmp");
+        fs::copy(file, &temp)?;
+        // Journals point at backups, so one has to be on disk before the journal does
+        File::options().write(true).open(&temp)?.sync_all()?;
+        fs::rename(temp, path)?;
+        Ok(hash)
+    }
+
+    /// Deletes the backups no installed file, deployed override or original refers to, returns how many.
+    /// Only call it while no transaction is open on the game, its journal may point at any of them
+    pub(super) fn collect_garbage(game_id: &str) -> io::Result<usize> {
+        let entries = match fs::read_dir(Self::dir(game_id)?) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        let mut referenced: BTreeSet<String> = BTreeSet::new();
+        referenced.extend(InstalledRegistry::list(game_id)?.into_iter().flat_map(|m| m.files).filter_map(|f| f.replaced));
+        referenced.extend(ProfileStore::deployed_overrides(game_id)?.into_iter().filter_map(|f| f.replaced));
+        referenced.extend(Self::list_originals(game_id)?.into_iter().filter_map(|o| o.hash));
+
+        let mut removed = 0;
+        for entry in entries {
+            let entry = entry?;
+            let name = entry.file_name();
+            let is_backup = name.len() == 64 && name.to_str().is_some_and(|n| n.bytes().all(|b| b.is_ascii_hexdigit()));
+            if is_backup && !name.to_str().is_some_and(|n| referenced.contains(n)) {
+                fs::remove_file(entry.path())?;
+                removed += 1;
+            }
+        }
+        Ok(removed)
+    }
+
+    /// Puts the backup with `hash` back at `destination`
+    pub fn restore(game_id: &str, hash: &str, destination: &Path) -> io::Result<()> {
+        let path = Self::path(game_id, hash)?;
+        if !path.is_file() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("No backup {hash} for {}", destination.display())));
+        }
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // A link into the staging store must not be written through
+        deploy::clear(destination)?;
+        fs::copy(path, destination)?;
+        Ok(())
+    }
+
+    /// Copies a file the user changed after a mod placed it to `modified/<root>/<path>` before the mod takes it
+    /// out again, replacing an earlier copy of the same file. Returns where it went
+    pub fn keep_modified(game_id: &str, root: InstallRoot, path: &str, file: &Path) -> io::Result<PathBuf> {
+        let kept = Self::dir(game_id)?.join("modified").join(root_name(root)).join(checked_relative(path)?);
+        if let Some(parent) = kept.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::copy(file, &kept)?;
+        Ok(kept)
+    }
+}
+
+/// Hex BLAKE3 of a file's contents
+pub fn hash_file(path: &Path) -> io::Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
+
+/// Hex BLAKE3 of a package, covering every volume of a split VMPAK
+pub fn hash_package(path: &Path) -> io::Result<String> {
+    let mut hasher = blake3::Hasher::new();
+    io::copy(&mut VolumeReader::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().to_hex().to_string())
+}
@@ -0,0 +1,70 @@
+use std::{collections::BTreeMap, io};
+
+use serde::Serialize;
+use specta::Type;
+
+use super::{InstalledMod, InstalledRegistry, ModInstaller};
+use crate::binary::InstallRoot;
+
+/// A file more than one mod writes
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Type)]
+pub struct FileConflict {
+    pub root: InstallRoot,
+    pub path: String,
+    /// Every mod providing the file, in the order they're deployed
+    pub mods: Vec<String>,
+    /// The mod whose copy ends up in the game, the last one deployed
+    pub winner: String,
+}
+
+/// Overlapping files of `mods`, which have to be in deploy order
+fn find_conflicts<'a>(mods: impl Iterator<Item = &'a InstalledMod>) -> Vec<FileConflict> {
+    let mut providers: BTreeMap<(InstallRoot, &str), Vec<String>> = BTreeMap::new();
+    for installed in mods {
+        for file in &installed.files {
+            let ids = providers.entry((file.root, &file.path)).or_default();
+            if !ids.contains(&installed.mod_id) {
+                ids.push(installed.mod_id.clone());
+            }
+        }
+    }
+
+    providers
+        .into_iter()
+        .filter(|(_, mods)| mods.len() > 1)
+        .map(|((root, path), mods)| FileConflict { root, path: path.to_string(), winner: mods[mods.len() - 1].clone(), mods })
+        .collect()
+}
+
+impl ModInstaller {
+    /// Files the enabled mods of `game_id` currently fight over
+    pub fn conflicts(game_id: &str) -> io::Result<Vec<FileConflict>> {
+        let mods = InstalledRegistry::list(game_id)?;
+        Ok(find_conflicts(mods.iter().filter(|m| m.enabled)))
+    }
+
+    /// Conflicts `mod_id` has with the enabled mods. A disabled mod is checked as if it was enabled at its place in
+    /// the load order, so this is what enabling it would overwrite. An update staged for it is checked instead of the installed copy
+    pub fn conflicts_of(game_id: &str, mod_id: &str) -> io::Result<Vec<FileConflict>> {
+        if let Some(staged) = Self::staged(game_id, mod_id)? {
+            return Self::conflicts_of_staged(game_id, &staged);
+        }
+        let mods = InstalledRegistry::list(game_id)?;
+        if !mods.iter().any(|m| m.mod_id == mod_id) {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("{mod_id} isn't installed")));
+        }
+
+        // Enabling a mod keeps its place, so later mods still win over it
+        let conflicts = find_conflicts(mods.iter().filter(|m| m.enabled || m.mod_id == mod_id));
+        Ok(conflicts.into_iter().filter(|c| c.mods.iter().any(|id| id == mod_id)).collect())
+    }
+
+    /// Conflicts the staged `staged` would have with the enabled mods once it takes the place of the installed copy
+    pub fn conflicts_of_staged(game_id: &str, staged: &InstalledMod) -> io::Result<Vec<FileConflict>> {
+        let mods = InstalledRegistry::list(game_id)?;
+        let slot = mods.iter().position(|m| m.mod_id == staged.mod_id).unwrap_or(mods.len());
+        let others = |m: &&InstalledMod| m.enabled && m.mod_id != staged.mod_id;
+        let order = mods[..slot].iter().filter(others).chain([staged]).chain(mods[slot..].iter().filter(others));
+        Ok(find_conflicts(order).into_iter().filter(|c| c.mods.contains(&staged.mod_id)).collect())
+    }
+}
@@ -0,0 +1,132 @@
+use std::{
+    fs::{self, File},
+    io,
+    path::Path,
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tracing::{debug, info};
+
+use super::{BackupStore, GamePaths, InstalledRegistry, Journal, ModInstaller, ProfileStore};
+
+/// How staged files get into the game directory. Links keep a single copy in the staging store,
+/// anything the filesystem can't do falls back to copying
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, Type)]
+#[serde(rename_all = "snake_case")]
+pub enum DeployMethod {
+    #[default]
+    Copy,
+    /// Only works when the staging store and the game are on the same filesystem.
+    /// The game writing to a linked file changes the staged copy too
+    Hardlink,
+    /// Needs developer mode or admin rights on Windows
+    Symlink,
+    /// Copy-on-write clone, on Btrfs, XFS and APFS
+    Reflink,
+}
+
+/// Removes whatever is at `path` without following links, so writing there never reaches a staged file
+pub(super) fn clear(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.is_dir() => {
+            Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{} is a directory", path.display())))
+        }
+        Ok(_) => fs::remove_file(path),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+#[cfg(unix)]
+fn symlink(staged: &Path, destination: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(staged, destination)
+}
+
+#[cfg(windows)]
+fn symlink(staged: &Path, destination: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_file(staged, destination)
+}
+
+#[cfg(not(any(unix, windows)))]
+fn symlink(_staged: &Path, _destination: &Path) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "Symlinks aren't supported on this platform"))
+}
+
+#[cfg(target_os = "linux")]
+fn reflink(staged: &Path, destination: &Path) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+    // _IOW(0x94, 9, int) from linux/fs.h
+    const FICLONE: libc::Ioctl = 0x4004_9409;
+
+    let source = File::open(staged)?;
+    let target = File::options().write(true).create_new(true).open(destination)?;
+    // SAFETY: both descriptors are open for the duration of the call and FICLONE takes the source fd by value
+    if unsafe { libc::ioctl(target.as_raw_fd(), FICLONE, source.as_raw_fd()) } != 0 {
+        let error = io::Error::last_os_error();
+        drop(target);
+        let _ = fs::remove_file(destination);
+        return Err(error);
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+fn reflink(staged: &Path, destination: &Path) -> io::Result<()> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let invalid = |_| io::Error::new(io::ErrorKind::InvalidInput, "Path contains a NUL byte");
+    let source = CString::new(staged.as_os_str().as_bytes()).map_err(invalid)?;
+    let target = CString::new(destination.as_os_str().as_bytes()).map_err(invalid)?;
+    // SAFETY: both are valid NUL terminated paths
+    if unsafe { libc::clonefile(source.as_ptr(), target.as_ptr(), 0) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn reflink(_staged: &Path, _destination: &Path) -> io::Result<()> {
+    Err(io::Error::new(io::ErrorKind::Unsupported, "Reflinks aren't supported on this platform"))
+}
+
+/// Puts `staged` at `destination` using `method`, replacing whatever was there.
+/// Returns the method that was actually used, which is `Copy` if linking didn't work
+pub(super) fn place(staged: &Path, destination: &Path, method: DeployMethod) -> io::Result<DeployMethod> {
+    clear(destination)?;
+    let linked = match method {
+        DeployMethod::Copy => None,
+        DeployMethod::Hardlink => Some(fs::hard_link(staged, destination)),
+        DeployMethod::Symlink => Some(symlink(staged, destination)),
+        DeployMethod::Reflink => Some(reflink(staged, destination)),
+    };
+    match linked {
+        Some(Ok(())) => return Ok(method),
+        Some(Err(e)) => {
+            debug!("Can't {:?} {} into the game, copying it instead: {}", method, staged.display(), e);
+            clear(destination)?;
+        }
+        None => {}
+    }
+
+    fs::copy(staged, destination)?;
+    File::options().write(true).open(destination)?.set_modified(fs::metadata(staged)?.modified()?)?;
+    Ok(DeployMethod::Copy)
+}
+
+impl ModInstaller {
+    /// Withdraws every managed mod and the profile's config overrides, then puts back the original of every file
+    /// the manager wrote, the load order file included. Mods stay installed and staged, but disabled
+    pub fn purge(game_id: &str, paths: &GamePaths) -> io::Result<()> {
+        Journal::run(game_id, "purge", || {
+            ProfileStore::withdraw_deployed_overrides(game_id, paths)?;
+            let current = InstalledRegistry::list(game_id)?;
+            let mut desired = current.clone();
+            desired.iter_mut().filter(|m| m.managed).for_each(|m| m.enabled = false);
+            Self::apply_order(game_id, &current, desired, paths)?;
+            BackupStore::restore_unowned_originals(game_id, paths)?;
+            info!("Purged all mods from {}", game_id);
+            Ok(())
+        })
+    }
+}
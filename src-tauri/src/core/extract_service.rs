@@ -0,0 +1,83 @@
+use std::{io, path::Path, sync::OnceLock};
+
+use serde::{Deserialize, Serialize};
+use tauri::{AppHandle, Emitter};
+use tracing::{debug, info, warn};
+
+use crate::binary::{extract_archive, ExtractLimits};
+
+static HANDLE: OnceLock<AppHandle> = OnceLock::new();
+
+#[derive(Serialize, Deserialize, Clone)]
+struct ExtractionStartedPayload {
+    mod_id: String,
+    filename: String,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct ExtractionProgressPayload {
+    mod_id: String,
+    percent: u8,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct ExtractionCompletedPayload {
+    mod_id: String,
+    path: String,
+}
+
+/// Unpacks downloaded archives for every provider, within `ExtractLimits` and reporting progress to the frontend
+/// with `extraction_started`, `extraction_progress` and `extraction_completed` events, like downloads do
+pub struct ExtractService;
+
+impl ExtractService {
+    pub fn set_handle(handle: AppHandle) {
+        if HANDLE.set(handle).is_err() {
+            warn!("Extraction events already have a handle");
+        } else {
+            debug!("Extraction events can be emitted now");
+        }
+    }
+
+    fn emit<S: Serialize + Clone>(event: &str, payload: S) {
+        if let Some(handle) = HANDLE.get() {
+            handle.emit(event, payload).ok();
+        }
+    }
+
+    /// Runs `unpack` with a progress callback that emits an event whenever the percentage changes
+    fn tracked<T>(mod_id: &str, source: &Path, output: &Path, unpack: impl FnOnce(&mut dyn FnMut(u64, u64)) -> io::Result<T>) -> io::Result<T> {
+        Self::emit("extraction_started", ExtractionStartedPayload {
+            mod_id: mod_id.to_string(),
+            filename: source.display().to_string(),
+        });
+
+        let mut last = None;
+        let mut progress = |read: u64, len: u64| {
+            let percent = match len {
+                0 => 100,
+                len => (read.saturating_mul(100) / len).min(100) as u8,
+            };
+            if last != Some(percent) {
+                last = Some(percent);
+                Self::emit("extraction_progress", ExtractionProgressPayload { mod_id: mod_id.to_string(), percent });
+            }
+        };
+        let result = unpack(&mut progress)?;
+
+        Self::emit("extraction_completed", ExtractionCompletedPayload {
+            mod_id: mod_id.to_string(),
+            path: output.display().to_string(),
+        });
+        Ok(result)
+    }
+
+    /// Unpacks `source` into `destination`, a directory that must not exist yet. Returns how many files were written
+    pub fn extract(mod_id: &str, source: &Path, destination: &Path) -> io::Result<usize> {
+        let extracted = Self::tracked(mod_id, source, destination, |progress| {
+            extract_archive(source, destination, &ExtractLimits::default(), progress)
+        })?;
+        info!("Extracted {} files of {} to {}", extracted, mod_id, destination.display());
+        Ok(extracted)
+    }
+}
@@ -0,0 +1,208 @@
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tracing::error;
+
+use super::app_data_dir;
+use crate::binary::InstallRoot;
+
+/// Bumped when a record changes shape in a way `serde(default)` can't paper over
+///
+/// 1: `files` are plain paths from the package, nothing is owned
+/// 2: `files` track ownership, mods installed by the manager can be uninstalled
+const REGISTRY_VERSION: u32 = 2;
+
+/// Serializes read-modify-write cycles, installs can finish concurrently
+static WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+/// What the app remembers about one installed mod
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct InstalledMod {
+    pub mod_id: String,
+    pub game_id: String,
+    pub version: String,
+    /// Mod provider it was downloaded through
+    pub provider_id: String,
+    /// Seconds since the Unix epoch
+    pub installed_at: u64,
+    /// Hex BLAKE3 of the downloaded archive
+    pub archive_hash: String,
+    /// Whether the manager placed the files itself. Mods installed by a game provider can't be uninstalled from here
+    #[serde(default)]
+    pub managed: bool,
+    /// Disabled mods keep their staged files but have nothing deployed into the game
+    #[serde(default = "enabled_by_default")]
+    pub enabled: bool,
+    /// Files the mod placed, in the order they were written
+    #[serde(default)]
+    pub files: Vec<InstalledFile>,
+    /// Mods this one requires, uninstalling any of them is refused while this one is installed
+    #[serde(default)]
+    pub dependencies: Vec<String>,
+}
+
+fn enabled_by_default() -> bool {
+    true
+}
+
+/// A file owned by an installed mod
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+#[serde(from = "StoredFile")]
+pub struct InstalledFile {
+    pub root: InstallRoot,
+    /// `/` separated, relative to the root
+    pub path: String,
+    /// Hex BLAKE3 of what the mod wrote, empty if unknown
+    pub hash: String,
+    /// Hex BLAKE3 of the file this one overwrote, restored from the backup store when the mod goes away
+    pub replaced: Option<String>,
+}
+
+/// Version 1 registries only stored the path
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredFile {
+    Path(String),
+    File { root: InstallRoot, path: String, hash: String, replaced: Option<String> },
+}
+
+impl From<StoredFile> for InstalledFile {
+    fn from(stored: StoredFile) -> Self {
+        match stored {
+            StoredFile::Path(path) => Self { root: InstallRoot::Game, path, hash: String::new(), replaced: None },
+            StoredFile::File { root, path, hash, replaced } => Self { root, path, hash, replaced },
+        }
+    }
+}
+
+impl InstalledFile {
+    pub fn same_target(&self, other: &InstalledFile) -> bool {
+        self.root == other.root && self.path == other.path
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct RegistryFile {
+    version: u32,
+    /// In load order, mods later in the list win the files they share with earlier ones
+    #[serde(default)]
+    mods: Vec<InstalledMod>,
+}
+
+/// Persistent record of installed mods, one JSON file per game
+pub struct InstalledRegistry;
+
+impl InstalledRegistry {
+    pub fn dir() -> PathBuf {
+        app_data_dir().join("installed")
+    }
+
+    pub(super) fn path(game_id: &str) -> io::Result<PathBuf> {
+        check_game_id(game_id)?;
+        Ok(Self::dir().join(format!("{game_id}.json")))
+    }
+
+    fn load(game_id: &str) -> io::Result<RegistryFile> {
+        let path = Self::path(game_id)?;
+        let contents = match fs::read(&path) {
+            Ok(c) => c,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(RegistryFile { version: REGISTRY_VERSION, mods: Vec::new() }),
+            Err(e) => return Err(e),
+        };
+
+        let registry: RegistryFile = serde_json::from_slice(&contents)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Installed mod registry {} is invalid: {e}", path.display())))?;
+        if registry.version > REGISTRY_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("Installed mod registry {} was written by a newer version of the manager", path.display()),
+            ));
+        }
+        Ok(registry)
+    }
+
+    /// Written to a temporary file and renamed over the old one, so a crash never leaves half a registry
+    fn save(game_id: &str, registry: &RegistryFile) -> io::Result<()> {
+        let path = Self::path(game_id)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let contents = serde_json::to_vec_pretty(registry).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        write_atomic(&path, &contents)
+    }
+
+    /// Loads, changes and saves the registry of `game_id` while holding the write lock
+    pub fn update<T>(game_id: &str, change: impl FnOnce(&mut Vec<InstalledMod>) -> T) -> io::Result<T> {
+        let _guard = WRITE_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut registry = Self::load(game_id)?;
+        let result = change(&mut registry.mods);
+        registry.version = REGISTRY_VERSION;
+        Self::save(game_id, &registry)?;
+        Ok(result)
+    }
+
+    pub fn list(game_id: &str) -> io::Result<Vec<InstalledMod>> {
+        Ok(Self::load(game_id)?.mods)
+    }
+
+    pub fn get(game_id: &str, mod_id: &str) -> io::Result<Option<InstalledMod>> {
+        Ok(Self::load(game_id)?.mods.into_iter().find(|m| m.mod_id == mod_id))
+    }
+
+    /// Whether `mod_id` is installed, unreadable registries count as nothing installed
+    pub fn is_installed(game_id: &str, mod_id: &str) -> bool {
+        Self::get(game_id, mod_id).unwrap_or_else(|e| {
+            error!("Failed to read installed mods for {}: {}", game_id, e);
+            None
+        }).is_some()
+    }
+
+    /// Adds `installed`, replacing the record of an earlier install of the same mod
+    pub fn record(installed: InstalledMod) -> io::Result<()> {
+        let game_id = installed.game_id.clone();
+        Self::update(&game_id, |mods| match mods.iter_mut().find(|m| m.mod_id == installed.mod_id) {
+            Some(existing) => *existing = installed,
+            None => mods.push(installed),
+        })
+    }
+
+    /// Drops the record of `mod_id`, handing it back if there was one
+    #[allow(dead_code)]
+    pub fn remove(game_id: &str, mod_id: &str) -> io::Result<Option<InstalledMod>> {
+        Self::update(game_id, |mods| {
+            let index = mods.iter().position(|m| m.mod_id == mod_id)?;
+            Some(mods.remove(index))
+        })
+    }
+}
+
+/// Writes a temporary file next to `path`, syncs it and renames it over `path`
+pub(super) fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    let mut file = File::create(&temp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(temp, path)
+}
+
+/// Game ids end up in file names, so anything that could point elsewhere is refused
+pub(super) fn check_game_id(game_id: &str) -> io::Result<()> {
+    let valid = !game_id.is_empty() && !game_id.starts_with('.') && !game_id.contains(['/', '\\']);
+    if !valid {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("Invalid game id '{game_id}'")));
+    }
+    Ok(())
+}
+
+/// Seconds since the Unix epoch, for `InstalledMod::installed_at`
+pub fn unix_now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs())
+}
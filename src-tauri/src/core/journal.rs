@@ -0,0 +1,375 @@
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    fs::{self, File},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::{error, info, warn};
+
+use super::{
+    app_data_dir,
+    backup_store::hash_file,
+    deploy,
+    installed_registry::{check_game_id, write_atomic},
+    mod_installer::remove_empty_parents,
+    unix_now, BackupStore, InstalledRegistry, ModInstaller, ProfileStore,
+};
+
+/// 2: deployed mod files are restored from their staged copy instead of a backup
+const JOURNAL_VERSION: u32 = 2;
+
+/// Transactions run one at a time. Operations nested inside one on the same thread become part of it
+static TRANSACTION_LOCK: Mutex<()> = Mutex::new(());
+
+thread_local! {
+    static OPEN: RefCell<Option<Transaction>> = const { RefCell::new(None) };
+}
+
+/// First line of a journal, every following line is a `Step`
+#[derive(Debug, Serialize, Deserialize)]
+struct Header {
+    version: u32,
+    game_id: String,
+    operation: String,
+    started_at: u64,
+    /// Contents of the installed mod registry and the profiles before the transaction, `None` if there were none
+    registry: Option<String>,
+    profiles: Option<String>,
+}
+
+/// Something the transaction is about to change, written before it happens
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Step {
+    /// A file in one of the game's roots, `original` is the backup of what was there
+    File { root: PathBuf, destination: PathBuf, original: Option<String> },
+    /// A file in one of the game's roots that had the same contents as the staged file `staged`
+    Staged { destination: PathBuf, staged: PathBuf },
+    /// A directory that didn't exist before
+    Created { dir: PathBuf },
+    /// A directory moved to `aside`, only deleted once the transaction commits
+    Retired { dir: PathBuf, aside: PathBuf },
+    /// A directory renamed to `to`, which didn't exist before
+    Moved { from: PathBuf, to: PathBuf },
+}
+
+struct Transaction {
+    header: Header,
+    journal: File,
+    steps: Vec<Step>,
+    /// Destinations recorded already, only their state before the first change matters
+    files: BTreeSet<PathBuf>,
+    /// Staged copy of every deployed file by hash. Loaded when first needed and dropped whenever a staging directory moves
+    staged: Option<BTreeMap<String, PathBuf>>,
+}
+
+/// Takes the transaction of this thread back out if the operation panics, the journal on disk is recovered later
+struct OpenGuard;
+
+impl Drop for OpenGuard {
+    fn drop(&mut self) {
+        OPEN.with(|open| open.borrow_mut().take());
+    }
+}
+
+/// Where the staged copy of each file of the game's managed mods is, by hash
+fn staged_copies(game_id: &str) -> io::Result<BTreeMap<String, PathBuf>> {
+    let mut copies = BTreeMap::new();
+    for installed in InstalledRegistry::list(game_id)?.iter().filter(|m| m.managed) {
+        let staging = ModInstaller::staging_dir(game_id, &installed.mod_id)?;
+        for file in installed.files.iter().filter(|f| !f.hash.is_empty()) {
+            copies.entry(file.hash.clone()).or_insert_with(|| ModInstaller::staged_path(&staging, file));
+        }
+    }
+    Ok(copies)
+}
+
+fn read_optional(path: &Path) -> io::Result<Option<String>> {
+    match fs::read_to_string(path) {
+        Ok(contents) => Ok(Some(contents)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+fn restore_optional(path: &Path, contents: Option<&str>) -> io::Result<()> {
+    match contents {
+        Some(contents) => {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            write_atomic(path, contents.as_bytes())
+        }
+        None => match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        },
+    }
+}
+
+/// Write-ahead journal that makes installs, uninstalls and profile switches all or nothing. Every change to the game,
+/// the staging area, the registry and the profiles is recorded before it's made, a failed operation is rolled back
+/// right away and one interrupted by a crash on the next start
+pub struct Journal;
+
+impl Journal {
+    pub fn dir() -> PathBuf {
+        app_data_dir().join("journal")
+    }
+
+    fn path(game_id: &str) -> io::Result<PathBuf> {
+        check_game_id(game_id)?;
+        Ok(Self::dir().join(format!("{game_id}.jsonl")))
+    }
+
+    /// Runs `operation` on `game_id` as one transaction, rolling everything it changed back if it fails
+    pub fn run<T>(game_id: &str, operation: &str, change: impl FnOnce() -> io::Result<T>) -> io::Result<T> {
+        if OPEN.with(|open| open.borrow().is_some()) {
+            return change();
+        }
+        let _guard = TRANSACTION_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        Self::recover(game_id)?;
+
+        let header = Header {
+            version: JOURNAL_VERSION,
+            game_id: game_id.to_string(),
+            operation: operation.to_string(),
+            started_at: unix_now(),
+            registry: read_optional(&InstalledRegistry::path(game_id)?)?,
+            profiles: read_optional(&ProfileStore::path(game_id)?)?,
+        };
+        let path = Self::path(game_id)?;
+        fs::create_dir_all(Self::dir())?;
+        let mut line = serde_json::to_vec(&header).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        line.push(b'\n');
+        write_atomic(&path, &line)?;
+        let journal = File::options().append(true).open(&path)?;
+
+        let _open = OpenGuard;
+        OPEN.with(|open| {
+            *open.borrow_mut() = Some(Transaction { header, journal, steps: Vec::new(), files: BTreeSet::new(), staged: None })
+        });
+        let result = change();
+        let Some(transaction) = OPEN.with(|open| open.borrow_mut().take()) else {
+            return Err(io::Error::other(format!("The transaction of {game_id} was closed early")));
+        };
+
+        match result {
+            Ok(value) => {
+                Self::commit(&path, transaction);
+                Ok(value)
+            }
+            Err(e) => {
+                warn!("{} of {} failed, rolling back: {}", operation, game_id, e);
+                match Self::roll_back(&transaction.header, &transaction.steps).and_then(|()| fs::remove_file(&path)) {
+                    Ok(()) => {
+                        info!("Rolled back {} of {}", operation, game_id);
+                        Self::collect_garbage(game_id);
+                    }
+                    Err(e) => error!("Failed to roll back {} of {}, retrying on next start: {}", operation, game_id, e),
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Removing the journal is what commits, what was retired is only cleaned up afterwards
+    fn commit(path: &Path, transaction: Transaction) {
+        drop(transaction.journal);
+        if let Err(e) = fs::remove_file(path) {
+            error!("Failed to remove journal {}, the change will be rolled back on next start: {}", path.display(), e);
+            return;
+        }
+        for step in &transaction.steps {
+            if let Step::Retired { aside, .. } = step {
+                if let Err(e) = fs::remove_dir_all(aside) {
+                    warn!("Failed to remove {}: {}", aside.display(), e);
+                }
+            }
+        }
+        Self::collect_garbage(&transaction.header.game_id);
+    }
+
+    /// Backups only the finished transaction pointed to aren't needed anymore
+    fn collect_garbage(game_id: &str) {
+        match BackupStore::collect_garbage(game_id) {
+            Ok(0) => {}
+            Ok(removed) => info!("Removed {} backups of {} nothing refers to anymore", removed, game_id),
+            Err(e) => warn!("Failed to clean up the backups of {}: {}", game_id, e),
+        }
+    }
+
+    /// Appends `step` to the journal of the open transaction on `game_id`, if there is one
+    fn record(game_id: &str, step: impl FnOnce(&mut Transaction) -> io::Result<Option<Step>>) -> io::Result<()> {
+        OPEN.with(|open| {
+            let mut open = open.borrow_mut();
+            let Some(transaction) = open.as_mut().filter(|t| t.header.game_id == game_id) else {
+                return Ok(());
+            };
+            let Some(step) = step(transaction)? else {
+                return Ok(());
+            };
+            let mut line = serde_json::to_vec(&step).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            line.push(b'\n');
+            transaction.journal.write_all(&line)?;
+            // The step has to be on disk before the change it announces is made
+            transaction.journal.sync_data()?;
+            transaction.steps.push(step);
+            Ok(())
+        })
+    }
+
+    /// Call before `destination` changes, a rollback puts it back the way it was when the transaction began.
+    /// What a mod deployed there comes back from its staged copy, anything else is backed up first
+    pub(super) fn file(game_id: &str, root: &Path, destination: &Path) -> io::Result<()> {
+        Self::record(game_id, |transaction| {
+            if !transaction.files.insert(destination.to_path_buf()) {
+                return Ok(None);
+            }
+            if !destination.is_file() {
+                return Ok(Some(Step::File { root: root.to_path_buf(), destination: destination.to_path_buf(), original: None }));
+            }
+
+            let hash = hash_file(destination)?;
+            let staged = match &mut transaction.staged {
+                Some(staged) => staged,
+                staged => staged.insert(staged_copies(game_id)?),
+            };
+            if let Some(staged) = staged.get(&hash).filter(|s| s.is_file()) {
+                return Ok(Some(Step::Staged { destination: destination.to_path_buf(), staged: staged.clone() }));
+            }
+            let original = BackupStore::store_hashed(game_id, destination, hash)?;
+            Ok(Some(Step::File { root: root.to_path_buf(), destination: destination.to_path_buf(), original: Some(original) }))
+        })
+    }
+
+    /// Call before creating `dir`, a rollback removes it again if it didn't exist
+    pub(super) fn created(game_id: &str, dir: &Path) -> io::Result<()> {
+        Self::record(game_id, |_| Ok((!dir.exists()).then(|| Step::Created { dir: dir.to_path_buf() })))
+    }
+
+    /// Deletes `dir`. Inside a transaction it's only moved aside until the transaction commits
+    pub(super) fn retire(game_id: &str, dir: &Path) -> io::Result<()> {
+        let in_transaction = OPEN.with(|open| open.borrow().as_ref().is_some_and(|t| t.header.game_id == game_id));
+        if !in_transaction {
+            return fs::remove_dir_all(dir);
+        }
+        let mut aside = dir.as_os_str().to_owned();
+        aside.push(".retired");
+        let aside = PathBuf::from(aside);
+        if aside.exists() {
+            fs::remove_dir_all(&aside)?;
+        }
+        Self::record(game_id, |transaction| {
+            transaction.staged = None;
+            Ok(Some(Step::Retired { dir: dir.to_path_buf(), aside: aside.clone() }))
+        })?;
+        fs::rename(dir, &aside)
+    }
+
+    /// Renames the directory `from` to `to`, a rollback moves it back
+    pub(super) fn rename(game_id: &str, from: &Path, to: &Path) -> io::Result<()> {
+        Self::record(game_id, |transaction| {
+            transaction.staged = None;
+            Ok(Some(Step::Moved { from: from.to_path_buf(), to: to.to_path_buf() }))
+        })?;
+        fs::rename(from, to)
+    }
+
+    /// Undoes `steps` back to front. Every step can be undone twice, so a rollback that got interrupted can run again
+    fn roll_back(header: &Header, steps: &[Step]) -> io::Result<()> {
+        for step in steps.iter().rev() {
+            match step {
+                Step::File { root, destination, original } => match original {
+                    Some(hash) => BackupStore::restore(&header.game_id, hash, destination)?,
+                    // A directory in the way means the file never got written
+                    None if fs::symlink_metadata(destination).is_ok_and(|m| m.is_dir()) => {}
+                    None => {
+                        deploy::clear(destination)?;
+                        remove_empty_parents(destination, root);
+                    }
+                },
+                Step::Staged { destination, staged } => {
+                    deploy::clear(destination)?;
+                    if let Some(parent) = destination.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                    fs::copy(staged, destination)?;
+                }
+                Step::Created { dir } => match fs::remove_dir_all(dir) {
+                    Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                    _ => {}
+                },
+                Step::Retired { dir, aside } => {
+                    if aside.exists() {
+                        if dir.exists() {
+                            fs::remove_dir_all(dir)?;
+                        }
+                        fs::rename(aside, dir)?;
+                    }
+                }
+                Step::Moved { from, to } => {
+                    if to.exists() && !from.exists() {
+                        fs::rename(to, from)?;
+                    }
+                }
+            }
+        }
+        restore_optional(&InstalledRegistry::path(&header.game_id)?, header.registry.as_deref())?;
+        restore_optional(&ProfileStore::path(&header.game_id)?, header.profiles.as_deref())
+    }
+
+    /// Rolls back the transaction on `game_id` a crash interrupted, if there is one
+    fn recover(game_id: &str) -> io::Result<()> {
+        let path = Self::path(game_id)?;
+        let Some(contents) = read_optional(&path)? else {
+            return Ok(());
+        };
+        let mut lines = contents.lines();
+        let header: Header = lines
+            .next()
+            .and_then(|line| serde_json::from_str(line).ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, format!("Journal {} has no valid header", path.display())))?;
+        if header.version > JOURNAL_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("Journal {} was written by a newer version of the manager", path.display()),
+            ));
+        }
+        // The last line may have been cut off by the crash, the change it announced never started
+        let steps: Vec<Step> = lines.map_while(|line| serde_json::from_str(line).ok()).collect();
+
+        warn!("Rolling back the unfinished {} of {} started at {}", header.operation, game_id, header.started_at);
+        Self::roll_back(&header, &steps)?;
+        fs::remove_file(&path)?;
+        info!("Recovered {} from its journal", game_id);
+        Ok(())
+    }
+
+    /// Rolls back whatever was left unfinished when the manager last stopped, run once on startup
+    pub fn recover_all() {
+        let entries = match fs::read_dir(Self::dir()) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return,
+            Err(e) => {
+                error!("Failed to read journals: {}", e);
+                return;
+            }
+        };
+        let _guard = TRANSACTION_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        for path in entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()) {
+            if path.extension().is_some_and(|e| e == "jsonl") {
+                let Some(game_id) = path.file_stem().and_then(|s| s.to_str()) else {
+                    continue;
+                };
+                if let Err(e) = Self::recover(game_id) {
+                    error!("Failed to recover {}: {}", game_id, e);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::{fs, io, path::Path};
+
+use tracing::info;
+
+use super::{
+    installed_registry::write_atomic,
+    mod_installer::{checked_relative, root_dir},
+    BackupStore, GamePaths, InstalledMod, InstalledRegistry, Journal, ModInstaller,
+};
+
+fn deployed(installed: &InstalledMod) -> bool {
+    installed.managed && installed.enabled
+}
+
+impl ModInstaller {
+    /// Brings the game from the deployed state of `current` to that of `desired`, both in load order.
+    /// Everything from the first difference on is withdrawn back to front, then deployed again front to back,
+    /// so the result only depends on the order. Returns the state the game ended up in, even if a step failed
+    fn sync(
+        game_id: &str,
+        current: &[InstalledMod],
+        mut desired: Vec<InstalledMod>,
+        paths: &GamePaths,
+    ) -> (Vec<InstalledMod>, io::Result<()>) {
+        let roots = &paths.install_roots();
+        let first = current
+            .iter()
+            .zip(&desired)
+            .position(|(a, b)| a.mod_id != b.mod_id || deployed(a) != deployed(b))
+            .unwrap_or(current.len().min(desired.len()));
+
+        let mut withdrawn = current.to_vec();
+        for index in (first..withdrawn.len()).rev() {
+            if !deployed(&withdrawn[index]) {
+                continue;
+            }
+            // Everything after it is withdrawn already, so nothing gets handed over
+            if let Err(e) = Self::withdraw(game_id, &withdrawn, index, roots) {
+                return (withdrawn, Err(e));
+            }
+            withdrawn[index].enabled = false;
+            withdrawn[index].files.iter_mut().for_each(|f| f.replaced = None);
+        }
+
+        for index in first..desired.len() {
+            if !deployed(&desired[index]) {
+                desired[index].files.iter_mut().for_each(|f| f.replaced = None);
+                continue;
+            }
+            if let Err(e) = Self::deploy(game_id, &mut desired[index], roots, paths.deploy_method) {
+                // The rest never made it into the game
+                for installed in desired[index..].iter_mut().filter(|m| m.managed) {
+                    installed.enabled = false;
+                    installed.files.iter_mut().for_each(|f| f.replaced = None);
+                }
+                return (desired, Err(e));
+            }
+        }
+        (desired, Ok(()))
+    }
+
+    /// Deploys `desired` over `current` and records whatever state the game ends up in
+    pub(super) fn apply_order(game_id: &str, current: &[InstalledMod], desired: Vec<InstalledMod>, paths: &GamePaths) -> io::Result<()> {
+        let (state, result) = Self::sync(game_id, current, desired, paths);
+        InstalledRegistry::update(game_id, |mods| {
+            // Keep mods recorded by someone else in the meantime
+            let added: Vec<_> = mods.drain(..).filter(|m| !state.iter().any(|s| s.mod_id == m.mod_id)).collect();
+            mods.extend(state);
+            mods.extend(added);
+        })?;
+        result?;
+        Self::write_load_order(game_id, paths)
+    }
+
+    fn reorder(game_id: &str, mod_id: &str, paths: &GamePaths, target: impl FnOnce(&[InstalledMod]) -> io::Result<usize>) -> io::Result<()> {
+        Journal::run(game_id, "reorder", || {
+            let mods = InstalledRegistry::list(game_id)?;
+            let position = find_any(&mods, mod_id)?;
+
+            let mut desired = mods.clone();
+            let moved = desired.remove(position);
+            let index = target(&desired)?.min(desired.len());
+            desired.insert(index, moved);
+            Self::apply_order(game_id, &mods, desired, paths)?;
+            info!("Moved {} to position {} of the load order", mod_id, index);
+            Ok(())
+        })
+    }
+
+    /// Moves `mod_id` to `priority` in the load order, 0 loads first. Mods later in the order win conflicts
+    pub fn set_priority(game_id: &str, mod_id: &str, priority: usize, paths: &GamePaths) -> io::Result<()> {
+        Self::reorder(game_id, mod_id, paths, |_| Ok(priority))
+    }
+
+    /// Moves `mod_id` right in front of `other`, so `other` wins the files both provide
+    pub fn move_before(game_id: &str, mod_id: &str, other: &str, paths: &GamePaths) -> io::Result<()> {
+        Self::reorder(game_id, mod_id, paths, |mods| find_any(mods, other))
+    }
+
+    /// Moves `mod_id` right after `other`, so `mod_id` wins the files both provide
+    pub fn move_after(game_id: &str, mod_id: &str, other: &str, paths: &GamePaths) -> io::Result<()> {
+        Self::reorder(game_id, mod_id, paths, |mods| find_any(mods, other).map(|index| index + 1))
+    }
+
+    /// Rewrites the game's load order file, if it has one, from the plugins of the enabled mods.
+    /// Without plugin extensions there's nothing a game could read, so no file is written
+    pub fn write_load_order(game_id: &str, paths: &GamePaths) -> io::Result<()> {
+        let Some(file) = paths.load_order_file.as_ref().filter(|f| !f.plugin_extensions.is_empty()) else {
+            return Ok(());
+        };
+        let roots = paths.install_roots();
+        let root = root_dir(&roots, file.root)?;
+        let destination = root.join(checked_relative(&file.path)?);
+
+        let mut entries: Vec<String> = Vec::new();
+        for installed in InstalledRegistry::list(game_id)?.iter().filter(|m| m.enabled) {
+            let plugins = installed.files.iter().filter_map(|f| {
+                let name = f.path.rsplit('/').next().unwrap_or(&f.path);
+                let extension = Path::new(name).extension()?.to_str()?;
+                file.plugin_extensions.iter().any(|e| e.eq_ignore_ascii_case(extension)).then(|| name.to_string())
+            });
+            for plugin in plugins {
+                // A plugin provided twice loads where the winning copy is
+                entries.retain(|entry| *entry != plugin);
+                entries.push(plugin);
+            }
+        }
+
+        let contents: String = entries.iter().map(|entry| format!("{}{entry}\n", file.line_prefix)).collect();
+        if let Some(parent) = destination.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        BackupStore::remember_originals(game_id, &roots, [(file.root, file.path.as_str())])?;
+        Journal::file(game_id, root, &destination)?;
+        write_atomic(&destination, contents.as_bytes())
+    }
+}
+
+/// Position of `mod_id`, whether it's managed or not
+fn find_any(mods: &[InstalledMod], mod_id: &str) -> io::Result<usize> {
+    mods.iter()
+        .position(|m| m.mod_id == mod_id)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{mod_id} isn't installed")))
+}
@@ -1,5 +1,30 @@
+mod app_config;
+mod assets;
+mod backup_store;
+mod conflicts;
+mod deploy;
 mod download_service;
+mod extract_service;
+mod installed_registry;
+mod journal;
+mod load_order;
+mod mod_installer;
+mod originals;
+mod profiles;
 mod secret_service;
 
+#[cfg(test)]
+mod tests;
+
 pub use download_service::{DefaultDownloadService};
 pub use secret_service::*;
+pub use app_config::{app_data_dir, AppConfig, GamePaths, LoadOrderFile, SignaturePolicy};
+pub use backup_store::{hash_file, hash_package, BackupStore};
+pub use conflicts::FileConflict;
+pub use deploy::DeployMethod;
+pub use extract_service::ExtractService;
+pub use installed_registry::{unix_now, InstalledFile, InstalledMod, InstalledRegistry};
+pub use journal::Journal;
+pub use mod_installer::ModInstaller;
+pub use originals::{OriginalFile, VanillaMismatch};
+pub use profiles::{ConfigOverride, GameProfiles, Profile, ProfileMod, ProfileStore};
@@ -0,0 +1,571 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Component, Path, PathBuf},
+};
+
+use tracing::{info, warn};
+
+use super::{
+    app_data_dir,
+    assets::{keep_assets, PACK_ASSETS},
+    backup_store::{hash_file, hash_package},
+    deploy::{self, DeployMethod},
+    installed_registry::{check_game_id, write_atomic},
+    open_pack, unix_now, BackupStore, ExtractService, GamePaths, InstalledFile, InstalledMod, InstalledRegistry, Journal,
+};
+use crate::binary::{is_vmpak, InstallContext, InstallRoot, VmpakInstallManifest, VmpakMetadata};
+
+/// Places mods into the game directories itself, tracking every file so they can be taken out again.
+/// Each mod is extracted into its own staging directory first and deployed from there,
+/// so disabling and enabling it again doesn't need the package
+pub struct ModInstaller;
+
+/// Record of a staged update, kept in its staging directory until it's confirmed
+const STAGED_RECORD: &str = "mod.json";
+/// Where archives other than VMPAKs are unpacked inside the staging directory, before their files are laid out by root
+const UNPACKED_DIR: &str = ".unpacked";
+
+/// Hashes what passes through on the way to `inner`
+struct HashingWriter<W> {
+    inner: W,
+    hasher: blake3::Hasher,
+}
+
+impl<W: Write> Write for HashingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let written = self.inner.write(buf)?;
+        self.hasher.update(&buf[..written]);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+pub(super) fn root_dir(roots: &BTreeMap<InstallRoot, PathBuf>, root: InstallRoot) -> io::Result<&Path> {
+    roots.get(&root).map(PathBuf::as_path).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, format!("The {root:?} directory of this game isn't configured"))
+    })
+}
+
+/// `/` separated path as given in settings, refusing anything that could climb out of its root
+pub(super) fn checked_relative(path: &str) -> io::Result<&Path> {
+    let relative = Path::new(path);
+    if path.is_empty() || relative.components().any(|c| !matches!(c, Component::Normal(_))) {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{path} points outside its root")));
+    }
+    Ok(relative)
+}
+
+/// Name of the root inside a staging directory
+pub(super) fn root_name(root: InstallRoot) -> &'static str {
+    match root {
+        InstallRoot::Game => "game",
+        InstallRoot::Config => "config",
+        InstallRoot::Save => "save",
+    }
+}
+
+/// Dependencies a mod can't do without
+fn required_dependencies(metadata: &VmpakMetadata) -> Vec<String> {
+    metadata.dependencies.iter().filter(|d| !d.optional).map(|d| d.mod_id.clone()).collect()
+}
+
+/// Every regular file under `dir` as a `/` separated relative path
+fn unpacked_files(dir: &Path, relative: &str, files: &mut Vec<String>) -> io::Result<()> {
+    let mut children: Vec<_> = fs::read_dir(dir)?.collect::<io::Result<_>>()?;
+    children.sort_by_key(|c| c.file_name());
+
+    for child in children {
+        let name = child.file_name().to_string_lossy().into_owned();
+        let path = if relative.is_empty() { name } else { format!("{relative}/{name}") };
+        let file_type = child.file_type()?;
+        if file_type.is_dir() {
+            unpacked_files(&child.path(), &path, files)?;
+        } else if file_type.is_file() {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+/// Removes the directories between `path` and `root` that are left empty
+pub(super) fn remove_empty_parents(path: &Path, root: &Path) {
+    let mut parent = path.parent();
+    while let Some(dir) = parent.filter(|dir| *dir != root && dir.starts_with(root)) {
+        if fs::remove_dir(dir).is_err() {
+            break;
+        }
+        parent = dir.parent();
+    }
+}
+
+fn find<'a>(mods: &'a [InstalledMod], mod_id: &str) -> io::Result<(usize, &'a InstalledMod)> {
+    let (position, installed) = mods
+        .iter()
+        .enumerate()
+        .find(|(_, m)| m.mod_id == mod_id)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{mod_id} isn't installed")))?;
+    if !installed.managed {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("{mod_id} was installed by the game provider, the manager doesn't know which files are its own"),
+        ));
+    }
+    Ok((position, installed))
+}
+
+/// Refuses to take `mod_id` away while one of `dependents` still needs it, unless `force` is set
+fn check_dependents<'a>(mod_id: &str, dependents: impl Iterator<Item = &'a InstalledMod>, force: bool) -> io::Result<()> {
+    let dependents: Vec<_> = dependents.filter(|m| m.dependencies.iter().any(|d| d == mod_id)).map(|m| m.mod_id.as_str()).collect();
+    if dependents.is_empty() {
+        return Ok(());
+    }
+    if !force {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{mod_id} is required by {}", dependents.join(", "))));
+    }
+    warn!("Taking {} away even though {} depend on it", mod_id, dependents.join(", "));
+    Ok(())
+}
+
+impl ModInstaller {
+    /// Where the extracted files of `mod_id` are kept, whether it's enabled or not
+    pub fn staging_dir(game_id: &str, mod_id: &str) -> io::Result<PathBuf> {
+        check_game_id(game_id)?;
+        // Mod ids come from providers and can contain anything, hex keeps them a valid directory name
+        Ok(app_data_dir().join("staging").join(game_id).join(hex::encode(mod_id)))
+    }
+
+    pub(super) fn staged_path(staging: &Path, file: &InstalledFile) -> PathBuf {
+        staging.join(root_name(file.root)).join(&file.path)
+    }
+
+    /// Where a staged update of `mod_id` waits to be confirmed, next to the staging directory of the installed copy
+    fn pending_dir(game_id: &str, mod_id: &str) -> io::Result<PathBuf> {
+        let mut dir = Self::staging_dir(game_id, mod_id)?.into_os_string();
+        dir.push(".staged");
+        Ok(PathBuf::from(dir))
+    }
+
+    /// The record of `mod_id` as staged, if an update of it is waiting to be confirmed
+    pub fn staged(game_id: &str, mod_id: &str) -> io::Result<Option<InstalledMod>> {
+        let path = Self::pending_dir(game_id, mod_id)?.join(STAGED_RECORD);
+        let contents = match fs::read(&path) {
+            Ok(c) => c,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        serde_json::from_slice(&contents)
+            .map(Some)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Staged record {} is invalid: {e}", path.display())))
+    }
+
+    /// Installs `package`, a VMPAK or any archive `ExtractService` understands, replacing an earlier install
+    /// of the same mod. Files it overwrites are backed up first and nothing changes if it fails.
+    /// A mod that would overwrite files of other enabled mods is only staged and `AlreadyExists` returned,
+    /// `enable` deploys it once the user confirms. `metadata` describes packages that aren't VMPAKs and so carry none of their own,
+    /// `components` are the optional parts of the mod the user picked, `None` for the pack's defaults
+    pub fn install(
+        game_id: &str,
+        mod_id: &str,
+        provider_id: &str,
+        package: &Path,
+        paths: &GamePaths,
+        metadata: &VmpakMetadata,
+        components: Option<Vec<String>>,
+    ) -> io::Result<InstalledMod> {
+        let staged = Self::stage(game_id, mod_id, provider_id, package, paths, metadata, components)?;
+        let conflicts = Self::conflicts_of_staged(game_id, &staged)?;
+        if !conflicts.is_empty() {
+            let files: Vec<_> = conflicts.iter().map(|c| c.path.as_str()).collect();
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{mod_id} would overwrite files of other mods ({}), it's staged until enabled", files.join(", ")),
+            ));
+        }
+
+        if let Err(e) = Self::enable(game_id, mod_id, paths) {
+            if let Err(e) = Self::discard_staged(game_id, mod_id) {
+                warn!("Failed to discard the staged {}: {}", mod_id, e);
+            }
+            return Err(e);
+        }
+        let installed = InstalledRegistry::get(game_id, mod_id)?
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{mod_id} disappeared while installing")))?;
+        info!("Installed {} {} ({} files)", mod_id, installed.version, installed.files.len());
+        Ok(installed)
+    }
+
+    /// Extracts `package` next to the installed copy of the mod, if there is one, without touching it.
+    /// Nothing is deployed, `enable` swaps it in and `discard_staged` throws it away
+    pub fn stage(
+        game_id: &str,
+        mod_id: &str,
+        provider_id: &str,
+        package: &Path,
+        paths: &GamePaths,
+        metadata: &VmpakMetadata,
+        components: Option<Vec<String>>,
+    ) -> io::Result<InstalledMod> {
+        Journal::run(game_id, "stage", || Self::extract(game_id, mod_id, provider_id, package, paths, metadata, components))
+    }
+
+    /// Throws away the staged update of `mod_id`, the installed copy stays as it is
+    pub fn discard_staged(game_id: &str, mod_id: &str) -> io::Result<()> {
+        let pending = Self::pending_dir(game_id, mod_id)?;
+        if !pending.exists() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, format!("No update of {mod_id} is staged")));
+        }
+        Journal::run(game_id, "discard", || Journal::retire(game_id, &pending))?;
+        info!("Discarded the staged {}", mod_id);
+        Ok(())
+    }
+
+    fn extract(
+        game_id: &str,
+        mod_id: &str,
+        provider_id: &str,
+        package: &Path,
+        paths: &GamePaths,
+        metadata: &VmpakMetadata,
+        components: Option<Vec<String>>,
+    ) -> io::Result<InstalledMod> {
+        let context = InstallContext {
+            roots: paths.install_roots(),
+            game_version: paths.game_version.clone(),
+            installed_mods: InstalledRegistry::list(game_id)?
+                .into_iter()
+                .filter(|m| m.enabled && m.mod_id != mod_id)
+                .map(|m| m.mod_id)
+                .collect(),
+            components,
+        };
+        let mut installed = InstalledMod {
+            mod_id: mod_id.to_string(),
+            game_id: game_id.to_string(),
+            version: metadata.version.clone(),
+            provider_id: provider_id.to_string(),
+            installed_at: unix_now(),
+            archive_hash: hash_package(package)?,
+            managed: true,
+            enabled: false,
+            files: Vec::new(),
+            dependencies: required_dependencies(metadata),
+        };
+
+        let staging = Self::pending_dir(game_id, mod_id)?;
+        if staging.exists() {
+            Journal::retire(game_id, &staging)?;
+        }
+        Journal::created(game_id, &staging)?;
+        let staged = (|| -> io::Result<()> {
+            match is_vmpak(package)? {
+                true => Self::stage_pack(&mut installed, package, &context, &staging)?,
+                false => Self::stage_archive(&mut installed, package, &context, &staging, metadata)?,
+            }
+            let record = serde_json::to_vec_pretty(&installed).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+            write_atomic(&staging.join(STAGED_RECORD), &record)
+        })();
+
+        if let Err(e) = staged {
+            if let Err(e) = fs::remove_dir_all(&staging) {
+                warn!("Failed to clean up staging directory {}: {}", staging.display(), e);
+            }
+            return Err(e);
+        }
+
+        info!("Staged {} {} ({} files)", mod_id, installed.version, installed.files.len());
+        Ok(installed)
+    }
+
+    /// Writes the planned entries of the VMPAK `package` into `staging`, the pack's own metadata describes the mod
+    fn stage_pack(installed: &mut InstalledMod, package: &Path, context: &InstallContext, staging: &Path) -> io::Result<()> {
+        let mut reader = open_pack(package, &installed.mod_id)?;
+        installed.version = reader.metadata().version.clone();
+        installed.dependencies = required_dependencies(reader.metadata());
+        let plan = reader.plan_install(context)?;
+
+        installed.files.reserve(plan.files.len());
+        for planned in &plan.files {
+            let mut file = InstalledFile {
+                root: planned.root,
+                path: planned.target.to_string_lossy().replace('\\', "/"),
+                hash: String::new(),
+                replaced: None,
+            };
+            let attributes = reader
+                .entry(&planned.entry)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("VMPAK has no entry {}", planned.entry)))?
+                .attributes;
+
+            let path = Self::staged_path(staging, &file);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let mut output = HashingWriter { inner: BufWriter::new(File::create(&path)?), hasher: blake3::Hasher::new() };
+            io::copy(&mut reader.open_entry(&planned.entry)?, &mut output)?;
+            attributes.apply(&output.inner.into_inner().map_err(|e| e.into_error())?)?;
+
+            file.hash = output.hasher.finalize().to_hex().to_string();
+            installed.files.push(file);
+        }
+        keep_assets(&mut reader, &staging.join(PACK_ASSETS))
+    }
+
+    /// Unpacks any other archive into `staging` and moves its files to where the install manifest of `metadata` maps them
+    fn stage_archive(
+        installed: &mut InstalledMod,
+        package: &Path,
+        context: &InstallContext,
+        staging: &Path,
+        metadata: &VmpakMetadata,
+    ) -> io::Result<()> {
+        let unpacked = staging.join(UNPACKED_DIR);
+        ExtractService::extract(&installed.mod_id, package, &unpacked)?;
+        let mut entries = Vec::new();
+        unpacked_files(&unpacked, "", &mut entries)?;
+        let manifest = metadata.install.clone().unwrap_or_else(VmpakInstallManifest::everything);
+        let plan = manifest.plan(&entries, context)?;
+
+        // An entry can be mapped more than once, it's copied until its last mapping moves it
+        let last_use: BTreeMap<&str, usize> = plan.files.iter().enumerate().map(|(index, p)| (p.entry.as_str(), index)).collect();
+        installed.files.reserve(plan.files.len());
+        for (index, planned) in plan.files.iter().enumerate() {
+            let mut file = InstalledFile {
+                root: planned.root,
+                path: planned.target.to_string_lossy().replace('\\', "/"),
+                hash: String::new(),
+                replaced: None,
+            };
+            let source = unpacked.join(&planned.entry);
+            let path = Self::staged_path(staging, &file);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            match last_use[planned.entry.as_str()] == index {
+                true => fs::rename(&source, &path)?,
+                false => drop(fs::copy(&source, &path)?),
+            }
+
+            file.hash = hash_file(&path)?;
+            installed.files.push(file);
+        }
+        fs::remove_dir_all(&unpacked)
+    }
+
+    /// Swaps the staged update `staged` in for the installed copy of the same mod, keeping its place in the load order.
+    /// It's recorded as disabled, nothing is deployed yet
+    fn confirm_staged(game_id: &str, staged: InstalledMod, paths: &GamePaths) -> io::Result<()> {
+        let mod_id = staged.mod_id.clone();
+        let mods = InstalledRegistry::list(game_id)?;
+        let slot = mods.iter().position(|m| m.mod_id == mod_id).unwrap_or(mods.len());
+        match mods.iter().find(|m| m.mod_id == mod_id) {
+            // The update has the same id, so whatever depends on it is still satisfied
+            Some(existing) if existing.managed => Self::remove(game_id, &mods, &mod_id, paths)?,
+            Some(_) => warn!("{} was installed by the game provider, its files will be treated as the game's own", mod_id),
+            None => {}
+        }
+
+        let staging = Self::staging_dir(game_id, &mod_id)?;
+        if staging.exists() {
+            Journal::retire(game_id, &staging)?;
+        }
+        Journal::rename(game_id, &Self::pending_dir(game_id, &mod_id)?, &staging)?;
+        InstalledRegistry::update(game_id, |mods| {
+            mods.retain(|m| m.mod_id != mod_id);
+            mods.insert(slot.min(mods.len()), staged);
+        })
+    }
+
+    /// Places the staged files of `installed` into the game with `method`, backing up what they overwrite.
+    /// Nothing stays behind if a file fails
+    pub(super) fn deploy(
+        game_id: &str,
+        installed: &mut InstalledMod,
+        roots: &BTreeMap<InstallRoot, PathBuf>,
+        method: DeployMethod,
+    ) -> io::Result<()> {
+        let staging = Self::staging_dir(game_id, &installed.mod_id)?;
+        BackupStore::remember_originals(game_id, roots, installed.files.iter().map(|f| (f.root, f.path.as_str())))?;
+        let mut copied = 0;
+        for index in 0..installed.files.len() {
+            let deployed = (|| {
+                let file = &installed.files[index];
+                let root = root_dir(roots, file.root)?;
+                let destination = root.join(&file.path);
+                Journal::file(game_id, root, &destination)?;
+                let replaced = match destination.is_file() {
+                    true => Some(BackupStore::store(game_id, &destination)?),
+                    false => None,
+                };
+
+                if let Some(parent) = destination.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                let used = deploy::place(&Self::staged_path(&staging, file), &destination, method)?;
+                Ok((replaced, used))
+            })();
+
+            match deployed {
+                Ok((replaced, used)) => {
+                    installed.files[index].replaced = replaced;
+                    copied += usize::from(used != method);
+                }
+                Err(e) => {
+                    for file in installed.files[..index].iter().rev() {
+                        if let Err(e) = Self::remove_file(game_id, file, roots) {
+                            warn!("Failed to roll back {}: {}", file.path, e);
+                        }
+                    }
+                    return Err(e);
+                }
+            }
+        }
+        if copied > 0 {
+            warn!("Copied {} files of {} instead of using {:?}, see the debug log for why", copied, installed.mod_id, method);
+        }
+        Ok(())
+    }
+
+    /// Takes the files of the mod at `position` out of the game. Files a mod deployed later has overwritten
+    /// stay in place, their indices are returned so the record can hand over what they replaced
+    pub(super) fn withdraw(game_id: &str, mods: &[InstalledMod], position: usize, roots: &BTreeMap<InstallRoot, PathBuf>) -> io::Result<BTreeSet<usize>> {
+        let installed = &mods[position];
+        // Enabled mods are recorded in the order they were deployed, a later one writing the same file owns what's on disk
+        let later: Vec<_> = mods[position + 1..].iter().filter(|m| m.enabled).collect();
+        let mut handed_over = BTreeSet::new();
+        for (index, file) in installed.files.iter().enumerate().rev() {
+            if later.iter().any(|m| m.files.iter().any(|f| f.same_target(file))) {
+                info!("Leaving {} in place, a mod deployed after {} replaced it", file.path, installed.mod_id);
+                handed_over.insert(index);
+                continue;
+            }
+            Self::remove_file(game_id, file, roots)?;
+        }
+        Ok(handed_over)
+    }
+
+    /// The next owner of a shared file has to restore whatever `withdrawn` had replaced
+    fn hand_over(mods: &mut [InstalledMod], withdrawn: &InstalledMod, handed_over: &BTreeSet<usize>) {
+        let Some(position) = mods.iter().position(|m| m.mod_id == withdrawn.mod_id) else {
+            return;
+        };
+        for file in handed_over.iter().map(|&index| &withdrawn.files[index]) {
+            let next = mods[position + 1..].iter_mut().filter(|m| m.enabled).flat_map(|m| &mut m.files).find(|f| f.same_target(file));
+            if let Some(next) = next {
+                next.replaced = file.replaced.clone();
+            }
+        }
+    }
+
+    /// Removes exactly the files `mod_id` placed, restores what they overwrote and drops its staged copy,
+    /// along with an update staged for it. Refuses while other installed mods depend on it, unless `force` is set
+    pub fn uninstall(game_id: &str, mod_id: &str, paths: &GamePaths, force: bool) -> io::Result<InstalledMod> {
+        Journal::run(game_id, "uninstall", || {
+            let mods = InstalledRegistry::list(game_id)?;
+            let (_, installed) = find(&mods, mod_id)?;
+            check_dependents(mod_id, mods.iter(), force)?;
+            Self::remove(game_id, &mods, mod_id, paths)?;
+
+            let pending = Self::pending_dir(game_id, mod_id)?;
+            if pending.exists() {
+                Journal::retire(game_id, &pending)?;
+            }
+            info!("Uninstalled {} ({} files)", mod_id, installed.files.len());
+            Ok(installed.clone())
+        })
+    }
+
+    /// Takes the managed mod `mod_id` out of the game and the registry and drops its staged copy
+    fn remove(game_id: &str, mods: &[InstalledMod], mod_id: &str, paths: &GamePaths) -> io::Result<()> {
+        let (position, installed) = find(mods, mod_id)?;
+        let handed_over = match installed.enabled {
+            true => Self::withdraw(game_id, mods, position, &paths.install_roots())?,
+            false => BTreeSet::new(),
+        };
+        InstalledRegistry::update(game_id, |mods| {
+            Self::hand_over(mods, installed, &handed_over);
+            mods.retain(|m| m.mod_id != mod_id);
+        })?;
+        Self::write_load_order(game_id, paths)?;
+
+        let staging = Self::staging_dir(game_id, mod_id)?;
+        if let Err(e) = Journal::retire(game_id, &staging) {
+            warn!("Failed to remove staging directory {}: {}", staging.display(), e);
+        }
+        Ok(())
+    }
+
+    /// Withdraws the files of `mod_id` from the game but keeps them staged.
+    /// Refuses while enabled mods depend on it, unless `force` is set
+    pub fn disable(game_id: &str, mod_id: &str, paths: &GamePaths, force: bool) -> io::Result<()> {
+        Journal::run(game_id, "disable", || {
+            let mods = InstalledRegistry::list(game_id)?;
+            let (position, installed) = find(&mods, mod_id)?;
+            if !installed.enabled {
+                return Ok(());
+            }
+            check_dependents(mod_id, mods.iter().filter(|m| m.enabled), force)?;
+
+            let handed_over = Self::withdraw(game_id, &mods, position, &paths.install_roots())?;
+            InstalledRegistry::update(game_id, |mods| {
+                Self::hand_over(mods, installed, &handed_over);
+                if let Some(record) = mods.iter_mut().find(|m| m.mod_id == mod_id) {
+                    record.enabled = false;
+                    record.files.iter_mut().for_each(|f| f.replaced = None);
+                }
+            })?;
+            Self::write_load_order(game_id, paths)?;
+            info!("Disabled {}", mod_id);
+            Ok(())
+        })
+    }
+
+    /// Deploys the staged files of `mod_id` again, swapping in an update staged for it first.
+    /// Mods after it in the load order still win the files they share
+    pub fn enable(game_id: &str, mod_id: &str, paths: &GamePaths) -> io::Result<()> {
+        Journal::run(game_id, "enable", || {
+            if let Some(staged) = Self::staged(game_id, mod_id)? {
+                Self::confirm_staged(game_id, staged, paths)?;
+            }
+            let mods = InstalledRegistry::list(game_id)?;
+            let (position, installed) = find(&mods, mod_id)?;
+            if installed.enabled {
+                return Ok(());
+            }
+
+            let mut desired = mods.clone();
+            desired[position].enabled = true;
+            Self::apply_order(game_id, &mods, desired, paths)?;
+            info!("Enabled {}", mod_id);
+            Ok(())
+        })
+    }
+
+    /// Takes a single installed file out again, restoring the backup of what it replaced
+    /// and removing directories it leaves empty. Changes made to it since it was placed are kept in the backup store
+    pub(super) fn remove_file(game_id: &str, file: &InstalledFile, roots: &BTreeMap<InstallRoot, PathBuf>) -> io::Result<()> {
+        let root = root_dir(roots, file.root)?;
+        let destination = root.join(&file.path);
+        Journal::file(game_id, root, &destination)?;
+
+        if destination.is_file() && !file.hash.is_empty() && hash_file(&destination)? != file.hash {
+            let kept = BackupStore::keep_modified(game_id, file.root, &file.path, &destination)?;
+            warn!("{} changed since it was installed, kept a copy at {}", destination.display(), kept.display());
+        }
+
+        match &file.replaced {
+            Some(hash) => BackupStore::restore(game_id, hash, &destination)?,
+            None => match fs::remove_file(&destination) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            },
+        }
+
+        remove_empty_parents(&destination, root);
+        Ok(())
+    }
+}
@@ -0,0 +1,209 @@
+use std::{
+    collections::BTreeMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tracing::{info, warn};
+
+use super::{
+    backup_store::hash_file,
+    deploy,
+    installed_registry::write_atomic,
+    mod_installer::{checked_relative, remove_empty_parents, root_dir},
+    BackupStore, GamePaths, InstalledRegistry, Journal, ProfileStore,
+};
+use crate::binary::InstallRoot;
+
+const ORIGINALS_VERSION: u32 = 1;
+
+static WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+/// What a game file looked like before the manager first wrote to it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct OriginalFile {
+    pub root: InstallRoot,
+    /// `/` separated, relative to the root
+    pub path: String,
+    /// Backup of the original, `None` if the game had no such file
+    pub hash: Option<String>,
+}
+
+/// A game file that isn't the way it was before the manager touched it
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Type)]
+pub struct VanillaMismatch {
+    pub root: InstallRoot,
+    pub path: String,
+    pub expected: Option<String>,
+    /// Hash of what's in the game now, `None` if the file is missing
+    pub found: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OriginalsFile {
+    version: u32,
+    originals: Vec<OriginalFile>,
+}
+
+impl BackupStore {
+    fn originals_path(game_id: &str) -> io::Result<PathBuf> {
+        Ok(Self::dir(game_id)?.join("originals.json"))
+    }
+
+    fn load_originals(game_id: &str) -> io::Result<Option<Vec<OriginalFile>>> {
+        let path = Self::originals_path(game_id)?;
+        let contents = match fs::read(&path) {
+            Ok(c) => c,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e),
+        };
+
+        let file: OriginalsFile = serde_json::from_slice(&contents)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Original file index {} is invalid: {e}", path.display())))?;
+        if file.version > ORIGINALS_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("Original file index {} was written by a newer version of the manager", path.display()),
+            ));
+        }
+        Ok(Some(file.originals))
+    }
+
+    /// Games set up before originals were indexed get theirs from what the deployed mods and overrides replaced.
+    /// The first mod in load order to own a file holds what was there before any of them
+    fn seed_originals(game_id: &str) -> io::Result<Vec<OriginalFile>> {
+        let mut originals: Vec<OriginalFile> = Vec::new();
+        let mods = InstalledRegistry::list(game_id)?;
+        let deployed = mods.iter().filter(|m| m.managed && m.enabled).flat_map(|m| &m.files);
+        for file in deployed.chain(&ProfileStore::deployed_overrides(game_id)?) {
+            if !originals.iter().any(|o| o.root == file.root && o.path == file.path) {
+                originals.push(OriginalFile { root: file.root, path: file.path.clone(), hash: file.replaced.clone() });
+            }
+        }
+        Ok(originals)
+    }
+
+    /// Every game file the manager ever wrote to, with what was there before
+    pub fn list_originals(game_id: &str) -> io::Result<Vec<OriginalFile>> {
+        match Self::load_originals(game_id)? {
+            Some(originals) => Ok(originals),
+            None => Self::seed_originals(game_id),
+        }
+    }
+
+    /// Backs up the files at `targets` the manager is about to write for the first time. Call before writing them
+    pub(super) fn remember_originals<'a>(
+        game_id: &str,
+        roots: &BTreeMap<InstallRoot, PathBuf>,
+        targets: impl IntoIterator<Item = (InstallRoot, &'a str)>,
+    ) -> io::Result<()> {
+        let _guard = WRITE_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut originals = Self::list_originals(game_id)?;
+        let known = originals.len();
+        for (root, path) in targets {
+            if originals.iter().any(|o| o.root == root && o.path == path) {
+                continue;
+            }
+            let destination = root_dir(roots, root)?.join(checked_relative(path)?);
+            let hash = match destination.is_file() {
+                true => Some(Self::store(game_id, &destination)?),
+                false => None,
+            };
+            originals.push(OriginalFile { root, path: path.to_string(), hash });
+        }
+        if originals.len() == known && Self::originals_path(game_id)?.is_file() {
+            return Ok(());
+        }
+
+        fs::create_dir_all(Self::dir(game_id)?)?;
+        let file = OriginalsFile { version: ORIGINALS_VERSION, originals };
+        let contents = serde_json::to_vec_pretty(&file).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        write_atomic(&Self::originals_path(game_id)?, &contents)
+    }
+
+    /// Game files that differ from their originals. Empty if the game is vanilla, as far as the manager ever touched it
+    pub fn verify_vanilla(game_id: &str, paths: &GamePaths) -> io::Result<Vec<VanillaMismatch>> {
+        let roots = paths.install_roots();
+        let mut mismatches = Vec::new();
+        for original in Self::list_originals(game_id)? {
+            let Ok(root) = root_dir(&roots, original.root) else {
+                warn!("Can't verify {}, its root isn't configured for {}", original.path, game_id);
+                continue;
+            };
+            let destination = root.join(checked_relative(&original.path)?);
+            let found = match destination.is_file() {
+                true => Some(hash_file(&destination)?),
+                false => None,
+            };
+            if found != original.hash {
+                mismatches.push(VanillaMismatch { root: original.root, path: original.path, expected: original.hash, found });
+            }
+        }
+        Ok(mismatches)
+    }
+
+    /// Puts the original of a single game file back. Refuses while a deployed mod or config override owns it
+    pub fn restore_original(game_id: &str, root: InstallRoot, path: &str, paths: &GamePaths) -> io::Result<()> {
+        Journal::run(game_id, "restore original", || {
+            let original = Self::list_originals(game_id)?
+                .into_iter()
+                .find(|o| o.root == root && o.path == path)
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("The manager never changed {path}")))?;
+
+            let mods = InstalledRegistry::list(game_id)?;
+            let owner = mods.iter().filter(|m| m.managed && m.enabled).find(|m| m.files.iter().any(|f| f.root == root && f.path == path));
+            if let Some(owner) = owner {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{path} belongs to {}, disable it first", owner.mod_id)));
+            }
+            if ProfileStore::deployed_overrides(game_id)?.iter().any(|f| f.root == root && f.path == path) {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{path} is a config override of the active profile")));
+            }
+
+            let roots = paths.install_roots();
+            Self::put_back(game_id, &original, root_dir(&roots, root)?)?;
+            info!("Restored the original {} of {}", path, game_id);
+            Ok(())
+        })
+    }
+
+    /// Puts back the original of every game file that no deployed mod or config override owns
+    pub(super) fn restore_unowned_originals(game_id: &str, paths: &GamePaths) -> io::Result<()> {
+        let mods = InstalledRegistry::list(game_id)?;
+        let overrides = ProfileStore::deployed_overrides(game_id)?;
+        let owned: Vec<_> = mods.iter().filter(|m| m.managed && m.enabled).flat_map(|m| &m.files).chain(&overrides).collect();
+        let roots = paths.install_roots();
+        for original in Self::list_originals(game_id)? {
+            if owned.iter().any(|f| f.root == original.root && f.path == original.path) {
+                continue;
+            }
+            match root_dir(&roots, original.root) {
+                Ok(root) => Self::put_back(game_id, &original, root)?,
+                Err(_) => warn!("Can't restore {}, its root isn't configured for {}", original.path, game_id),
+            }
+        }
+        Ok(())
+    }
+
+    fn put_back(game_id: &str, original: &OriginalFile, root: &Path) -> io::Result<()> {
+        let destination = root.join(checked_relative(&original.path)?);
+        let current = match destination.is_file() {
+            true => Some(hash_file(&destination)?),
+            false => None,
+        };
+        if current == original.hash {
+            return Ok(());
+        }
+        Journal::file(game_id, root, &destination)?;
+        match &original.hash {
+            Some(hash) => Self::restore(game_id, hash, &destination),
+            None => {
+                deploy::clear(&destination)?;
+                remove_empty_parents(&destination, root);
+                Ok(())
+            }
+        }
+    }
+}
@@ -0,0 +1,288 @@
+use std::{fs, io, path::PathBuf, sync::Mutex};
+
+use serde::{Deserialize, Serialize};
+use specta::Type;
+use tracing::info;
+
+use super::{
+    app_data_dir, deploy,
+    installed_registry::{check_game_id, write_atomic},
+    mod_installer::{checked_relative, root_dir},
+    BackupStore, GamePaths, InstalledFile, InstalledMod, InstalledRegistry, Journal, ModInstaller,
+};
+use crate::binary::InstallRoot;
+
+const PROFILES_VERSION: u32 = 1;
+/// What every game starts out with
+const DEFAULT_PROFILE: &str = "Default";
+
+static WRITE_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct ProfileMod {
+    pub mod_id: String,
+    pub enabled: bool,
+}
+
+/// A file the profile writes over whatever the game or its mods put there, usually a config
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct ConfigOverride {
+    #[serde(default = "config_root")]
+    pub root: InstallRoot,
+    /// `/` separated, relative to the root
+    pub path: String,
+    pub contents: String,
+}
+
+fn config_root() -> InstallRoot {
+    InstallRoot::Config
+}
+
+/// A named setup of one game
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct Profile {
+    pub name: String,
+    /// In load order. Mods installed since the profile was last active aren't listed and stay disabled in it
+    #[serde(default)]
+    pub mods: Vec<ProfileMod>,
+    #[serde(default)]
+    pub overrides: Vec<ConfigOverride>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, Type)]
+pub struct GameProfiles {
+    pub active: String,
+    pub profiles: Vec<Profile>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ProfilesFile {
+    version: u32,
+    active: String,
+    profiles: Vec<Profile>,
+    /// Overrides of the active profile that are in the game right now
+    #[serde(default)]
+    deployed_overrides: Vec<InstalledFile>,
+}
+
+impl Profile {
+    fn snapshot(name: &str, mods: &[InstalledMod]) -> Self {
+        Self {
+            name: name.to_string(),
+            mods: mods.iter().map(|m| ProfileMod { mod_id: m.mod_id.clone(), enabled: m.enabled }).collect(),
+            overrides: Vec::new(),
+        }
+    }
+}
+
+fn not_found(name: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, format!("There's no profile {name}"))
+}
+
+/// Named profiles per game. The installed mod registry always holds the state of the active profile,
+/// the other profiles remember theirs from when they were last active
+pub struct ProfileStore;
+
+impl ProfileStore {
+    pub(super) fn path(game_id: &str) -> io::Result<PathBuf> {
+        check_game_id(game_id)?;
+        Ok(app_data_dir().join("profiles").join(format!("{game_id}.json")))
+    }
+
+    fn load(game_id: &str) -> io::Result<ProfilesFile> {
+        let path = Self::path(game_id)?;
+        let contents = match fs::read(&path) {
+            Ok(c) => c,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                return Ok(ProfilesFile {
+                    version: PROFILES_VERSION,
+                    active: DEFAULT_PROFILE.to_string(),
+                    profiles: vec![Profile::snapshot(DEFAULT_PROFILE, &[])],
+                    deployed_overrides: Vec::new(),
+                })
+            }
+            Err(e) => return Err(e),
+        };
+
+        let profiles: ProfilesFile = serde_json::from_slice(&contents)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("Profiles {} are invalid: {e}", path.display())))?;
+        if profiles.version > PROFILES_VERSION {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("Profiles {} were written by a newer version of the manager", path.display()),
+            ));
+        }
+        Ok(profiles)
+    }
+
+    /// Saved even if `change` fails, switching can get halfway and what did happen has to be remembered
+    fn update<T>(game_id: &str, change: impl FnOnce(&mut ProfilesFile) -> io::Result<T>) -> io::Result<T> {
+        let _guard = WRITE_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let mut profiles = Self::load(game_id)?;
+        let result = change(&mut profiles);
+        profiles.version = PROFILES_VERSION;
+
+        let path = Self::path(game_id)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let contents = serde_json::to_vec_pretty(&profiles).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        write_atomic(&path, &contents)?;
+        result
+    }
+
+    /// Every profile of the game, the active one as it is right now
+    pub fn list(game_id: &str) -> io::Result<GameProfiles> {
+        let mut file = Self::load(game_id)?;
+        let current = InstalledRegistry::list(game_id)?;
+        if let Some(active) = file.profiles.iter_mut().find(|p| p.name == file.active) {
+            active.mods = Profile::snapshot(&file.active, &current).mods;
+        }
+        Ok(GameProfiles { active: file.active, profiles: file.profiles })
+    }
+
+    /// Adds an empty profile, every installed mod is disabled in it
+    pub fn create(game_id: &str, name: &str) -> io::Result<Profile> {
+        let current = InstalledRegistry::list(game_id)?;
+        let mut profile = Profile::snapshot(name, &current);
+        // Mods installed by a game provider can't be withdrawn, they're on in every profile
+        profile.mods.iter_mut().zip(&current).for_each(|(m, installed)| m.enabled = !installed.managed);
+        Self::add(game_id, profile)
+    }
+
+    /// Copies `source`, including its config overrides, into a new profile called `name`
+    pub fn clone_profile(game_id: &str, source: &str, name: &str) -> io::Result<Profile> {
+        let mut profile = Self::list(game_id)?.profiles.into_iter().find(|p| p.name == source).ok_or_else(|| not_found(source))?;
+        profile.name = name.to_string();
+        Self::add(game_id, profile)
+    }
+
+    fn add(game_id: &str, profile: Profile) -> io::Result<Profile> {
+        if profile.name.trim().is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Profiles need a name"));
+        }
+        Self::update(game_id, |file| {
+            if file.profiles.iter().any(|p| p.name == profile.name) {
+                return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("There's already a profile {}", profile.name)));
+            }
+            file.profiles.push(profile.clone());
+            Ok(profile)
+        })
+    }
+
+    /// The active profile can't be deleted, switch away from it first
+    pub fn delete(game_id: &str, name: &str) -> io::Result<()> {
+        Self::update(game_id, |file| {
+            if file.active == name {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("{name} is the active profile")));
+            }
+            let index = file.profiles.iter().position(|p| p.name == name).ok_or_else(|| not_found(name))?;
+            file.profiles.remove(index);
+            Ok(())
+        })
+    }
+
+    /// Replaces the config overrides of `name`, redeploying them if it's the active profile
+    pub fn set_overrides(game_id: &str, name: &str, overrides: Vec<ConfigOverride>, paths: &GamePaths) -> io::Result<()> {
+        for config in &overrides {
+            checked_relative(&config.path)?;
+        }
+        Journal::run(game_id, "set overrides", || Self::update(game_id, |file| {
+            let index = file.profiles.iter().position(|p| p.name == name).ok_or_else(|| not_found(name))?;
+            file.profiles[index].overrides = overrides;
+            if file.active == name {
+                Self::withdraw_overrides(game_id, file, paths)?;
+                Self::deploy_overrides(game_id, file, index, paths)?;
+            }
+            Ok(())
+        }))
+    }
+
+    /// Makes `name` the active profile: the current state is saved into the old one,
+    /// then the game is redeployed with the mods, load order and overrides of `name`
+    pub fn switch(game_id: &str, name: &str, paths: &GamePaths) -> io::Result<()> {
+        Journal::run(game_id, "switch profile", || Self::update(game_id, |file| {
+            let index = file.profiles.iter().position(|p| p.name == name).ok_or_else(|| not_found(name))?;
+            if file.active == name {
+                return Ok(());
+            }
+
+            let current = InstalledRegistry::list(game_id)?;
+            let previous = file.active.clone();
+            if let Some(active) = file.profiles.iter_mut().find(|p| p.name == previous) {
+                active.mods = Profile::snapshot(&previous, &current).mods;
+            }
+            Self::withdraw_overrides(game_id, file, paths)?;
+
+            // Listed mods first in the profile's order, anything installed since keeps its relative place after them
+            let target = &file.profiles[index];
+            let mut desired: Vec<InstalledMod> = target
+                .mods
+                .iter()
+                .filter_map(|p| {
+                    let mut installed = current.iter().find(|m| m.mod_id == p.mod_id)?.clone();
+                    installed.enabled = p.enabled || !installed.managed;
+                    Some(installed)
+                })
+                .collect();
+            for installed in current.iter().filter(|m| !target.mods.iter().any(|p| p.mod_id == m.mod_id)) {
+                let mut installed = installed.clone();
+                installed.enabled = !installed.managed;
+                desired.push(installed);
+            }
+
+            ModInstaller::apply_order(game_id, &current, desired, paths)?;
+            file.active = name.to_string();
+            Self::deploy_overrides(game_id, file, index, paths)?;
+            info!("Switched {} from profile {} to {}", game_id, previous, name);
+            Ok(())
+        }))
+    }
+
+    /// Overrides of the active profile that are in the game right now
+    pub(super) fn deployed_overrides(game_id: &str) -> io::Result<Vec<InstalledFile>> {
+        Ok(Self::load(game_id)?.deployed_overrides)
+    }
+
+    /// Takes the active profile's overrides out of the game, they're deployed again on the next switch or change
+    pub(super) fn withdraw_deployed_overrides(game_id: &str, paths: &GamePaths) -> io::Result<()> {
+        Self::update(game_id, |file| Self::withdraw_overrides(game_id, file, paths))
+    }
+
+    fn withdraw_overrides(game_id: &str, file: &mut ProfilesFile, paths: &GamePaths) -> io::Result<()> {
+        let roots = paths.install_roots();
+        while let Some(deployed) = file.deployed_overrides.pop() {
+            if let Err(e) = ModInstaller::remove_file(game_id, &deployed, &roots) {
+                file.deployed_overrides.push(deployed);
+                return Err(e);
+            }
+        }
+        Ok(())
+    }
+
+    fn deploy_overrides(game_id: &str, file: &mut ProfilesFile, index: usize, paths: &GamePaths) -> io::Result<()> {
+        let roots = paths.install_roots();
+        BackupStore::remember_originals(game_id, &roots, file.profiles[index].overrides.iter().map(|c| (c.root, c.path.as_str())))?;
+        for config in &file.profiles[index].overrides {
+            let root = root_dir(&roots, config.root)?;
+            let destination = root.join(checked_relative(&config.path)?);
+            Journal::file(game_id, root, &destination)?;
+            let replaced = match destination.is_file() {
+                true => Some(BackupStore::store(game_id, &destination)?),
+                false => None,
+            };
+            if let Some(parent) = destination.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            deploy::clear(&destination)?;
+            fs::write(&destination, &config.contents)?;
+            file.deployed_overrides.push(InstalledFile {
+                root: config.root,
+                path: config.path.clone(),
+                hash: blake3::hash(config.contents.as_bytes()).to_hex().to_string(),
+                replaced,
+            });
+        }
+        Ok(())
+    }
+}
@@ -1,4 +1,10 @@
+use std::{fs::File, io, path::Path};
+
 use keyring::{Entry, Error};
+use tracing::debug;
+
+use super::AppConfig;
+use crate::binary::{VmpakEncryptionHeader, VmpakHeader, VmpakKey, VmpakReader, VmpakUnlock};
 
 /// Example: "core:provider", will return the value
 pub fn load_provider_secret(provider_id: &str) -> Result<String, Error> {
@@ -13,3 +19,50 @@ pub fn set_provider_secret(provider_id: &str, secret: &str) -> Result<String, Er
     let pass = entry.get_password()?;
     Ok(pass)
 }
+
+/// Example: "team-private", will return the hex encoded VMPAK encryption key
+pub fn load_pack_key(key_id: &str) -> Result<String, Error> {
+    let entry = Entry::new("void-mod-manager/pack-key", key_id)?;
+    let key = entry.get_password()?;
+    Ok(key)
+}
+
+pub fn set_pack_key(key_id: &str, key: &str) -> Result<String, Error> {
+    let entry = Entry::new("void-mod-manager/pack-key", key_id)?;
+    entry.set_password(key)?;
+    let key = entry.get_password()?;
+    Ok(key)
+}
+
+/// Finds a stored pack key that opens the encrypted pack at `path`. The key stored under `mod_id` is tried first,
+/// then the ones listed in `AppConfig::pack_keys`. `None` if the pack isn't encrypted
+pub fn pack_unlock(path: &Path, mod_id: &str) -> io::Result<Option<VmpakUnlock>> {
+    let mut file = File::open(path)?;
+    let header = VmpakHeader::read(&mut file)?;
+    let Some(encryption) = VmpakEncryptionHeader::read_for(&mut file, &header)? else {
+        return Ok(None);
+    };
+
+    let key_ids = AppConfig::load().pack_keys;
+    for key_id in std::iter::once(mod_id).chain(key_ids.iter().map(String::as_str)) {
+        let Ok(stored) = load_pack_key(key_id) else {
+            continue;
+        };
+        match VmpakKey::from_hex(&stored).and_then(|key| encryption.unlock_with_key(key)) {
+            Ok(key) => {
+                debug!("Unlocking {} with pack key {}", path.display(), key_id);
+                return Ok(Some(VmpakUnlock::Key(key)));
+            }
+            Err(e) => debug!("Pack key {} doesn't open {}: {}", key_id, path.display(), e),
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::PermissionDenied, format!("{} is encrypted and no stored pack key opens it", path.display())))
+}
+
+/// Opens the pack at `path`, unlocking it with a stored key if it's encrypted
+pub fn open_pack(path: &Path, mod_id: &str) -> io::Result<VmpakReader> {
+    match pack_unlock(path, mod_id)? {
+        Some(unlock) => VmpakReader::open_with_unlock(path, &unlock),
+        None => VmpakReader::open(path),
+    }
+}
@@ -0,0 +1,693 @@
+use std::{
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use super::{app_config::TEST_DIR, deploy, *};
+use crate::binary::{InstallComponent, InstallCondition, InstallMapping, InstallRoot, VmpakDependency, VmpakFlags, VmpakInstallManifest, VmpakMetadata, VmpakWriter};
+
+/// Scratch data, config and game directories. Until it's dropped, everything in `core` on this thread uses them
+struct TestEnv(PathBuf);
+
+impl TestEnv {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("vmm-core-test-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(path.join("game")).unwrap();
+        fs::create_dir_all(path.join("config")).unwrap();
+        TEST_DIR.set(Some(path.clone()));
+        Self(path)
+    }
+
+    fn game(&self, path: &str) -> PathBuf {
+        self.0.join("game").join(path)
+    }
+
+    fn paths(&self) -> GamePaths {
+        GamePaths {
+            game_dir: self.0.join("game"),
+            config_dir: Some(self.0.join("config")),
+            save_dir: None,
+            load_order_file: None,
+            deploy_method: DeployMethod::Copy,
+            game_version: None,
+        }
+    }
+
+    /// Writes a VMPAK of `mod_id` with `files` and returns its path
+    fn pack(&self, mod_id: &str, files: &[(&str, &str)], dependencies: &[&str]) -> PathBuf {
+        let mut metadata = VmpakMetadata::new(mod_id, mod_id, "1.0.0", "test-game");
+        metadata.dependencies =
+            dependencies.iter().map(|d| VmpakDependency { mod_id: d.to_string(), version: None, optional: false }).collect();
+        let path = self.0.join(format!("{mod_id}.vmpak"));
+        let mut writer = VmpakWriter::new(fs::File::create(&path).unwrap(), &metadata, VmpakFlags::empty()).unwrap();
+        for (name, contents) in files {
+            writer.add_entry(name, &mut contents.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap();
+        path
+    }
+
+    fn stage(&self, mod_id: &str, files: &[(&str, &str)], dependencies: &[&str]) -> InstalledMod {
+        let package = self.pack(mod_id, files, dependencies);
+        let metadata = VmpakMetadata::new(mod_id, mod_id, "1.0.0", "test-game");
+        ModInstaller::stage(GAME, mod_id, "test-provider", &package, &self.paths(), &metadata, None).unwrap()
+    }
+
+    /// Stages the mod and confirms it right away, whatever it overwrites
+    fn install(&self, mod_id: &str, files: &[(&str, &str)], dependencies: &[&str]) -> InstalledMod {
+        self.stage(mod_id, files, dependencies);
+        ModInstaller::enable(GAME, mod_id, &self.paths()).unwrap();
+        InstalledRegistry::get(GAME, mod_id).unwrap().unwrap()
+    }
+
+    fn read(&self, path: &str) -> Option<String> {
+        fs::read_to_string(self.game(path)).ok()
+    }
+}
+
+impl Drop for TestEnv {
+    fn drop(&mut self) {
+        TEST_DIR.set(None);
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+const GAME: &str = "test-game";
+
+fn write(path: &Path, contents: &str) {
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, contents).unwrap();
+}
+
+fn load_order_file(plugin_extensions: &[&str]) -> Option<LoadOrderFile> {
+    Some(LoadOrderFile {
+        root: InstallRoot::Game,
+        path: "plugins.txt".into(),
+        plugin_extensions: plugin_extensions.iter().map(|e| e.to_string()).collect(),
+        line_prefix: "*".into(),
+    })
+}
+
+fn installed_ids() -> Vec<String> {
+    InstalledRegistry::list(GAME).unwrap().into_iter().map(|m| m.mod_id).collect()
+}
+
+#[test]
+fn uninstall_removes_exactly_what_the_mod_placed() {
+    let env = TestEnv::new("uninstall");
+    write(&env.game("data/orig.txt"), "vanilla");
+    write(&env.game("data/other.txt"), "untouched");
+
+    let installed = env.install("a", &[("data/orig.txt", "A"), ("data/sub/deep/a.txt", "a")], &[]);
+    assert_eq!(installed.files.len(), 2);
+    assert_eq!(env.read("data/orig.txt").as_deref(), Some("A"));
+    assert_eq!(env.read("data/sub/deep/a.txt").as_deref(), Some("a"));
+
+    ModInstaller::uninstall(GAME, "a", &env.paths(), false).unwrap();
+    assert_eq!(env.read("data/orig.txt").as_deref(), Some("vanilla"));
+    assert_eq!(env.read("data/other.txt").as_deref(), Some("untouched"));
+    assert!(!env.game("data/sub").exists());
+    assert!(installed_ids().is_empty());
+    assert!(!ModInstaller::staging_dir(GAME, "a").unwrap().exists());
+}
+
+#[test]
+fn uninstall_is_refused_while_dependents_are_installed() {
+    let env = TestEnv::new("dependents");
+    env.install("a", &[("a.txt", "a")], &[]);
+    env.install("b", &[("b.txt", "b")], &["a"]);
+
+    let refused = ModInstaller::uninstall(GAME, "a", &env.paths(), false).unwrap_err();
+    assert_eq!(refused.kind(), std::io::ErrorKind::InvalidInput);
+    assert_eq!(env.read("a.txt").as_deref(), Some("a"));
+
+    ModInstaller::uninstall(GAME, "a", &env.paths(), true).unwrap();
+    assert_eq!(env.read("a.txt"), None);
+    assert_eq!(installed_ids(), ["b"]);
+}
+
+#[test]
+fn shared_files_are_handed_over_to_the_next_owner() {
+    let env = TestEnv::new("hand-over");
+    write(&env.game("shared.txt"), "vanilla");
+    env.install("a", &[("shared.txt", "A")], &[]);
+    env.install("b", &[("shared.txt", "B")], &[]);
+    assert_eq!(env.read("shared.txt").as_deref(), Some("B"));
+
+    // b's copy stays, and it now owns the backup of the vanilla file a had replaced
+    ModInstaller::uninstall(GAME, "a", &env.paths(), false).unwrap();
+    assert_eq!(env.read("shared.txt").as_deref(), Some("B"));
+    let b = InstalledRegistry::get(GAME, "b").unwrap().unwrap();
+    assert_eq!(b.files[0].replaced, Some(blake3::hash(b"vanilla").to_hex().to_string()));
+
+    ModInstaller::uninstall(GAME, "b", &env.paths(), false).unwrap();
+    assert_eq!(env.read("shared.txt").as_deref(), Some("vanilla"));
+}
+
+#[test]
+fn files_changed_after_install_are_kept() {
+    let env = TestEnv::new("modified");
+    env.install("a", &[("settings.ini", "default")], &[]);
+    write(&env.game("settings.ini"), "edited by the user");
+
+    ModInstaller::uninstall(GAME, "a", &env.paths(), false).unwrap();
+    assert_eq!(env.read("settings.ini"), None);
+    let kept = BackupStore::dir(GAME).unwrap().join("modified/game/settings.ini");
+    assert_eq!(fs::read_to_string(kept).unwrap(), "edited by the user");
+}
+
+#[test]
+fn broken_config_is_an_error_before_saving() {
+    let _env = TestEnv::new("config");
+    let mut config = AppConfig::try_load().unwrap();
+    config.pack_keys.push("key".into());
+    config.save().unwrap();
+    assert_eq!(AppConfig::try_load().unwrap().pack_keys, ["key"]);
+
+    fs::write(AppConfig::path(), "{ not json").unwrap();
+    assert_eq!(AppConfig::try_load().unwrap_err().kind(), std::io::ErrorKind::InvalidData);
+    assert!(AppConfig::load().pack_keys.is_empty());
+}
+
+#[test]
+fn unsigned_packs_are_refused_when_the_config_is_broken() {
+    let env = TestEnv::new("signature-policy");
+    let package = env.pack("a", &[("a.txt", "a")], &[]);
+    AppConfig::check_package(&package).unwrap();
+
+    AppConfig { signature_policy: SignaturePolicy::Reject, ..Default::default() }.save().unwrap();
+    assert_eq!(AppConfig::check_package(&package).unwrap_err().kind(), std::io::ErrorKind::PermissionDenied);
+
+    // Falling back to the default policy would allow it
+    fs::write(AppConfig::path(), "{ not json").unwrap();
+    assert_eq!(AppConfig::check_package(&package).unwrap_err().kind(), std::io::ErrorKind::PermissionDenied);
+}
+
+#[test]
+fn disabled_mods_stay_staged_and_come_back_in_order() {
+    let env = TestEnv::new("enable");
+    write(&env.game("shared.txt"), "vanilla");
+    env.install("a", &[("shared.txt", "A"), ("dir/a.txt", "a")], &[]);
+    env.install("b", &[("shared.txt", "B"), ("b.txt", "b")], &["a"]);
+
+    assert_eq!(ModInstaller::disable(GAME, "a", &env.paths(), false).unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+    ModInstaller::disable(GAME, "b", &env.paths(), false).unwrap();
+    assert_eq!(env.read("shared.txt").as_deref(), Some("A"));
+    assert_eq!(env.read("b.txt"), None);
+    ModInstaller::disable(GAME, "a", &env.paths(), false).unwrap();
+    assert_eq!(env.read("shared.txt").as_deref(), Some("vanilla"));
+    assert!(!env.game("dir").exists());
+    assert!(ModInstaller::staging_dir(GAME, "a").unwrap().is_dir());
+
+    // b is later in the load order, so it wins even when it's enabled first
+    ModInstaller::enable(GAME, "b", &env.paths()).unwrap();
+    ModInstaller::enable(GAME, "a", &env.paths()).unwrap();
+    assert_eq!(env.read("shared.txt").as_deref(), Some("B"));
+    assert_eq!(env.read("dir/a.txt").as_deref(), Some("a"));
+    assert!(InstalledRegistry::list(GAME).unwrap().iter().all(|m| m.enabled));
+
+    ModInstaller::uninstall(GAME, "b", &env.paths(), false).unwrap();
+    ModInstaller::uninstall(GAME, "a", &env.paths(), false).unwrap();
+    assert_eq!(env.read("shared.txt").as_deref(), Some("vanilla"));
+}
+
+#[test]
+fn conflicting_installs_wait_for_confirmation() {
+    let env = TestEnv::new("confirm");
+    env.install("a", &[("shared.txt", "A")], &[]);
+
+    let package = env.pack("b", &[("shared.txt", "B"), ("b.txt", "b")], &[]);
+    let metadata = VmpakMetadata::new("b", "b", "1.0.0", "test-game");
+    let refused = ModInstaller::install(GAME, "b", "test-provider", &package, &env.paths(), &metadata, None).unwrap_err();
+    assert_eq!(refused.kind(), std::io::ErrorKind::AlreadyExists);
+    assert_eq!(env.read("shared.txt").as_deref(), Some("A"));
+    assert_eq!(env.read("b.txt"), None);
+    assert_eq!(installed_ids(), ["a"]);
+
+    let conflicts = ModInstaller::conflicts_of(GAME, "b").unwrap();
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].path, "shared.txt");
+    assert_eq!(conflicts[0].mods, ["a", "b"]);
+    assert_eq!(conflicts[0].winner, "b");
+
+    ModInstaller::enable(GAME, "b", &env.paths()).unwrap();
+    assert_eq!(env.read("shared.txt").as_deref(), Some("B"));
+    assert_eq!(installed_ids(), ["a", "b"]);
+    assert!(ModInstaller::staged(GAME, "b").unwrap().is_none());
+
+    // Nothing to confirm, so it goes straight in
+    let package = env.pack("c", &[("c.txt", "c")], &[]);
+    let installed = ModInstaller::install(GAME, "c", "test-provider", &package, &env.paths(), &metadata, None).unwrap();
+    assert!(installed.enabled);
+    assert_eq!(env.read("c.txt").as_deref(), Some("c"));
+}
+
+#[test]
+fn disabled_mods_conflict_from_their_place_in_the_load_order() {
+    let env = TestEnv::new("disabled-conflicts");
+    env.install("a", &[("shared.txt", "A")], &[]);
+    env.install("b", &[("shared.txt", "B")], &[]);
+    ModInstaller::disable(GAME, "a", &env.paths(), false).unwrap();
+
+    // a loads before b, so enabling it again doesn't win it the file
+    let conflicts = ModInstaller::conflicts_of(GAME, "a").unwrap();
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].mods, ["a", "b"]);
+    assert_eq!(conflicts[0].winner, "b");
+    ModInstaller::enable(GAME, "a", &env.paths()).unwrap();
+    assert_eq!(env.read("shared.txt").as_deref(), Some("B"));
+}
+
+#[test]
+fn staging_an_update_leaves_the_installed_copy_alone() {
+    let env = TestEnv::new("stage-update");
+    env.install("a", &[("a.txt", "1")], &[]);
+    env.install("b", &[("b.txt", "b")], &["a"]);
+
+    let staged = env.stage("a", &[("a.txt", "2"), ("new.txt", "new")], &[]);
+    assert!(!staged.enabled);
+    assert!(ModInstaller::conflicts_of_staged(GAME, &staged).unwrap().is_empty());
+    assert_eq!(env.read("a.txt").as_deref(), Some("1"));
+    assert_eq!(env.read("new.txt"), None);
+    assert_eq!(InstalledRegistry::get(GAME, "a").unwrap().unwrap().files.len(), 1);
+    assert_eq!(installed_ids(), ["a", "b"]);
+
+    ModInstaller::discard_staged(GAME, "a").unwrap();
+    assert!(ModInstaller::staged(GAME, "a").unwrap().is_none());
+    assert_eq!(env.read("a.txt").as_deref(), Some("1"));
+
+    // Confirming swaps it in without disturbing the mods that depend on it or its place in the load order
+    env.stage("a", &[("a.txt", "2"), ("new.txt", "new")], &[]);
+    ModInstaller::enable(GAME, "a", &env.paths()).unwrap();
+    assert_eq!(env.read("a.txt").as_deref(), Some("2"));
+    assert_eq!(env.read("new.txt").as_deref(), Some("new"));
+    assert_eq!(env.read("b.txt").as_deref(), Some("b"));
+    assert_eq!(installed_ids(), ["a", "b"]);
+}
+
+#[test]
+fn load_order_decides_winners_and_the_plugin_list() {
+    let env = TestEnv::new("load-order");
+    env.install("a", &[("shared.txt", "A"), ("Data/a.esp", "a")], &[]);
+    env.install("b", &[("shared.txt", "B"), ("Data/b.esp", "b"), ("readme.txt", "b")], &[]);
+    env.install("c", &[("c.txt", "c")], &[]);
+    let mut paths = env.paths();
+    paths.load_order_file = load_order_file(&["ESP"]);
+
+    ModInstaller::move_before(GAME, "b", "a", &paths).unwrap();
+    assert_eq!(installed_ids(), ["b", "a", "c"]);
+    assert_eq!(env.read("shared.txt").as_deref(), Some("A"));
+    assert_eq!(env.read("plugins.txt").as_deref(), Some("*b.esp\n*a.esp\n"));
+
+    ModInstaller::move_after(GAME, "b", "c", &paths).unwrap();
+    assert_eq!(installed_ids(), ["a", "c", "b"]);
+    assert_eq!(env.read("shared.txt").as_deref(), Some("B"));
+    assert_eq!(env.read("plugins.txt").as_deref(), Some("*a.esp\n*b.esp\n"));
+
+    ModInstaller::set_priority(GAME, "b", 0, &paths).unwrap();
+    assert_eq!(installed_ids(), ["b", "a", "c"]);
+    assert_eq!(env.read("shared.txt").as_deref(), Some("A"));
+
+    ModInstaller::disable(GAME, "a", &paths, false).unwrap();
+    assert_eq!(env.read("shared.txt").as_deref(), Some("B"));
+    assert_eq!(env.read("plugins.txt").as_deref(), Some("*b.esp\n"));
+}
+
+#[test]
+fn no_load_order_file_without_plugin_extensions() {
+    let env = TestEnv::new("no-plugins");
+    env.install("a", &[("Data/a.esp", "a")], &[]);
+    env.install("b", &[("b.txt", "b")], &[]);
+    let mut paths = env.paths();
+    paths.load_order_file = load_order_file(&[]);
+
+    ModInstaller::set_priority(GAME, "b", 0, &paths).unwrap();
+    ModInstaller::disable(GAME, "a", &paths, false).unwrap();
+    assert_eq!(installed_ids(), ["b", "a"]);
+    assert!(!env.game("plugins.txt").exists());
+}
+
+#[test]
+fn profiles_switch_mods_order_and_overrides() {
+    let env = TestEnv::new("profiles");
+    let ini = env.0.join("config/game.ini");
+    write(&ini, "vanilla");
+    env.install("a", &[("a.txt", "a")], &[]);
+    env.install("b", &[("b.txt", "b")], &[]);
+    let paths = env.paths();
+
+    let overrides = vec![ConfigOverride { root: InstallRoot::Config, path: "game.ini".into(), contents: "fov=90".into() }];
+    ProfileStore::set_overrides(GAME, "Default", overrides, &paths).unwrap();
+    assert_eq!(fs::read_to_string(&ini).unwrap(), "fov=90");
+    let empty = ProfileStore::create(GAME, "empty").unwrap();
+    assert!(empty.mods.iter().all(|m| !m.enabled));
+    ProfileStore::clone_profile(GAME, "Default", "copy").unwrap();
+
+    ProfileStore::switch(GAME, "empty", &paths).unwrap();
+    assert_eq!((env.read("a.txt"), env.read("b.txt")), (None, None));
+    assert_eq!(fs::read_to_string(&ini).unwrap(), "vanilla");
+    assert_eq!(ProfileStore::list(GAME).unwrap().active, "empty");
+
+    ProfileStore::switch(GAME, "Default", &paths).unwrap();
+    assert_eq!(env.read("a.txt").as_deref(), Some("a"));
+    assert_eq!(fs::read_to_string(&ini).unwrap(), "fov=90");
+
+    // Each profile keeps its own load order
+    ModInstaller::move_before(GAME, "b", "a", &paths).unwrap();
+    ProfileStore::switch(GAME, "copy", &paths).unwrap();
+    assert_eq!(installed_ids(), ["a", "b"]);
+    assert_eq!(fs::read_to_string(&ini).unwrap(), "fov=90");
+    ProfileStore::switch(GAME, "Default", &paths).unwrap();
+    assert_eq!(installed_ids(), ["b", "a"]);
+
+    assert_eq!(ProfileStore::delete(GAME, "Default").unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+    ProfileStore::delete(GAME, "empty").unwrap();
+    let names: Vec<_> = ProfileStore::list(GAME).unwrap().profiles.into_iter().map(|p| p.name).collect();
+    assert_eq!(names, ["Default", "copy"]);
+}
+
+#[test]
+fn profile_names_are_checked() {
+    let env = TestEnv::new("profile-names");
+    assert_eq!(ProfileStore::create(GAME, " ").unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+    assert_eq!(ProfileStore::create(GAME, "Default").unwrap_err().kind(), std::io::ErrorKind::AlreadyExists);
+    assert_eq!(ProfileStore::switch(GAME, "missing", &env.paths()).unwrap_err().kind(), std::io::ErrorKind::NotFound);
+}
+
+#[test]
+fn purge_leaves_the_game_vanilla() {
+    let env = TestEnv::new("purge");
+    write(&env.game("data/orig.txt"), "vanilla");
+    let mut paths = env.paths();
+    paths.load_order_file = load_order_file(&["esp"]);
+    env.install("a", &[("data/orig.txt", "A"), ("data/new/a.esp", "a")], &[]);
+    ModInstaller::write_load_order(GAME, &paths).unwrap();
+    let overrides = vec![ConfigOverride { root: InstallRoot::Config, path: "game.ini".into(), contents: "fov=90".into() }];
+    ProfileStore::set_overrides(GAME, "Default", overrides, &paths).unwrap();
+    assert_eq!(env.read("plugins.txt").as_deref(), Some("*a.esp\n"));
+    assert!(!BackupStore::verify_vanilla(GAME, &paths).unwrap().is_empty());
+
+    ModInstaller::purge(GAME, &paths).unwrap();
+    assert_eq!(env.read("data/orig.txt").as_deref(), Some("vanilla"));
+    assert!(!env.game("data/new").exists());
+    assert!(!env.game("plugins.txt").exists());
+    assert!(!env.0.join("config/game.ini").exists());
+    assert_eq!(BackupStore::verify_vanilla(GAME, &paths).unwrap(), []);
+    assert!(InstalledRegistry::list(GAME).unwrap().iter().all(|m| !m.enabled));
+}
+
+#[test]
+fn placing_never_writes_through_to_the_staged_copy() {
+    let env = TestEnv::new("place");
+    let staged = env.0.join("staged.txt");
+    let destination = env.game("placed.txt");
+    write(&staged, "staged");
+    let modified = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_700_000_000);
+    fs::File::options().write(true).open(&staged).unwrap().set_modified(modified).unwrap();
+
+    // Whatever is at the destination, a link included, gets replaced instead of written through
+    assert_eq!(deploy::place(&staged, &destination, DeployMethod::Symlink).unwrap(), DeployMethod::Symlink);
+    let used = deploy::place(&staged, &destination, DeployMethod::Reflink).unwrap();
+    // Filesystems that can't clone fall back to a copy with the staged modification time
+    if used == DeployMethod::Copy {
+        assert_eq!(fs::metadata(&destination).unwrap().modified().unwrap(), modified);
+    }
+    fs::write(&destination, "changed in the game").unwrap();
+    assert_eq!(fs::read_to_string(&staged).unwrap(), "staged");
+
+    // Copying is only a fallback for the link, a missing staged file is still an error
+    assert!(deploy::place(&env.0.join("missing"), &destination, DeployMethod::Hardlink).is_err());
+}
+
+#[test]
+fn clearing_refuses_directories() {
+    let env = TestEnv::new("clear");
+    fs::create_dir_all(env.game("dir/inner")).unwrap();
+    assert_eq!(deploy::clear(&env.game("dir")).unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+    assert!(env.game("dir/inner").is_dir());
+
+    deploy::clear(&env.game("missing.txt")).unwrap();
+    write(&env.game("file.txt"), "file");
+    deploy::clear(&env.game("file.txt")).unwrap();
+    assert!(!env.game("file.txt").exists());
+}
+
+/// Hashes of the backups in the store
+fn backups() -> Vec<String> {
+    let Ok(entries) = fs::read_dir(BackupStore::dir(GAME).unwrap()) else {
+        return Vec::new();
+    };
+    let mut hashes: Vec<_> = entries.map(|e| e.unwrap().file_name().into_string().unwrap()).filter(|n| n.len() == 64).collect();
+    hashes.sort();
+    hashes
+}
+
+/// Runs `change` in a transaction that never finishes, as if the manager crashed in the middle of it
+fn crash(change: impl FnOnce()) {
+    let run = || Journal::run(GAME, "crash", || -> std::io::Result<()> {
+        change();
+        panic!("crashed")
+    });
+    assert!(std::panic::catch_unwind(std::panic::AssertUnwindSafe(run)).is_err());
+}
+
+#[test]
+fn failed_deploys_roll_everything_back() {
+    let env = TestEnv::new("roll-back");
+    write(&env.game("shared.txt"), "vanilla");
+    env.install("a", &[("shared.txt", "A")], &[]);
+    env.install("b", &[("b.txt", "b"), ("blocked", "b")], &[]);
+    let paths = env.paths();
+    ProfileStore::create(GAME, "empty").unwrap();
+    ProfileStore::switch(GAME, "empty", &paths).unwrap();
+
+    // b's last file can't be placed anymore, the game has a directory there now
+    fs::create_dir_all(env.game("blocked/inner")).unwrap();
+    let registry = fs::read(InstalledRegistry::path(GAME).unwrap()).unwrap();
+    let profiles = fs::read(ProfileStore::path(GAME).unwrap()).unwrap();
+    assert!(ProfileStore::switch(GAME, "Default", &paths).is_err());
+    assert_eq!(env.read("shared.txt").as_deref(), Some("vanilla"));
+    assert_eq!(env.read("b.txt"), None);
+    assert!(env.game("blocked/inner").is_dir());
+    assert_eq!(fs::read(InstalledRegistry::path(GAME).unwrap()).unwrap(), registry);
+    assert_eq!(fs::read(ProfileStore::path(GAME).unwrap()).unwrap(), profiles);
+    assert!(!Journal::dir().join(format!("{GAME}.jsonl")).exists());
+
+    // Same for confirming an update, which stays staged
+    ModInstaller::enable(GAME, "a", &paths).unwrap();
+    env.stage("a", &[("shared.txt", "A2"), ("blocked", "a")], &[]);
+    assert!(ModInstaller::enable(GAME, "a", &paths).is_err());
+    assert_eq!(env.read("shared.txt").as_deref(), Some("A"));
+    assert_eq!(InstalledRegistry::get(GAME, "a").unwrap().unwrap().files.len(), 1);
+    assert!(ModInstaller::staged(GAME, "a").unwrap().is_some());
+}
+
+#[test]
+fn crashes_are_rolled_back_on_the_next_start() {
+    let env = TestEnv::new("crash");
+    write(&env.game("shared.txt"), "vanilla");
+    let installed = env.install("a", &[("shared.txt", "A"), ("dir/a.txt", "a")], &[]);
+    let paths = env.paths();
+    let registry = fs::read(InstalledRegistry::path(GAME).unwrap()).unwrap();
+    let vanilla = installed.files[0].replaced.clone().unwrap();
+
+    let mut backed_up = Vec::new();
+    crash(|| {
+        ModInstaller::disable(GAME, "a", &paths, false).unwrap();
+        backed_up = backups();
+        ModInstaller::uninstall(GAME, "a", &paths, false).unwrap();
+    });
+    assert_eq!(env.read("shared.txt").as_deref(), Some("vanilla"));
+    // What a deployed comes back from its staged copy, so it wasn't backed up
+    assert_eq!(backed_up, [vanilla]);
+
+    // The crash cut off the step it was writing
+    let journal = Journal::dir().join(format!("{GAME}.jsonl"));
+    fs::File::options().append(true).open(&journal).unwrap().write_all(br#"{"kind":"file","root":"#).unwrap();
+    let unfinished = fs::read(&journal).unwrap();
+    Journal::recover_all();
+    assert!(!journal.exists());
+    assert_eq!(env.read("shared.txt").as_deref(), Some("A"));
+    assert_eq!(env.read("dir/a.txt").as_deref(), Some("a"));
+    assert!(ModInstaller::staging_dir(GAME, "a").unwrap().is_dir());
+    assert_eq!(fs::read(InstalledRegistry::path(GAME).unwrap()).unwrap(), registry);
+
+    // Crashing again before the journal was removed rolls back what's rolled back already
+    fs::write(&journal, unfinished).unwrap();
+    Journal::recover_all();
+    assert!(!journal.exists());
+    assert_eq!(env.read("shared.txt").as_deref(), Some("A"));
+    assert_eq!(env.read("dir/a.txt").as_deref(), Some("a"));
+    assert_eq!(fs::read(InstalledRegistry::path(GAME).unwrap()).unwrap(), registry);
+}
+
+#[test]
+fn backups_are_dropped_once_nothing_needs_them() {
+    let env = TestEnv::new("backups");
+    write(&env.game("shared.txt"), "vanilla");
+    let vanilla = env.install("a", &[("shared.txt", "A")], &[]).files[0].replaced.clone().unwrap();
+    env.install("b", &[("shared.txt", "B")], &[]);
+    assert_eq!(backups().len(), 2);
+
+    ModInstaller::uninstall(GAME, "b", &env.paths(), false).unwrap();
+    assert_eq!(env.read("shared.txt").as_deref(), Some("A"));
+    assert_eq!(backups(), [vanilla.as_str()]);
+
+    // The original stays until it's no longer needed to get back to vanilla either
+    ModInstaller::uninstall(GAME, "a", &env.paths(), false).unwrap();
+    assert_eq!(backups(), [vanilla]);
+}
+
+#[test]
+fn originals_are_listed_verified_and_restored() {
+    let env = TestEnv::new("originals");
+    write(&env.game("orig.txt"), "vanilla");
+    env.install("a", &[("orig.txt", "A"), ("new.txt", "new")], &[]);
+    let paths = env.paths();
+
+    let originals = BackupStore::list_originals(GAME).unwrap();
+    let listed: Vec<_> = originals.iter().map(|o| (o.path.as_str(), o.hash.is_some())).collect();
+    assert_eq!(listed, [("orig.txt", true), ("new.txt", false)]);
+    let mismatches = BackupStore::verify_vanilla(GAME, &paths).unwrap();
+    assert_eq!(mismatches.len(), 2);
+    assert_eq!(mismatches[0].expected, originals[0].hash);
+    assert_eq!(mismatches[1].expected, None);
+
+    let refused = BackupStore::restore_original(GAME, InstallRoot::Game, "orig.txt", &paths).unwrap_err();
+    assert_eq!(refused.kind(), std::io::ErrorKind::InvalidInput);
+    assert_eq!(env.read("orig.txt").as_deref(), Some("A"));
+    let unknown = BackupStore::restore_original(GAME, InstallRoot::Game, "other.txt", &paths).unwrap_err();
+    assert_eq!(unknown.kind(), std::io::ErrorKind::NotFound);
+
+    // Once nothing owns them, files changed outside the manager can be put back one at a time
+    ModInstaller::disable(GAME, "a", &paths, false).unwrap();
+    write(&env.game("orig.txt"), "edited");
+    write(&env.game("new.txt"), "stray");
+    assert_eq!(BackupStore::verify_vanilla(GAME, &paths).unwrap().len(), 2);
+    BackupStore::restore_original(GAME, InstallRoot::Game, "orig.txt", &paths).unwrap();
+    assert_eq!(env.read("orig.txt").as_deref(), Some("vanilla"));
+    assert_eq!(BackupStore::verify_vanilla(GAME, &paths).unwrap().len(), 1);
+    BackupStore::restore_original(GAME, InstallRoot::Game, "new.txt", &paths).unwrap();
+    assert_eq!(env.read("new.txt"), None);
+    assert_eq!(BackupStore::verify_vanilla(GAME, &paths).unwrap(), []);
+}
+
+#[test]
+fn originals_are_seeded_from_what_deployed_mods_replaced() {
+    let env = TestEnv::new("seed-originals");
+    write(&env.game("orig.txt"), "vanilla");
+    env.install("a", &[("orig.txt", "A")], &[]);
+    env.install("b", &[("orig.txt", "B"), ("b.txt", "b")], &[]);
+    let indexed = BackupStore::list_originals(GAME).unwrap();
+    assert_eq!(indexed.len(), 2);
+
+    // Games set up before originals were indexed have no index, the first owner of a file knows what it replaced
+    fs::remove_file(BackupStore::dir(GAME).unwrap().join("originals.json")).unwrap();
+    assert_eq!(BackupStore::list_originals(GAME).unwrap(), indexed);
+    ModInstaller::purge(GAME, &env.paths()).unwrap();
+    assert_eq!(env.read("orig.txt").as_deref(), Some("vanilla"));
+    assert_eq!(env.read("b.txt"), None);
+}
+
+#[test]
+fn manifests_follow_the_game_version_and_picked_components() {
+    let env = TestEnv::new("manifest");
+    let mapping = |source: &str, versions: &[&str]| InstallMapping {
+        source: source.into(),
+        target: String::new(),
+        root: InstallRoot::Game,
+        when: (!versions.is_empty())
+            .then(|| InstallCondition { game_versions: versions.iter().map(|v| v.to_string()).collect(), ..Default::default() }),
+    };
+    let mut metadata = VmpakMetadata::new("m", "m", "1.0.0", GAME);
+    metadata.install = Some(VmpakInstallManifest {
+        files: vec![mapping("base/", &[]), mapping("v1/", &["1.*"]), mapping("v2/", &["2.*"])],
+        components: vec![InstallComponent {
+            id: "extra".into(),
+            name: "Extra".into(),
+            description: String::new(),
+            default: false,
+            files: vec![mapping("extra/", &[])],
+            when: None,
+        }],
+    });
+    let package = env.0.join("m.vmpak");
+    let mut writer = VmpakWriter::new(fs::File::create(&package).unwrap(), &metadata, VmpakFlags::empty()).unwrap();
+    for name in ["base/base.txt", "v1/patch.txt", "v2/patch.txt", "extra/extra.txt"] {
+        writer.add_entry(name, &mut name.as_bytes()).unwrap();
+    }
+    writer.finish().unwrap();
+
+    let mut paths = env.paths();
+    paths.game_version = Some("1.4".into());
+    ModInstaller::install(GAME, "m", "test-provider", &package, &paths, &metadata, None).unwrap();
+    assert_eq!(env.read("base.txt").as_deref(), Some("base/base.txt"));
+    assert_eq!(env.read("patch.txt").as_deref(), Some("v1/patch.txt"));
+    assert_eq!(env.read("extra.txt"), None);
+
+    // Reinstalling once the game is updated, this time with the optional component
+    paths.game_version = Some("2.0".into());
+    ModInstaller::install(GAME, "m", "test-provider", &package, &paths, &metadata, Some(vec!["extra".into()])).unwrap();
+    assert_eq!(env.read("patch.txt").as_deref(), Some("v2/patch.txt"));
+    assert_eq!(env.read("extra.txt").as_deref(), Some("extra/extra.txt"));
+    let unknown = ModInstaller::install(GAME, "m", "test-provider", &package, &paths, &metadata, Some(vec!["missing".into()]));
+    assert_eq!(unknown.unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn archives_are_staged_as_they_are_unpacked() {
+    let env = TestEnv::new("archive");
+    env.install("a", &[("a.txt", "a")], &[]);
+    let archive = env.0.join("z.zip");
+    let mut writer = zip::ZipWriter::new(fs::File::create(&archive).unwrap());
+    for (name, contents) in [("data/z.txt", "z"), ("z.ini", "ini")] {
+        writer.start_file(name, zip::write::SimpleFileOptions::default()).unwrap();
+        writer.write_all(contents.as_bytes()).unwrap();
+    }
+    writer.finish().unwrap();
+
+    // Archives carry no metadata of their own, what the provider knows describes them
+    let mut metadata = VmpakMetadata::new("z", "Zipped", "2.1.0", GAME);
+    metadata.dependencies = vec![VmpakDependency { mod_id: "a".into(), version: None, optional: false }];
+    let mut manifest = VmpakInstallManifest::everything();
+    manifest.files.push(InstallMapping { source: "z.ini".into(), target: "z.ini".into(), root: InstallRoot::Config, when: None });
+    metadata.install = Some(manifest);
+
+    let installed = ModInstaller::install(GAME, "z", "test-provider", &archive, &env.paths(), &metadata, None).unwrap();
+    assert_eq!((installed.version.as_str(), installed.dependencies.as_slice()), ("2.1.0", ["a".to_string()].as_slice()));
+    assert_eq!(installed.files.len(), 3);
+    assert_eq!(env.read("data/z.txt").as_deref(), Some("z"));
+    assert_eq!(env.read("z.ini").as_deref(), Some("ini"));
+    assert_eq!(fs::read_to_string(env.0.join("config/z.ini")).unwrap(), "ini");
+    assert!(!ModInstaller::staging_dir(GAME, "z").unwrap().join(".unpacked").exists());
+}
+
+#[test]
+fn installed_packs_keep_their_assets() {
+    let env = TestEnv::new("assets");
+    env.install(
+        "a",
+        &[
+            ("a.txt", "a"),
+            (".vmpak/README.md", "# Sharper textures"),
+            (".vmpak/names.json", r#"{ "de": "Schärfere Texturen" }"#),
+            (".vmpak/screenshots/1.png", "png"),
+        ],
+        &[],
+    );
+    assert_eq!(env.read(".vmpak/README.md"), None);
+
+    let details = ModInstaller::pack_details(GAME, "a").unwrap().unwrap();
+    assert_eq!(details.description, "# Sharper textures");
+    assert_eq!(details.carousel_images.len(), 1);
+    assert!(details.installed);
+    assert_eq!(ModInstaller::pack_summary(GAME, "a", Some("de-AT")).unwrap().unwrap().name, "Schärfere Texturen");
+
+    // Without assets the provider knows more, but the pack still describes itself
+    env.install("b", &[("b.txt", "b")], &[]);
+    assert!(ModInstaller::pack_details(GAME, "b").unwrap().is_none());
+    assert_eq!(ModInstaller::pack_summary(GAME, "b", None).unwrap().unwrap().name, "b");
+    assert!(ModInstaller::pack_summary(GAME, "missing", None).unwrap().is_none());
+}
@@ -3,7 +3,7 @@ use std::sync::Arc;
 
 use lib_vmm::runtime::Context as AppContext;
 use taurpc::Router;
-use crate::{core::{DefaultDownloadService}};
+use crate::{core::{DefaultDownloadService, ExtractService}};
 use crate::services::{ModService, ModServiceImpl, CapabilityService, CapabilityServiceImpl};
 
 
@@ -21,6 +21,7 @@ pub fn run(ctx: Arc<AppContext>, download_service: Arc<DefaultDownloadService>)
   tauri::Builder::default()
     .setup(move |app| {
         download_service.set_handle(app.handle().clone());
+        ExtractService::set_handle(app.handle().clone());
         Ok(())
     })
     .manage(ctx.clone())
@@ -0,0 +1,2 @@
+//! The VMPAK format, shared by the app, the `vmpak` command line tool and the fuzz targets
+pub mod binary;
@@ -1,6 +1,5 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 mod core;
-mod binary;
 mod frontend;
 mod services;
 
@@ -8,6 +7,7 @@ use lib_vmm::{api::DefaultProviderApi, runtime::ContextBuilder};
 use tracing::{info, trace, warn};
 use tracing_log::LogTracer;
 use std::{env, sync::Arc};
+use void_mod_manager_lib::binary;
 
 use crate::core::DefaultDownloadService;
 
@@ -34,6 +34,9 @@ async fn main() {
         }
     }
 
+    // Before anything else touches a game, undo what a crash left halfway
+    core::Journal::recover_all();
+
     let mut ctx_builder = ContextBuilder::new();
 
     let download_service = Arc::new(DefaultDownloadService::new());
@@ -1,7 +1,20 @@
-use std::sync::Arc;
+use std::{collections::BTreeMap, io, path::{Path, PathBuf}, sync::{Arc, Mutex, PoisonError}};
 
-use lib_vmm::{registry::RegistryError, runtime::Context as AppContext, traits::{discovery::{DiscoveryQuery, DiscoveryResult, ModExtendedMetadata}, game_provider::GameMetadata, mod_provider::ModDownloadResult}};
+use lib_vmm::{registry::RegistryError, runtime::Context as AppContext, traits::{discovery::{DiscoveryQuery, DiscoveryResult, ModExtendedMetadata, ModSummary}, game_provider::GameMetadata, mod_provider::ModDownloadResult}};
 use taurpc::procedures;
+use tracing::{error, info, warn};
+
+use crate::binary::{is_vmpak, ArchiveKind, InstallRoot, VmpakKey, VmpakMetadata};
+use crate::core::{
+    hash_package, open_pack, set_pack_key, unix_now, AppConfig, BackupStore, ConfigOverride, ExtractService, FileConflict, GameProfiles, GamePaths, InstalledMod, InstalledRegistry,
+    ModInstaller, OriginalFile, Profile, ProfileStore, VanillaMismatch,
+};
+
+/// Advertised by mod providers of games that read a plugin list, the load order file is only written for those
+const LOAD_ORDER_CAPABILITY: &str = "load_order_file";
+
+/// Mods seen while browsing, by id. Packages that carry no metadata of their own get it from here when they're installed
+static SUMMARIES: Mutex<BTreeMap<String, ModSummary>> = Mutex::new(BTreeMap::new());
 
 #[procedures(export_to = "../src/generated/types.ts")]
 pub trait ModService {
@@ -19,7 +32,88 @@ pub trait ModService {
 
     async fn list_games() -> Result<Vec<String>, ()>;
 
-    async fn download_mod(id: String) -> Result<(), ()>;
+    /// `components` are the optional parts of the mod to install, `None` for the pack's defaults.
+    /// A mod that would overwrite files of other mods is only staged, its conflicts are returned so the user can
+    /// `enable_mod` or `discard_staged` it. Empty once the mod is deployed
+    async fn download_mod(id: String, components: Option<Vec<String>>) -> Result<Vec<FileConflict>, String>;
+
+    /// In load order, later mods win the files they share with earlier ones
+    async fn list_installed(game_id: String) -> Result<Vec<InstalledMod>, ()>;
+
+    /// Looks the mod up in the active game's registry
+    async fn get_installed(mod_id: String) -> Option<InstalledMod>;
+
+    /// The installed mod as its own pack describes it, with the name for `locale` (e.g. `de-AT`) if the pack has one.
+    /// `None` for mods that weren't installed from a VMPAK
+    async fn get_installed_summary(mod_id: String, locale: Option<String>) -> Option<ModSummary>;
+
+    /// Removes the files the mod placed in the active game and restores what they replaced.
+    /// Refused while other mods depend on it unless `force` is set, the error says why
+    async fn uninstall_mod(mod_id: String, force: bool) -> Result<(), String>;
+
+    /// Downloads and extracts the mod for the active game without deploying it or touching an installed copy,
+    /// returning the files it would overwrite. `enable_mod` swaps it in once the user confirms, `discard_staged` throws it away.
+    /// `components` are the optional parts of the mod to install, `None` for the pack's defaults
+    async fn stage_mod(id: String, components: Option<Vec<String>>) -> Result<Vec<FileConflict>, String>;
+
+    /// Throws away what `stage_mod` extracted, an installed copy of the mod stays as it is
+    async fn discard_staged(mod_id: String) -> Result<(), String>;
+
+    /// Files the enabled mods of the game overwrite in each other
+    async fn get_conflicts(game_id: String) -> Result<Vec<FileConflict>, ()>;
+
+    /// Conflicts of one mod of the active game, for a disabled one what enabling it would overwrite
+    async fn get_mod_conflicts(mod_id: String) -> Result<Vec<FileConflict>, String>;
+
+    /// Deploys the staged files of a disabled mod into the active game again, or confirms an update `stage_mod` staged
+    async fn enable_mod(mod_id: String) -> Result<(), String>;
+
+    /// Takes the mod's files out of the active game but keeps them staged for `enable_mod`
+    async fn disable_mod(mod_id: String, force: bool) -> Result<(), String>;
+
+    /// Moves the mod to `priority` in the active game's load order, 0 loads first
+    async fn set_priority(mod_id: String, priority: u32) -> Result<(), String>;
+
+    /// Moves the mod right in front of `other_id`, which then wins the files both provide
+    async fn move_before(mod_id: String, other_id: String) -> Result<(), String>;
+
+    /// Moves the mod right after `other_id`, so it wins the files both provide
+    async fn move_after(mod_id: String, other_id: String) -> Result<(), String>;
+
+    /// Takes every mod and config override out of the active game, the mods stay installed but disabled
+    async fn purge_game() -> Result<(), String>;
+
+    /// Every file of the game the manager ever wrote to, with a backup of what was there before
+    async fn list_originals(game_id: String) -> Result<Vec<OriginalFile>, ()>;
+
+    /// Puts the original of a file of the active game back, refused while a mod or config override owns it
+    async fn restore_original(root: InstallRoot, path: String) -> Result<(), String>;
+
+    /// Game files that differ from their originals, empty if the game is vanilla
+    async fn verify_vanilla(game_id: String) -> Result<Vec<VanillaMismatch>, ()>;
+
+    async fn list_profiles(game_id: String) -> Result<GameProfiles, ()>;
+
+    /// Adds a profile to the active game with every mod disabled
+    async fn create_profile(name: String) -> Result<Profile, String>;
+
+    async fn clone_profile(source: String, name: String) -> Result<Profile, String>;
+
+    /// Redeploys the active game with the profile's mods, load order and config overrides
+    async fn switch_profile(name: String) -> Result<(), String>;
+
+    async fn delete_profile(name: String) -> Result<(), String>;
+
+    async fn set_profile_overrides(name: String, overrides: Vec<ConfigOverride>) -> Result<(), String>;
+
+    async fn get_game_paths(game_id: String) -> Option<GamePaths>;
+
+    /// Once set, mods of the game are installed by the manager and can be uninstalled again
+    async fn set_game_paths(game_id: String, paths: GamePaths) -> Result<(), ()>;
+
+    /// Stores a hex encoded VMPAK key in the keyring. Keys named after a mod id open that mod's packs,
+    /// any other name is tried on every encrypted pack
+    async fn set_pack_key(key_id: String, key: String) -> Result<(), String>;
 }
 
 #[derive(Clone)]
@@ -27,6 +121,175 @@ pub struct ModServiceImpl {
     pub ctx: Arc<AppContext>
 }
 
+impl ModServiceImpl {
+    /// Applies the configured signature policy to a downloaded package before it gets installed
+    async fn check_package_signature(path: PathBuf) -> Result<(), ()> {
+        tokio::task::spawn_blocking(move || AppConfig::check_package(&path))
+            .await
+            .map_err(|e| error!("Signature check task failed: {}", e))?
+            .map_err(|e| error!("Refusing to install package: {}", e))
+    }
+
+    /// Hashes the package and, for VMPAKs, reads its version.
+    /// Encrypted packs without a stored key that opens them only get hashed
+    fn describe_package(path: &Path, mod_id: &str) -> io::Result<(String, Option<String>)> {
+        let hash = hash_package(path)?;
+        let version = match is_vmpak(path)? {
+            true => open_pack(path, mod_id)
+                .map_err(|e| warn!("Can't read installed package {}: {}", path.display(), e))
+                .ok()
+                .map(|reader| reader.metadata().version.clone()),
+            false => None,
+        };
+        Ok((hash, version))
+    }
+
+    /// Game providers get archives extracted into a directory next to the download, so none of them has to deal
+    /// with archive formats. VMPAKs, RAR archives and anything that isn't a known archive are handed over as they are
+    async fn unpack_for_provider(mod_id: String, package: PathBuf) -> Result<PathBuf, ()> {
+        let unpacked = tokio::task::spawn_blocking(move || -> io::Result<PathBuf> {
+            // There's no RAR decoder here, but the provider may have one
+            let unpackable = ArchiveKind::detect(&package)?.is_some_and(|kind| kind != ArchiveKind::Rar);
+            if is_vmpak(&package)? || !unpackable {
+                return Ok(package);
+            }
+            let mut destination = package.clone().into_os_string();
+            destination.push(".extracted");
+            let destination = PathBuf::from(destination);
+            if destination.exists() {
+                std::fs::remove_dir_all(&destination)?;
+            }
+            ExtractService::extract(&mod_id, &package, &destination)?;
+            Ok(destination)
+        })
+        .await;
+        match unpacked {
+            Ok(Ok(path)) => Ok(path),
+            Ok(Err(e)) => {
+                error!("Failed to extract downloaded package: {}", e);
+                Err(())
+            }
+            Err(e) => {
+                error!("Extraction task failed: {}", e);
+                Err(())
+            }
+        }
+    }
+
+    /// Remembers a finished install, a failure here doesn't undo the install so it's only logged
+    async fn record_install(&self, game_id: String, provider_id: String, mod_id: String, package: PathBuf) {
+        let described = {
+            let mod_id = mod_id.clone();
+            tokio::task::spawn_blocking(move || Self::describe_package(&package, &mod_id)).await
+        };
+        let (archive_hash, version) = match described {
+            Ok(Ok(described)) => described,
+            Ok(Err(e)) => {
+                error!("Failed to read installed package of {}: {}", mod_id, e);
+                return;
+            }
+            Err(e) => {
+                error!("Package inspection task failed: {}", e);
+                return;
+            }
+        };
+
+        // Only VMPAKs say what version they are, fall back to what the provider reports
+        let version = match version {
+            Some(version) => version,
+            None => self.ctx.get_extended_info(&mod_id).await.map(|info| info.version).unwrap_or_default(),
+        };
+
+        // The game provider decides where the package's entries end up, so none of its files are claimed
+        let installed = InstalledMod {
+            mod_id,
+            game_id,
+            version,
+            provider_id,
+            installed_at: unix_now(),
+            archive_hash,
+            managed: false,
+            enabled: true,
+            files: Vec::new(),
+            dependencies: Vec::new(),
+        };
+        if let Err(e) = InstalledRegistry::record(installed) {
+            error!("Failed to record installed mod: {}", e);
+        }
+    }
+
+    /// What the mod provider told us about `mod_id`, for packages that don't describe themselves
+    async fn provider_metadata(&self, game_id: &str, mod_id: &str) -> VmpakMetadata {
+        let extended = self.ctx.get_extended_info(mod_id).await.ok();
+        let summary = SUMMARIES.lock().unwrap_or_else(PoisonError::into_inner).get(mod_id).cloned();
+        VmpakMetadata::from_provider(game_id, mod_id, summary.as_ref(), extended.as_ref())
+    }
+
+    /// Installs through the manager itself, which tracks every file it writes.
+    /// Returns the conflicts of a mod that was only staged because it would overwrite files of other mods
+    async fn install_managed(
+        &self,
+        game_id: String,
+        provider_id: String,
+        mod_id: String,
+        package: PathBuf,
+        paths: GamePaths,
+        components: Option<Vec<String>>,
+    ) -> Result<Vec<FileConflict>, String> {
+        let metadata = self.provider_metadata(&game_id, &mod_id).await;
+        tokio::task::spawn_blocking(move || {
+            match ModInstaller::install(&game_id, &mod_id, &provider_id, &package, &paths, &metadata, components) {
+                Ok(()) => Ok(Vec::new()),
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                    info!("{}", e);
+                    ModInstaller::conflicts_of(&game_id, &mod_id)
+                }
+                Err(e) => Err(e),
+            }
+        })
+        .await
+        .map_err(|e| format!("Install task failed: {e}"))?
+        .map_err(|e| {
+            error!("Failed to install mod: {}", e);
+            e.to_string()
+        })
+    }
+
+    /// Where the active game lives, if that's configured. Its load order file is left out unless the game's
+    /// mod provider says the game reads one
+    fn active_game_paths(&self, game_id: &str) -> Option<GamePaths> {
+        let mut paths = AppConfig::load().game_paths.remove(game_id)?;
+        let supported = self
+            .ctx
+            .active_game_required_provider()
+            .and_then(|provider_id| self.ctx.get_mod_provider(&provider_id).ok())
+            .is_some_and(|provider| provider.capabilities().iter().any(|cap| cap.id() == LOAD_ORDER_CAPABILITY));
+        if !supported && paths.load_order_file.take().is_some() {
+            warn!("Ignoring the load order file of {}, its provider doesn't support one", game_id);
+        }
+        Some(paths)
+    }
+
+    /// Runs a blocking operation of the managed installer on the active game
+    async fn with_installer<T: Send + 'static>(
+        &self,
+        operation: impl FnOnce(&str, &GamePaths) -> io::Result<T> + Send + 'static,
+    ) -> Result<T, String> {
+        let game_id = self.ctx.active_game().ok_or_else(|| "No active game selected".to_string())?;
+        let paths = self
+            .active_game_paths(&game_id)
+            .ok_or_else(|| format!("The install location of {game_id} isn't configured"))?;
+
+        tokio::task::spawn_blocking(move || operation(&game_id, &paths))
+            .await
+            .map_err(|e| format!("Installer task failed: {e}"))?
+            .map_err(|e| {
+                error!("Mod installer failed: {}", e);
+                e.to_string()
+            })
+    }
+}
+
 #[taurpc::resolvers]
 impl ModService for ModServiceImpl {
     async fn greet(self) -> String {
@@ -55,11 +318,27 @@ impl ModService for ModServiceImpl {
         })
         .await.map_err(|e| RegistryError::NotFound(format!("Discovery error: {e}")));
 
+        if let Ok(result) = &result {
+            let mut summaries = SUMMARIES.lock().unwrap_or_else(PoisonError::into_inner);
+            summaries.extend(result.mods.iter().map(|m| (m.id.clone(), m.clone())));
+        }
         result
     }
 
     async fn get_extended_info(self, id: String) -> Result<ModExtendedMetadata, RegistryError> {
-        self.ctx.get_extended_info(&id).await
+        // An installed pack that ships a readme or screenshots describes the version that's actually installed
+        if let Some(game_id) = self.ctx.active_game() {
+            let mod_id = id.clone();
+            match tokio::task::spawn_blocking(move || ModInstaller::pack_details(&game_id, &mod_id)).await {
+                Ok(Ok(Some(details))) => return Ok(details),
+                Ok(Ok(None)) => {}
+                Ok(Err(e)) => warn!("Can't read the assets of installed mod {}: {}", id, e),
+                Err(e) => error!("Asset task failed: {}", e),
+            }
+        }
+        let mut info = self.ctx.get_extended_info(&id).await?;
+        info.installed = self.ctx.active_game().is_some_and(|game_id| InstalledRegistry::is_installed(&game_id, &id));
+        Ok(info)
     }
 
     async fn get_metadata_for(self, id: String) -> Result<GameMetadata, RegistryError> {
@@ -82,44 +361,207 @@ impl ModService for ModServiceImpl {
             .collect())
     }
 
-    async fn download_mod(self, id: String) -> Result<(), ()> {
-        let provider_id = match self.ctx.active_game_required_provider() {
-            Some(id) => id,
-            None => return Err(())
-        };
+    async fn download_mod(self, id: String, components: Option<Vec<String>>) -> Result<Vec<FileConflict>, String> {
+        let provider_id = self.ctx.active_game_required_provider().ok_or_else(|| "No active game selected".to_string())?;
+        let mod_provider = self.ctx.get_mod_provider(&provider_id).map_err(|_| format!("Mod provider {provider_id} isn't available"))?;
 
-        let mod_provider = match self.ctx.get_mod_provider(&provider_id) {
-            Ok(provider) => provider,
-            Err(_) => return Err(())
-        };
+        let path = mod_provider.download_mod(id.clone()).await;
 
-        let path = mod_provider.download_mod(id).await;
-
-        let game_provider_id = match self.ctx.active_game() {
-            Some(id) => id,
-            None => return Err(())
-        };
-
-        let game_provider = match self.ctx.get_game_provider(&game_provider_id) {
-            Ok(provider) => provider,
-            Err(_) => return Err(())
-        };
+        let game_provider_id = self.ctx.active_game().ok_or_else(|| "No active game selected".to_string())?;
+        let game_provider = self.ctx.get_game_provider(&game_provider_id).map_err(|_| format!("Game provider {game_provider_id} isn't available"))?;
 
 
         match path {
             ModDownloadResult::Completed(ref p) => {
-                if game_provider.install_mod(p).is_err() {
-                    return Err(());
+                Self::check_package_signature(p.clone()).await.map_err(|()| "The package's signature was rejected".to_string())?;
+                // Without knowing where the game lives, installing is up to the game provider
+                match self.active_game_paths(&game_provider_id) {
+                    Some(paths) => return self.install_managed(game_provider_id, provider_id, id, p.clone(), paths, components).await,
+                    None => {
+                        let unpacked = Self::unpack_for_provider(id.clone(), p.clone()).await.map_err(|()| format!("Unpacking {id} failed"))?;
+                        let installed = game_provider.install_mod(&unpacked);
+                        if unpacked != *p {
+                            if let Err(e) = std::fs::remove_dir_all(&unpacked) {
+                                warn!("Failed to remove {}: {}", unpacked.display(), e);
+                            }
+                        }
+                        if installed.is_err() {
+                            return Err(format!("The game provider failed to install {id}"));
+                        }
+                        self.record_install(game_provider_id, provider_id, id, p.clone()).await;
+                    }
                 }
             },
+            ModDownloadResult::Failed(e) => return Err(format!("Downloading {id} failed: {e}")),
             _ => {
                 println!("[dbg] Dropped mod result (fail)");
-                return Err(());
+                return Err(format!("Downloading {id} failed"));
             }
         }
 
+        Ok(Vec::new())
+    }
+
+    async fn list_installed(self, game_id: String) -> Result<Vec<InstalledMod>, ()> {
+        InstalledRegistry::list(&game_id).map_err(|e| error!("Failed to read installed mods for {}: {}", game_id, e))
+    }
+
+    async fn get_installed(self, mod_id: String) -> Option<InstalledMod> {
+        let game_id = self.ctx.active_game()?;
+        InstalledRegistry::get(&game_id, &mod_id).unwrap_or_else(|e| {
+            error!("Failed to read installed mods for {}: {}", game_id, e);
+            None
+        })
+    }
+
+    async fn get_installed_summary(self, mod_id: String, locale: Option<String>) -> Option<ModSummary> {
+        let game_id = self.ctx.active_game()?;
+        tokio::task::spawn_blocking(move || {
+            ModInstaller::pack_summary(&game_id, &mod_id, locale.as_deref()).unwrap_or_else(|e| {
+                warn!("Can't read the assets of installed mod {}: {}", mod_id, e);
+                None
+            })
+        })
+        .await
+        .map_err(|e| error!("Asset task failed: {}", e))
+        .ok()
+        .flatten()
+    }
+
+    async fn uninstall_mod(self, mod_id: String, force: bool) -> Result<(), String> {
+        self.with_installer(move |game_id, paths| ModInstaller::uninstall(game_id, &mod_id, paths, force)).await?;
+        Ok(())
+    }
+
+    async fn enable_mod(self, mod_id: String) -> Result<(), String> {
+        self.with_installer(move |game_id, paths| ModInstaller::enable(game_id, &mod_id, paths)).await
+    }
+
+    async fn disable_mod(self, mod_id: String, force: bool) -> Result<(), String> {
+        self.with_installer(move |game_id, paths| ModInstaller::disable(game_id, &mod_id, paths, force)).await
+    }
+
+    async fn set_priority(self, mod_id: String, priority: u32) -> Result<(), String> {
+        self.with_installer(move |game_id, paths| ModInstaller::set_priority(game_id, &mod_id, priority as usize, paths)).await
+    }
+
+    async fn move_before(self, mod_id: String, other_id: String) -> Result<(), String> {
+        self.with_installer(move |game_id, paths| ModInstaller::move_before(game_id, &mod_id, &other_id, paths)).await
+    }
+
+    async fn move_after(self, mod_id: String, other_id: String) -> Result<(), String> {
+        self.with_installer(move |game_id, paths| ModInstaller::move_after(game_id, &mod_id, &other_id, paths)).await
+    }
+
+    async fn purge_game(self) -> Result<(), String> {
+        self.with_installer(ModInstaller::purge).await
+    }
+
+    async fn list_originals(self, game_id: String) -> Result<Vec<OriginalFile>, ()> {
+        BackupStore::list_originals(&game_id).map_err(|e| error!("Failed to read the original files of {}: {}", game_id, e))
+    }
+
+    async fn restore_original(self, root: InstallRoot, path: String) -> Result<(), String> {
+        self.with_installer(move |game_id, paths| BackupStore::restore_original(game_id, root, &path, paths)).await
+    }
+
+    async fn verify_vanilla(self, game_id: String) -> Result<Vec<VanillaMismatch>, ()> {
+        let Some(paths) = AppConfig::load().game_paths.remove(&game_id) else {
+            error!("The install location of {} isn't configured", game_id);
+            return Err(());
+        };
+        tokio::task::spawn_blocking(move || BackupStore::verify_vanilla(&game_id, &paths).map_err(|e| error!("Failed to verify {}: {}", game_id, e)))
+            .await
+            .map_err(|e| error!("Verify task failed: {}", e))?
+    }
+
+    async fn list_profiles(self, game_id: String) -> Result<GameProfiles, ()> {
+        ProfileStore::list(&game_id).map_err(|e| error!("Failed to read profiles of {}: {}", game_id, e))
+    }
+
+    async fn create_profile(self, name: String) -> Result<Profile, String> {
+        let game_id = self.ctx.active_game().ok_or_else(|| "No active game selected".to_string())?;
+        ProfileStore::create(&game_id, &name).map_err(|e| e.to_string())
+    }
+
+    async fn clone_profile(self, source: String, name: String) -> Result<Profile, String> {
+        let game_id = self.ctx.active_game().ok_or_else(|| "No active game selected".to_string())?;
+        ProfileStore::clone_profile(&game_id, &source, &name).map_err(|e| e.to_string())
+    }
+
+    async fn switch_profile(self, name: String) -> Result<(), String> {
+        self.with_installer(move |game_id, paths| ProfileStore::switch(game_id, &name, paths)).await
+    }
+
+    async fn delete_profile(self, name: String) -> Result<(), String> {
+        let game_id = self.ctx.active_game().ok_or_else(|| "No active game selected".to_string())?;
+        ProfileStore::delete(&game_id, &name).map_err(|e| e.to_string())
+    }
+
+    async fn set_profile_overrides(self, name: String, overrides: Vec<ConfigOverride>) -> Result<(), String> {
+        self.with_installer(move |game_id, paths| ProfileStore::set_overrides(game_id, &name, overrides, paths)).await
+    }
+
+    async fn get_game_paths(self, game_id: String) -> Option<GamePaths> {
+        AppConfig::load().game_paths.remove(&game_id)
+    }
+
+    async fn set_game_paths(self, game_id: String, paths: GamePaths) -> Result<(), ()> {
+        if !paths.game_dir.is_dir() {
+            error!("Game directory {} doesn't exist", paths.game_dir.display());
+            return Err(());
+        }
+        let mut config = AppConfig::try_load().map_err(|e| error!("Not saving over the config: {}", e))?;
+        config.game_paths.insert(game_id, paths);
+        config.save().map_err(|e| error!("Failed to save config: {}", e))
+    }
+
+    async fn set_pack_key(self, key_id: String, key: String) -> Result<(), String> {
+        let key = VmpakKey::from_hex(&key).map_err(|e| e.to_string())?;
+        set_pack_key(&key_id, &key.to_hex()).map_err(|e| {
+            warn!(error = ?e, "Failed to store pack key");
+            "Failed to store the pack key in the keyring".to_string()
+        })?;
+
+        let mut config = AppConfig::try_load().map_err(|e| e.to_string())?;
+        if !config.pack_keys.contains(&key_id) {
+            config.pack_keys.push(key_id);
+            config.save().map_err(|e| format!("Failed to save config: {e}"))?;
+        }
+        info!("Stored pack key");
         Ok(())
     }
 
+    async fn stage_mod(self, id: String, components: Option<Vec<String>>) -> Result<Vec<FileConflict>, String> {
+        let provider_id = self.ctx.active_game_required_provider().ok_or_else(|| "No active game selected".to_string())?;
+        let mod_provider = self.ctx.get_mod_provider(&provider_id).map_err(|_| format!("Mod provider {provider_id} isn't available"))?;
+
+        let package = match mod_provider.download_mod(id.clone()).await {
+            ModDownloadResult::Completed(path) => path,
+            ModDownloadResult::Failed(e) => return Err(format!("Downloading {id} failed: {e}")),
+            _ => return Err(format!("Downloading {id} failed")),
+        };
+        Self::check_package_signature(package.clone()).await.map_err(|()| "The package's signature was rejected".to_string())?;
 
+        let game_id = self.ctx.active_game().ok_or_else(|| "No active game selected".to_string())?;
+        let metadata = self.provider_metadata(&game_id, &id).await;
+        self.with_installer(move |game_id, paths| {
+            let staged = ModInstaller::stage(game_id, &id, &provider_id, &package, paths, &metadata, components)?;
+            ModInstaller::conflicts_of_staged(game_id, &staged)
+        })
+        .await
+    }
+
+    async fn discard_staged(self, mod_id: String) -> Result<(), String> {
+        self.with_installer(move |game_id, _| ModInstaller::discard_staged(game_id, &mod_id)).await
+    }
+
+    async fn get_conflicts(self, game_id: String) -> Result<Vec<FileConflict>, ()> {
+        ModInstaller::conflicts(&game_id).map_err(|e| error!("Failed to read installed mods for {}: {}", game_id, e))
+    }
+
+    async fn get_mod_conflicts(self, mod_id: String) -> Result<Vec<FileConflict>, String> {
+        let game_id = self.ctx.active_game().ok_or_else(|| "No active game selected".to_string())?;
+        ModInstaller::conflicts_of(&game_id, &mod_id).map_err(|e| e.to_string())
+    }
 }
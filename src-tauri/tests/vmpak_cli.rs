@@ -0,0 +1,137 @@
+//! Runs the `vmpak` binary end to end, checking its output and exit codes
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+use void_mod_manager_lib::binary::VmpakReader;
+
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("vmpak-cli-{}-{name}", std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    fn join(&self, name: &str) -> PathBuf {
+        self.0.join(name)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn vmpak(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_vmpak")).args(args).output().unwrap()
+}
+
+fn json(output: &Output) -> serde_json::Value {
+    serde_json::from_slice(&output.stdout).unwrap()
+}
+
+fn arg(path: &Path) -> &str {
+    path.to_str().unwrap()
+}
+
+fn pack_sample(dir: &TempDir) -> PathBuf {
+    let source = dir.join("mod");
+    fs::create_dir_all(source.join("textures")).unwrap();
+    fs::write(source.join("readme.txt"), "hello ".repeat(100)).unwrap();
+    fs::write(source.join("textures/data.bin"), (0..20_000u32).map(|i| (i * 31 % 251) as u8).collect::<Vec<_>>()).unwrap();
+
+    let output = dir.join("mod.vmpak");
+    let packed = vmpak(&[
+        "pack", arg(&source), "-o", arg(&output), "--id", "sample", "--name", "Sample", "--mod-version", "1.0.0", "--game", "game",
+        "--compression", "stored", "--dedup", "off", "--json",
+    ]);
+    assert!(packed.status.success(), "{}", String::from_utf8_lossy(&packed.stderr));
+    assert_eq!(json(&packed)["entries"], 2);
+    output
+}
+
+#[test]
+fn packs_lists_and_unpacks() {
+    let dir = TempDir::new("round-trip");
+    let pack = pack_sample(&dir);
+
+    let listed = vmpak(&["ls", arg(&pack), "--json"]);
+    assert!(listed.status.success());
+    let mut paths: Vec<_> = json(&listed).as_array().unwrap().iter().map(|e| e["path"].as_str().unwrap().to_string()).collect();
+    paths.sort();
+    assert_eq!(paths, ["readme.txt", "textures/data.bin"]);
+
+    let unpacked = dir.join("unpacked");
+    assert!(vmpak(&["unpack", arg(&pack), arg(&unpacked)]).status.success());
+    assert_eq!(fs::read(unpacked.join("readme.txt")).unwrap(), fs::read(dir.join("mod/readme.txt")).unwrap());
+    assert_eq!(fs::read(unpacked.join("textures/data.bin")).unwrap(), fs::read(dir.join("mod/textures/data.bin")).unwrap());
+}
+
+#[test]
+fn verify_exits_with_2_on_corruption() {
+    let dir = TempDir::new("verify");
+    let pack = pack_sample(&dir);
+    assert!(vmpak(&["verify", arg(&pack)]).status.success());
+
+    let entry = VmpakReader::open(&pack).unwrap().entries().iter().find(|e| e.path == "textures/data.bin").unwrap().clone();
+    let mut bytes = fs::read(&pack).unwrap();
+    bytes[entry.offset as usize + 10] ^= 0xFF;
+    fs::write(&pack, bytes).unwrap();
+
+    let verified = vmpak(&["verify", arg(&pack), "--json"]);
+    assert_eq!(verified.status.code(), Some(2));
+    assert_eq!(json(&verified)["corrupt_entries"][0]["path"], "textures/data.bin");
+
+    // Usage errors are 1, not 2
+    assert_eq!(vmpak(&["ls", arg(&dir.join("missing.vmpak"))]).status.code(), Some(1));
+}
+
+#[test]
+fn metadata_files_have_to_exist_when_named() {
+    let dir = TempDir::new("metadata");
+    fs::create_dir_all(dir.join("mod")).unwrap();
+    fs::write(dir.join("mod/readme.txt"), "hello").unwrap();
+    let pack = |metadata: &Path| vmpak(&["pack", arg(&dir.join("mod")), "-o", arg(&dir.join("mod.vmpak")), "--metadata", arg(metadata), "--id", "m", "--game", "g"]);
+
+    let missing = pack(&dir.join("missing.json"));
+    assert_eq!(missing.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&missing.stderr).contains("missing.json"));
+
+    // Broken metadata is bad input, not a corrupt pack
+    fs::write(dir.join("broken.json"), "{").unwrap();
+    assert_eq!(pack(&dir.join("broken.json")).status.code(), Some(1));
+}
+
+#[test]
+fn convert_only_replaces_the_output_once_it_succeeds() {
+    let dir = TempDir::new("convert");
+    let output = dir.join("mod.vmpak");
+    fs::write(&output, "previous").unwrap();
+    fs::write(dir.join("mod.vmpak.001"), "stale").unwrap();
+    let convert = |archive: &Path| vmpak(&["convert", arg(archive), "-o", arg(&output), "--id", "m", "--game", "g"]);
+
+    fs::write(dir.join("broken.zip"), b"PK\x03\x04 not really a zip").unwrap();
+    assert!(!convert(&dir.join("broken.zip")).status.success());
+    assert_eq!(fs::read_to_string(&output).unwrap(), "previous");
+    assert!(!dir.join("mod.vmpak.tmp").exists());
+
+    let mut archive = tar::Builder::new(fs::File::create(dir.join("mod.tar")).unwrap());
+    let mut header = tar::Header::new_gnu();
+    header.set_size(5);
+    header.set_mode(0o644);
+    archive.append_data(&mut header, "readme.txt", &b"hello"[..]).unwrap();
+    archive.finish().unwrap();
+    drop(archive);
+
+    let converted = convert(&dir.join("mod.tar"));
+    assert!(converted.status.success(), "{}", String::from_utf8_lossy(&converted.stderr));
+    assert!(!dir.join("mod.vmpak.001").exists());
+    assert_eq!(VmpakReader::open(&output).unwrap().entries().len(), 1);
+}
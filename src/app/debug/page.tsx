@@ -116,7 +116,7 @@ export default function DebugPage() {
           onClick={() => {
             (async () => {
               const rpc = getTauRPC();
-              await rpc.download_mod("1");
+              await rpc.download_mod("1", null);
             })();
           }}
         >
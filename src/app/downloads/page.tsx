@@ -10,8 +10,10 @@ import {
   XIcon,
 } from "lucide-react";
 import { useCallback, useEffect, useMemo, useRef, useState } from "react";
+import { toast } from "sonner";
 import { Button } from "@/components/primitives/button";
 import Input from "@/components/primitives/input";
+import type { FileConflict } from "@/generated/types";
 import { getTauRPC } from "@/lib/taurpc/useTaurpc";
 
 type DownloadStatus =
@@ -60,6 +62,27 @@ function formatEta(sec?: number): string {
   return `${m}m${s > 0 ? ` ${s}s` : ""}`;
 }
 
+// A mod that would overwrite files of other mods is only staged until the user picks what to do with it
+function confirmStaged(id: string, conflicts: FileConflict[]) {
+  if (conflicts.length === 0) return;
+  const rpc = getTauRPC();
+  toast(`${id} would overwrite ${conflicts.length} file(s) of other mods`, {
+    duration: Infinity,
+    action: {
+      label: "Enable",
+      onClick: () =>
+        rpc.enable_mod(id).catch((e) => toast.error(`Enable failed: ${e}`)),
+    },
+    cancel: {
+      label: "Discard",
+      onClick: () =>
+        rpc
+          .discard_staged(id)
+          .catch((e) => toast.error(`Discard failed: ${e}`)),
+    },
+  });
+}
+
 function generateDisplayName(id: string) {
   return `Mod_${id.slice(0, 6)}`;
 }
@@ -91,13 +114,16 @@ const DownloadsPage = () => {
         startedCount = toStart.length;
         const rpc = getTauRPC();
         toStart.forEach((item) => {
-          rpc.download_mod(item.id).catch((e) => {
-            console.error(
-              "Failed to initiate backend download for",
-              item.id,
-              e,
-            );
-          });
+          rpc
+            .download_mod(item.id, null)
+            .then((conflicts) => confirmStaged(item.id, conflicts))
+            .catch((e) => {
+              console.error(
+                "Failed to initiate backend download for",
+                item.id,
+                e,
+              );
+            });
         });
         return [...prevActive, ...toStart];
       });
@@ -236,7 +262,8 @@ const DownloadsPage = () => {
       // Fire backend download call
       const rpc = getTauRPC();
       rpc
-        .download_mod(id)
+        .download_mod(id, null)
+        .then((conflicts) => confirmStaged(id, conflicts))
         .catch((e) =>
           console.error("Failed to initiate backend download", id, e),
         );
@@ -8,6 +8,17 @@ export type ApiKeyValidationError = "Empty" | { TooShort: { min_len: number } }
 
 export type ApiSubmitResponse = { id: string; value: string }
 
+/**
+ * A file the profile writes over whatever the game or its mods put there, usually a config
+ */
+export type ConfigOverride = { root: InstallRoot; path: string; contents: string }
+
+/**
+ * How staged files get into the game directory. Links keep a single copy in the staging store,
+ * anything the filesystem can't do falls back to copying
+ */
+export type DeployMethod = "copy" | "hardlink" | "symlink" | "reflink"
+
 export type DiscoveryMeta = { provider_id: string; game_id: string; pagination: PaginationMeta; applied_tags: string[]; available_tags: Tag[] | null }
 
 export type DiscoveryResult = { meta: DiscoveryMeta; mods: ModSummary[] }
@@ -16,18 +27,64 @@ export type Field = { id: string; label: string; field_type: FieldType; placehol
 
 export type FieldType = "Text" | "Password" | { Select: string[] } | "MarkdownInfo"
 
+/**
+ * A file more than one mod writes
+ */
+export type FileConflict = { root: InstallRoot; path: string; mods: string[]; winner: string }
+
 export type FormSchema = { title: string; description: string | null; fields: Field[] }
 
 export type GameIcon = { Path: string }
 
 export type GameMetadata = { id: string; display_name: string; short_name: string; icon: GameIcon; provider_source: ProviderSource }
 
+/**
+ * Where a game lives on disk. Mods are only installed by the manager itself once this is known,
+ * otherwise installing is left to the game provider
+ */
+export type GamePaths = { game_dir: string; config_dir: string | null; save_dir: string | null; load_order_file: LoadOrderFile | null; deploy_method: DeployMethod; game_version: string | null }
+
+export type GameProfiles = { active: string; profiles: Profile[] }
+
+/**
+ * Directory a mapping installs into. Where each one actually is comes from the game, not the pack
+ */
+export type InstallRoot = "game" | "config" | "save"
+
+/**
+ * A file owned by an installed mod
+ */
+export type InstalledFile = { root: InstallRoot; path: string; hash: string; replaced: string | null }
+
+/**
+ * What the app remembers about one installed mod
+ */
+export type InstalledMod = { mod_id: string; game_id: string; version: string; provider_id: string; installed_at: number; archive_hash: string; managed: boolean; enabled: boolean; files: InstalledFile[]; dependencies: string[] }
+
+/**
+ * A file the game reads its load order from, rewritten whenever the order or the enabled mods change.
+ * Only used for games whose mod provider supports one
+ */
+export type LoadOrderFile = { root: InstallRoot; path: string; plugin_extensions: string[]; line_prefix: string }
+
 export type ModExtendedMetadata = { header_image: string; carousel_images: string[]; version: string; installed: boolean; description: string }
 
 export type ModSummary = { id: string; name: string; description: string; short_description: string; downloads: number; views: number; likes: number; thumbnail_image: string; tags: string[]; user_name: string; user_avatar: string }
 
+/**
+ * What a game file looked like before the manager first wrote to it
+ */
+export type OriginalFile = { root: InstallRoot; path: string; hash: string | null }
+
 export type PaginationMeta = { current: number; page_size: number; total_pages: number | null; total_items: number | null }
 
+/**
+ * A named setup of one game
+ */
+export type Profile = { name: string; mods: ProfileMod[]; overrides: ConfigOverride[] }
+
+export type ProfileMod = { mod_id: string; enabled: boolean }
+
 export type ProviderSource = "Core" | { Plugin: string }
 
 /**
@@ -37,15 +94,46 @@ export type RegistryError = { InvalidId: string } | { ProviderAlreadyExists: str
 
 export type Tag = { id: string; name: string }
 
-const ARGS_MAP = { '':'{"download_mod":["id"],"get_active_game":[],"get_discovery_mods":["page"],"get_extended_info":["id"],"get_metadata_for":["id"],"greet":[],"list_games":[],"set_active_game":["id"]}', 'capabilities':'{"api_key_should_show":[],"api_key_submit_response":["values"],"list_capabilities":[],"requires_api_key":[]}' }
-export type Router = { "": {download_mod: (id: string) => Promise<null>, 
+/**
+ * A game file that isn't the way it was before the manager touched it
+ */
+export type VanillaMismatch = { root: InstallRoot; path: string; expected: string | null; found: string | null }
+
+const ARGS_MAP = { '':'{"clone_profile":["source","name"],"create_profile":["name"],"delete_profile":["name"],"disable_mod":["mod_id","force"],"discard_staged":["mod_id"],"download_mod":["id","components"],"enable_mod":["mod_id"],"get_active_game":[],"get_conflicts":["game_id"],"get_discovery_mods":["page"],"get_extended_info":["id"],"get_game_paths":["game_id"],"get_installed":["mod_id"],"get_installed_summary":["mod_id","locale"],"get_metadata_for":["id"],"get_mod_conflicts":["mod_id"],"greet":[],"list_games":[],"list_installed":["game_id"],"list_originals":["game_id"],"list_profiles":["game_id"],"move_after":["mod_id","other_id"],"move_before":["mod_id","other_id"],"purge_game":[],"restore_original":["root","path"],"set_active_game":["id"],"set_game_paths":["game_id","paths"],"set_pack_key":["key_id","key"],"set_priority":["mod_id","priority"],"set_profile_overrides":["name","overrides"],"stage_mod":["id","components"],"switch_profile":["name"],"uninstall_mod":["mod_id","force"],"verify_vanilla":["game_id"]}', 'capabilities':'{"api_key_should_show":[],"api_key_submit_response":["values"],"list_capabilities":[],"requires_api_key":[]}' }
+export type Router = { "": {clone_profile: (source: string, name: string) => Promise<Profile>, 
+create_profile: (name: string) => Promise<Profile>, 
+delete_profile: (name: string) => Promise<null>, 
+disable_mod: (mod_id: string, force: boolean) => Promise<null>, 
+discard_staged: (mod_id: string) => Promise<null>, 
+download_mod: (id: string, components: string[] | null) => Promise<FileConflict[]>, 
+enable_mod: (mod_id: string) => Promise<null>, 
 get_active_game: () => Promise<string | null>, 
+get_conflicts: (game_id: string) => Promise<FileConflict[]>, 
 get_discovery_mods: (page: number | null) => Promise<DiscoveryResult>, 
 get_extended_info: (id: string) => Promise<ModExtendedMetadata>, 
+get_game_paths: (game_id: string) => Promise<GamePaths | null>, 
+get_installed: (mod_id: string) => Promise<InstalledMod | null>, 
+get_installed_summary: (mod_id: string, locale: string | null) => Promise<ModSummary | null>, 
 get_metadata_for: (id: string) => Promise<GameMetadata>, 
+get_mod_conflicts: (mod_id: string) => Promise<FileConflict[]>, 
 greet: () => Promise<string>, 
 list_games: () => Promise<string[]>, 
-set_active_game: (id: string) => Promise<null>},
+list_installed: (game_id: string) => Promise<InstalledMod[]>, 
+list_originals: (game_id: string) => Promise<OriginalFile[]>, 
+list_profiles: (game_id: string) => Promise<GameProfiles>, 
+move_after: (mod_id: string, other_id: string) => Promise<null>, 
+move_before: (mod_id: string, other_id: string) => Promise<null>, 
+purge_game: () => Promise<null>, 
+restore_original: (root: InstallRoot, path: string) => Promise<null>, 
+set_active_game: (id: string) => Promise<null>, 
+set_game_paths: (game_id: string, paths: GamePaths) => Promise<null>, 
+set_pack_key: (key_id: string, key: string) => Promise<null>, 
+set_priority: (mod_id: string, priority: number) => Promise<null>, 
+set_profile_overrides: (name: string, overrides: ConfigOverride[]) => Promise<null>, 
+stage_mod: (id: string, components: string[] | null) => Promise<FileConflict[]>, 
+switch_profile: (name: string) => Promise<null>, 
+uninstall_mod: (mod_id: string, force: boolean) => Promise<null>, 
+verify_vanilla: (game_id: string) => Promise<VanillaMismatch[]>},
 "capabilities": {api_key_should_show: () => Promise<FormSchema | null>, 
 api_key_submit_response: (values: ApiSubmitResponse[]) => Promise<boolean>, 
 list_capabilities: () => Promise<string[]>, 